@@ -1,3 +1,5 @@
+import { Units } from "./weather";
+
 export class Settings {
   weather_settings: WeatherSettings;
   news_settings: NewsSettings;
@@ -7,10 +9,22 @@ export class Settings {
 export class WeatherSettings {
   weather_source: string;
   temp_unhits: string;
+  units: Units | null;
   polling_rate: number;
+  history_days: number;
   api_key: string;
   lat: number;
   lon: number;
+  locations: WeatherLocation[];
+  online_geocoding: boolean;
+}
+
+// A location without a lat and lon is looked up by its name when the settings are saved.
+export class WeatherLocation {
+  name: string;
+  lat: number | null;
+  lon: number | null;
+  primary: boolean;
 }
 
 export class NewsSettings {
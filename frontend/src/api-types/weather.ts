@@ -1,10 +1,37 @@
 import * as dayjs from "dayjs";
 
+// The latest weather for every location, sent as `weather` on the update stream.
+export class WeatherReports {
+  // The name of the location to show when none is picked.
+  primary: string;
+  reports: { [location: string]: Weather };
+
+  constructor(data: any) {
+    this.primary = data.primary;
+    this.reports = {};
+    for (const location of Object.keys(data.reports)) {
+      this.reports[location] = new Weather(data.reports[location]);
+    }
+  }
+}
+
 export class Weather {
+  // What everything in the report is measured in.
+  units: Units;
   current_weather: CurrentWeather;
   forecast: Forecast[];
+  hourly: HourlyForecast[];
+  minutely: MinutelyPrecipitation[];
+  precipitation_starts_at: Date | null;
+  alerts: WeatherAlert[];
+  fetched_at: Date | null;
+  // The name of the weather provider the report came from.
+  provider: string | null;
+  // Set when the weather couldn't be updated and this report is out of date.
+  stale: boolean;
 
   constructor(data: any) {
+    this.units = data.units;
     this.current_weather = data.current_weather;
 
     this.forecast = data.forecast;
@@ -12,13 +39,57 @@ export class Weather {
     for (const f of this.forecast) {
       f.date = dayjs(f.date, 'YYYY-MM-DD').toDate();
     }
+
+    this.hourly = data.hourly ?? [];
+    for (const h of this.hourly) {
+      h.time = dayjs(h.time).toDate();
+    }
+
+    this.minutely = data.minutely ?? [];
+    for (const m of this.minutely) {
+      m.time = dayjs(m.time).toDate();
+    }
+
+    this.precipitation_starts_at = data.precipitation_starts_at
+      ? dayjs(data.precipitation_starts_at).toDate()
+      : null;
+
+    this.alerts = (data.alerts ?? []).map(parseAlert);
+
+    this.fetched_at = data.fetched_at ? dayjs(data.fetched_at).toDate() : null;
+    this.provider = data.provider ?? null;
+    this.stale = data.stale ?? false;
   }
 }
 
+export class Units {
+  temperature: "Kelvin" | "Celsius" | "Fahrenheit";
+  wind: "MetresPerSecond" | "KilometresPerHour" | "MilesPerHour" | "Knots";
+  pressure: "Hectopascals" | "InchesOfMercury";
+  // For minutely precipitation, per hour.
+  precipitation: "Millimetres" | "Inches";
+  // For visibility.
+  distance: "Metres" | "Kilometres" | "Miles";
+}
+
+// Everything is in the report's units. Details some weather providers don't have are null.
 export class CurrentWeather {
   temp: number;
+  feels_like: number;
   humidity: number;
+  pressure: number | null;
+  dew_point: number | null;
+  visibility: number | null;
+  uv_index: number | null;
+  cloudiness: number;
+  wind_speed: number;
+  wind_direction: number;
+  wind_gust: number | null;
+  condition_code: number;
+  icon: string;
   description: string;
+  sunrise: string | null;
+  sunset: string | null;
 }
 
 export class Forecast {
@@ -29,4 +100,115 @@ export class Forecast {
   rain_chance: number;
   cloudiness: number;
   description: string;
-}
\ No newline at end of file
+  feels_like: number | null;
+  pressure: number | null;
+  dew_point: number | null;
+  uv_index: number | null;
+  wind_speed: number;
+  wind_direction: number;
+  wind_gust: number | null;
+  condition_code: number;
+  icon: string;
+  sunrise: string | null;
+  sunset: string | null;
+  moon_phase: number;
+}
+
+export class HourlyForecast {
+  time: Date;
+  temp: number;
+  humidity: number;
+  rain_chance: number;
+  cloudiness: number;
+  description: string;
+}
+
+export class MinutelyPrecipitation {
+  time: Date;
+  precipitation: number;
+}
+
+export class WeatherAlert {
+  sender: string;
+  event: string;
+  start: Date;
+  end: Date;
+  description: string;
+}
+
+export function parseAlert(data: any): WeatherAlert {
+  return {
+    ...data,
+    start: dayjs(data.start).toDate(),
+    end: dayjs(data.end).toDate(),
+  };
+}
+
+// Sent as `weatheralert` on the update stream when an alert comes up, changes or goes away.
+export class AlertUpdate {
+  // The name of the location the alert is for.
+  location: string;
+  change: "new" | "updated" | "expired";
+  alert: WeatherAlert;
+
+  constructor(data: any) {
+    this.location = data.location;
+    this.change = data.change;
+    this.alert = parseAlert(data.alert);
+  }
+}
+// What `GET /geocode?q=` gives back for each place a name or postal code could be.
+export class Place {
+  name: string;
+  // The ISO 3166 country code, like "DE".
+  country: string;
+  region: string | null;
+  lat: number;
+  lon: number;
+}
+
+// What `GET /weather/history` gives back for each report that was fetched, in the temperature
+// units from the settings.
+export class HistoryEntry {
+  time: Date;
+  location: string;
+  provider: string | null;
+  temp: number;
+  forecast: ForecastTemps[];
+
+  constructor(data: any) {
+    this.time = dayjs(data.time).toDate();
+    this.location = data.location;
+    this.provider = data.provider;
+    this.temp = data.temp;
+    this.forecast = data.forecast;
+  }
+}
+
+export class ForecastTemps {
+  // Like "2024-01-10".
+  date: string;
+  high: number;
+  low: number;
+}
+
+// What `GET /weather/history/days` gives back for each day in each location, in the temperature
+// units from the settings.
+export class DaySummary {
+  // Like "2024-01-10".
+  date: string;
+  location: string;
+  high: number;
+  low: number;
+  forecasts: ForecastAccuracy[];
+}
+
+export class ForecastAccuracy {
+  provider: string | null;
+  days_ahead: number;
+  high: number;
+  low: number;
+  // The forecast minus what was seen.
+  high_error: number;
+  low_error: number;
+}
@@ -47,7 +47,7 @@ export class ClockPanel extends ContentPanel {
     }
 
     if(this.globalData.weather) {
-      document.getElementById("clock-temperature").innerHTML = `${Math.round(this.globalData.weather.current_weather.temp * 9.0/5.0 + 32)}&#176;`
+      document.getElementById("clock-temperature").innerHTML = `${Math.round(this.globalData.weather.current_weather.temp)}&#176;`
       document.getElementById("clock-weather").innerHTML = this.globalData.weather.current_weather.description;      
     }
   }
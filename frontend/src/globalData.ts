@@ -1,4 +1,4 @@
-import { Weather } from "./api-types/weather";
+import { Weather, WeatherReports } from "./api-types/weather";
 
 export class GlobalData {
   apiKey?: {key: string};
@@ -6,7 +6,9 @@ export class GlobalData {
   lat?: number;
   lon?: number;
   
+  // The primary location's weather.
   weather?: Weather;
+  weatherReports?: WeatherReports;
 
   theme?: string;
 }
\ No newline at end of file
@@ -2,7 +2,7 @@ import './style.css';
 import { ClockPanel } from './clock/clock';
 import { WeatherPanel } from './weather/weather';
 import { GlobalData } from './globalData';
-import { Weather } from './api-types/weather';
+import { WeatherReports } from './api-types/weather';
 import { Settings } from './api-types/settings';
 import { ContentPanel } from './contentPanel';
 
@@ -38,8 +38,9 @@ socket.onmessage = (event) => {
   console.log(messageData);
 
   if (messageData.hasOwnProperty('weather')) {
-    const weather = new Weather(messageData.weather);
-    globalData.weather = weather;
+    const reports = new WeatherReports(messageData.weather);
+    globalData.weatherReports = reports;
+    globalData.weather = reports.reports[reports.primary];
   }
 }
 
@@ -1,8 +1,10 @@
 use actix_files::Files;
 use actix_rt::Arbiter;
-use actix_web::{get, web, App, HttpResponse, HttpServer};
+use actix_web::{get, post, web, App, HttpResponse, HttpServer};
+use chrono::{Local, NaiveDate};
 use futures::{channel::mpsc, SinkExt, StreamExt};
-use std::{net::SocketAddr, sync::Arc};
+use serde::Deserialize;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 use tokio::net::{TcpListener, TcpStream};
 use tokio_tungstenite::{
     accept_async,
@@ -15,26 +17,120 @@ mod service;
 mod settings;
 mod voice;
 mod weather;
+use crate::message::UpdateMessage;
 use crate::news::NewsService;
 use crate::service::ServiceHandler;
 use crate::settings::SETTINGS;
-use crate::weather::WeatherService;
+use crate::voice::CommandExecutor;
+use crate::weather::{WeatherHistory, WeatherService};
+
+#[derive(Deserialize)]
+struct WeatherQuery {
+    location: Option<String>,
+}
 
 #[get("/weather")]
-/// Get the most recent weather that's been queried or return nothing if no weather information is
-/// available.
-async fn get_weather(service_handler: web::Data<Arc<ServiceHandler>>) -> HttpResponse {
+/// Get the most recent weather that's been queried for every location, or just for the location
+/// named in the query, or return nothing if no weather information is available.
+async fn get_weather(
+    service_handler: web::Data<Arc<ServiceHandler>>,
+    query: web::Query<WeatherQuery>,
+) -> HttpResponse {
     let weather_report = service_handler
         .get_latest_result(WeatherService::get_service_name())
         .await;
-    match weather_report {
-        Some(report) => HttpResponse::Ok()
-            .content_type("application/json")
-            .body(report),
-        None => HttpResponse::NoContent().body("No weather available at this time"),
+    let report = match (weather_report, &query.location) {
+        (Some(report), None) => report,
+        (Some(report), Some(location)) => match serde_json::from_str(&report) {
+            Ok(UpdateMessage::Weather(reports)) => match reports.find(Some(location)) {
+                Some((_, report)) => serde_json::to_string(report).unwrap(),
+                None => {
+                    return HttpResponse::NotFound().body(format!("No weather for {}", location))
+                }
+            },
+            _ => return HttpResponse::InternalServerError().body("Couldn't read the weather"),
+        },
+        (None, _) => return HttpResponse::NoContent().body("No weather available at this time"),
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(report)
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    // The first and last days to include, like "2024-01-10". Either can be left out.
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+/// Reads the weather history between the given days off of the server's threads, since it can
+/// be a big file.
+async fn read_weather_history(query: &HistoryQuery) -> Result<Vec<weather::HistoryEntry>, String> {
+    let (from, to) = (query.from, query.to);
+    match web::block(move || {
+        WeatherHistory::default()
+            .read(from, to)
+            .map_err(|e| e.to_string())
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(e) => Err(e.to_string()),
     }
 }
 
+#[get("/weather/history")]
+/// Get every weather report that was fetched between the given days, oldest first, with
+/// temperatures in the units from the settings.
+async fn get_weather_history(query: web::Query<HistoryQuery>) -> HttpResponse {
+    let entries = match read_weather_history(&query).await {
+        Ok(entries) => entries,
+        Err(e) => return HttpResponse::InternalServerError().body(e),
+    };
+    let units = SETTINGS.read().unwrap().weather_settings.units();
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|entry| entry.in_units(&units.temperature))
+        .collect();
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&entries).unwrap())
+}
+
+#[get("/weather/history/days")]
+/// Get the high and low for each day and location between the given days, along with what was
+/// forecast for them beforehand by each weather provider.
+async fn get_weather_history_days(query: web::Query<HistoryQuery>) -> HttpResponse {
+    let entries = match read_weather_history(&query).await {
+        Ok(entries) => entries,
+        Err(e) => return HttpResponse::InternalServerError().body(e),
+    };
+    let units = SETTINGS.read().unwrap().weather_settings.units();
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&weather::summarize(&entries, &units.temperature)).unwrap())
+}
+
+#[derive(Deserialize)]
+struct GeocodeQuery {
+    q: String,
+}
+
+#[get("/geocode")]
+/// Look up where a place name or postal code is, returning every place it could be, most likely
+/// first.
+async fn get_geocode(query: web::Query<GeocodeQuery>) -> HttpResponse {
+    let (weather_settings, language) = {
+        let settings = SETTINGS.read().unwrap();
+        (settings.weather_settings.clone(), settings.language)
+    };
+    let places = weather::geocode(&query.q, &weather_settings, language).await;
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&places).unwrap())
+}
+
 #[get("/news")]
 /// Get the most recent news that's been queried or return nothing if not news information is
 /// available.
@@ -48,6 +144,58 @@ async fn get_news(service_handler: web::Data<Arc<ServiceHandler>>) -> HttpRespon
     }
 }
 
+#[get("/voice/devices")]
+/// List the audio input devices that can be used to listen for voice commands.
+async fn get_voice_devices() -> HttpResponse {
+    // Probing the audio devices can block, so keep it off of the server's threads.
+    let devices =
+        web::block(|| voice::audio::list_input_devices().map_err(|e| e.to_string())).await;
+    match devices {
+        Ok(Ok(devices)) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string(&devices).unwrap()),
+        Ok(Err(e)) => HttpResponse::InternalServerError().body(e),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+#[get("/voice/history")]
+/// Get everything the tablet has heard recently, oldest first, along with what it did about it.
+async fn get_voice_history(executor: web::Data<CommandExecutor>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&executor.history()).unwrap())
+}
+
+#[derive(Deserialize)]
+struct TextCommand {
+    text: String,
+}
+
+#[post("/command")]
+/// Run typed text as if it had been said, returning what it was understood as and what happened.
+async fn post_command(
+    executor: web::Data<CommandExecutor>,
+    command: web::Json<TextCommand>,
+) -> HttpResponse {
+    let executor = executor.get_ref().clone();
+    let text = command.into_inner().text;
+    let language = SETTINGS.read().unwrap().language;
+    // Carrying out a command can block, so keep it off of the server's threads.
+    let result = web::block(move || {
+        voice::run_text_command(&executor, &text, language, &Local::now())
+            .map_err(|e| e.to_string())
+    })
+    .await;
+    match result {
+        Ok(Ok(result)) => HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string(&result).unwrap()),
+        Ok(Err(e)) => HttpResponse::InternalServerError().body(e),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
 /// Wrapper function to handle any errors that result from establishing the update
 /// connection to the frontend.
 async fn accept_update_connection(
@@ -91,6 +239,21 @@ async fn handle_update_connection(
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    // `smart_tablet replay <recordings>...` runs recorded utterances back through the voice
+    // pipeline instead of starting up the tablet.
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("replay") {
+        let paths: Vec<PathBuf> = args[2..].iter().map(PathBuf::from).collect();
+        match voice::replay(&paths) {
+            Ok(true) => return Ok(()),
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("Couldn't replay utterances: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     let mut arbiter = Arbiter::new();
     {
         let _settings = SETTINGS.read().unwrap();
@@ -113,6 +276,15 @@ async fn main() -> std::io::Result<()> {
 
     // Start up all the relevant services in the service handler.
     let service_handler = ServiceHandler::new();
+    let (history_len, dialog_timeout) = {
+        let voice_settings = &SETTINGS.read().unwrap().voice_settings;
+        (
+            voice_settings.history_len,
+            Duration::from_secs(voice_settings.dialog_timeout_secs),
+        )
+    };
+    let command_executor =
+        CommandExecutor::new(service_handler.clone(), history_len, dialog_timeout).with_speech();
     service_handler.start_service(
         &mut arbiter,
         update_tx.clone(),
@@ -126,7 +298,7 @@ async fn main() -> std::io::Result<()> {
     service_handler.start_service(
         &mut arbiter,
         update_tx.clone(),
-        Box::new(voice::CommandService::new()),
+        Box::new(voice::CommandService::new(command_executor.clone())),
     );
 
     let service_handler = Arc::new(service_handler);
@@ -134,10 +306,17 @@ async fn main() -> std::io::Result<()> {
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(service_handler.clone()))
+            .app_data(web::Data::new(command_executor.clone()))
             .route("/settings", web::post().to(settings::change_settings))
             .route("/settings", web::get().to(settings::get_settings))
             .service(get_weather)
+            .service(get_weather_history)
+            .service(get_weather_history_days)
+            .service(get_geocode)
             .service(get_news)
+            .service(get_voice_devices)
+            .service(get_voice_history)
+            .service(post_command)
             .service(Files::new("/", "./frontend/dist").index_file("index.html"))
     })
     .bind("127.0.0.1:8080")?
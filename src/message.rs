@@ -1,10 +1,20 @@
 use crate::news;
+use crate::voice::HistoryEntry;
 use crate::weather;
+use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum UpdateMessage {
-    Weather(weather::WeatherReport),
+    Weather(weather::WeatherReports),
+    // A severe weather alert has come up, changed or gone away. Unlike the weather report, this
+    // should be brought to the user's attention right away.
+    WeatherAlert(weather::AlertUpdate),
     News(Vec<news::NewsItem>),
+    Utterance(HistoryEntry),
+    TimerFinished(Duration),
+    Alarm(DateTime<Local>),
+    Reminder(String),
 }
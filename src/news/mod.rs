@@ -13,7 +13,7 @@ use rss_news::RssNewsSource;
 #[derive(Serialize, Deserialize)]
 pub struct NewsItem {
     source: String,
-    title: Option<String>,
+    pub title: Option<String>,
     description: Option<String>,
     pub_date: Option<DateTime<FixedOffset>>,
 }
@@ -24,6 +24,12 @@ pub enum NewsSource {
 }
 
 impl NewsSource {
+    pub fn name(&self) -> String {
+        match self {
+            NewsSource::Rss(source) => source.name(),
+        }
+    }
+
     pub async fn get_news(&self) -> Result<Vec<NewsItem>, Box<dyn std::error::Error>> {
         match self {
             NewsSource::Rss(source) => source.get_news().await,
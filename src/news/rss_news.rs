@@ -16,6 +16,14 @@ pub enum RssNewsSource {
 }
 
 impl RssNewsSource {
+    /// The name of the source, as it's shown next to its news.
+    pub fn name(&self) -> String {
+        match self {
+            RssNewsSource::Custom(name, _) => name.clone(),
+            _ => self.to_string(),
+        }
+    }
+
     fn get_url(&self) -> String {
         match self {
             RssNewsSource::NPR => NPR_FEED.into(),
@@ -28,10 +36,7 @@ impl RssNewsSource {
         let url = self.get_url();
         let content = reqwest::get(url).await?.bytes().await?;
         let channel = Channel::read_from(&content[..])?;
-        let source_name = match self {
-            RssNewsSource::Custom(name, _) => name.clone(),
-            _ => self.to_string(),
-        };
+        let source_name = self.name();
         let news_items = channel
             .items
             .iter()
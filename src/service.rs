@@ -16,6 +16,10 @@ pub trait Service {
     // Sets the sender within the service so that it can transmit its results out as it gets them.
     fn set_sender(&mut self, tx: mpsc::Sender<Box<dyn Serialize + Send + Sync>>);
 
+    // Sets the sender for updates that go straight out to the frontend without becoming the
+    // service's latest result, like notifications. Services that don't send any can ignore this.
+    fn set_update_sender(&mut self, _update_tx: mpsc::UnboundedSender<String>) {}
+
     // Starts the service. Currently, services run indefinitely.
     async fn start_service(&mut self);
 
@@ -26,7 +30,8 @@ pub trait Service {
 
 /// A structure to spawn services and listen for their latest results. Note that this erases the
 /// types of the responses down to `dyn Serialize`, so this is used to hold the data that will
-/// eventually be serialized and sent to the front end.
+/// eventually be serialized and sent to the front end. Clones share the same latest results.
+#[derive(Clone)]
 pub struct ServiceHandler {
     latest_results: Arc<Mutex<HashMap<String, Box<dyn Serialize + Send + Sync>>>>,
 }
@@ -48,6 +53,7 @@ impl ServiceHandler {
     ) {
         let (tx, rx) = mpsc::channel(1);
         service.set_sender(tx);
+        service.set_update_sender(update_tx.clone());
         let service_name = service.get_service_name();
         let latest_results = self.latest_results.clone();
         arbiter.spawn(async move { service.start_service().await });
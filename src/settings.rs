@@ -1,12 +1,13 @@
 use crate::{
     news::{rss_news::RssNewsSource, NewsSource},
-    weather::{TemperatureUnits, WeatherSource},
+    voice::{audio::AudioInput, tts::TtsEngineKind, vad::VadAggressiveness},
+    weather::{geocode, TemperatureUnits, Units, WeatherLocation, WeatherSource},
 };
 use actix_web::{dev::BodyEncoding, http::ContentEncoding, web, HttpResponse};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fs::OpenOptions,
     io::{Read, Write},
     path::PathBuf,
@@ -45,23 +46,91 @@ lazy_static! {
     };
 }
 
-// Sorry, I'm only supporting English as it's the only language I know and the Deepspeech models
-// are only trained for English. I'm adding the hooks for other language support if sometime in the
-// future someone else would like to add support.
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+// The language picks the Deepspeech model, the voice command grammar, the spoken number and time
+// vocabularies and the language of text that comes back from APIs like OpenWeather. Each language
+// needs its own intents_{code}.json, numbers_{code}.json and times_{code}.json next to the binary,
+// and a Deepspeech model trained for it in VoiceSettings::language_models.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Language {
     English,
+    German,
+}
+
+impl Language {
+    /// The ISO 639-1 code for the language, which is also the suffix on all of the per-language
+    /// data files.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::German => "de",
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct WeatherSettings {
     pub weather_source: WeatherSource,
+    // Tried in order when the weather source can't be reached.
+    #[serde(default)]
+    pub fallback_sources: Vec<WeatherSource>,
+    // How long in seconds a weather report is reused before asking for a new one.
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+    // How many days of fetched reports to keep in the weather history.
+    #[serde(default = "default_history_days")]
+    pub history_days: u32,
+    // Only used when there aren't any units, which is how settings from before each unit could be
+    // picked say what units to use.
     pub temp_units: TemperatureUnits,
+    // The units the weather is shown in. These can change without asking for the weather again.
+    #[serde(default)]
+    pub units: Option<Units>,
     // Time between API queries in minutes.
     pub polling_rate: u32,
     pub api_key: String,
+    // The places to get the weather for.
+    #[serde(default)]
+    pub locations: Vec<WeatherLocation>,
+    // Where to get the weather for if there aren't any locations, which is how settings from
+    // before there could be more than one location say where the weather's for.
+    #[serde(default)]
     pub lat: f32,
+    #[serde(default)]
     pub lon: f32,
+    // Whether place names that aren't in the bundled places are looked up through the weather
+    // providers.
+    #[serde(default)]
+    pub online_geocoding: bool,
+}
+
+impl WeatherSettings {
+    /// The places to get the weather for, which always has at least one place in it.
+    pub fn locations(&self) -> Vec<WeatherLocation> {
+        if !self.locations.is_empty() {
+            return self.locations.clone();
+        }
+        vec![WeatherLocation {
+            name: "Home".into(),
+            lat: Some(self.lat),
+            lon: Some(self.lon),
+            primary: true,
+        }]
+    }
+
+    /// The units the weather is shown in.
+    pub fn units(&self) -> Units {
+        self.units
+            .clone()
+            .unwrap_or_else(|| Units::from(&self.temp_units))
+    }
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    600
+}
+
+fn default_history_days() -> u32 {
+    365
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -71,10 +140,156 @@ pub struct NewsSettings {
     pub polling_rate: u32,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ModelPaths {
+    pub model_path: PathBuf,
+    pub scorer_path: PathBuf,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct VoiceSettings {
+    // The Deepspeech model used for any language that doesn't have one in language_models.
     pub model_path: PathBuf,
     pub scorer_path: PathBuf,
+    // Deepspeech models for specific languages, keyed by language.
+    #[serde(default)]
+    pub language_models: HashMap<Language, ModelPaths>,
+    // Where to listen for commands. Anything other than the microphone is mostly useful for
+    // testing the voice pipeline on machines without one.
+    #[serde(default)]
+    pub audio_input: AudioInput,
+    // The name of the microphone to listen on, as listed by /voice/devices. If this isn't set,
+    // the system's default input device is used.
+    #[serde(default)]
+    pub device_name: Option<String>,
+    #[serde(default)]
+    pub vad_settings: VadSettings,
+    #[serde(default)]
+    pub recognition_settings: RecognitionSettings,
+    // If set, every utterance is saved to this directory as a WAV file, along with a JSON file
+    // describing how it was transcribed and parsed. These can be run back through the pipeline
+    // with `smart_tablet replay <path>`.
+    #[serde(default)]
+    pub debug_recording_path: Option<PathBuf>,
+    // How many recognized utterances to keep around in the history.
+    #[serde(default = "default_history_len")]
+    pub history_len: usize,
+    // How long to wait for an answer to a question, or for a follow-up to a command, before
+    // going back to treating everything as a new command.
+    #[serde(default = "default_dialog_timeout_secs")]
+    pub dialog_timeout_secs: u64,
+    #[serde(default)]
+    pub tts_settings: TtsSettings,
+}
+
+fn default_history_len() -> usize {
+    50
+}
+
+fn default_dialog_timeout_secs() -> u64 {
+    15
+}
+
+impl VoiceSettings {
+    /// The Deepspeech model and scorer to use for the given language.
+    pub fn model_paths(&self, language: Language) -> ModelPaths {
+        match self.language_models.get(&language) {
+            Some(paths) => paths.clone(),
+            None => ModelPaths {
+                model_path: self.model_path.clone(),
+                scorer_path: self.scorer_path.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct VadSettings {
+    pub mode: VadAggressiveness,
+    // Audio with a peak amplitude under this is never checked for speech.
+    pub silence_level: i16,
+    // Whether to raise the gate above silence_level when the room is noisy. The gate is then
+    // noise_floor_margin times the estimated level of the ambient noise.
+    pub adaptive_noise_floor: bool,
+    pub noise_floor_margin: f32,
+    // How much audio from before speech is detected gets fed to Deepspeech, in milliseconds.
+    pub pre_speech_ms: u32,
+    // How long the silence after speech has to last before the utterance is considered finished,
+    // in milliseconds.
+    pub end_of_utterance_ms: u32,
+}
+
+impl Default for VadSettings {
+    fn default() -> Self {
+        Self {
+            mode: VadAggressiveness::Aggressive,
+            silence_level: 1000,
+            adaptive_noise_floor: true,
+            noise_floor_margin: 2.0,
+            pre_speech_ms: 300,
+            end_of_utterance_ms: 500,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct RecognitionSettings {
+    // How many candidate transcripts to ask Deepspeech for. They're tried in order until one
+    // parses to a command.
+    pub n_best: u32,
+    // Candidates less confident than this are never acted on. Deepspeech's confidences get lower
+    // the longer the utterance is, so the confidences in /voice/history are the best guide to
+    // what this should be.
+    pub min_confidence: Option<f64>,
+    // Candidates that are this much less confident than the most confident one are never acted
+    // on either.
+    pub max_confidence_gap: f64,
+    // How much Deepspeech is nudged towards hearing the words that set commands apart, like
+    // "weather" or "timer". These are short words that are easy to miss. Zero turns this off.
+    pub command_word_boost: f32,
+    // How much Deepspeech is nudged towards hearing the names of things the tablet knows about,
+    // like news sources.
+    pub name_boost: f32,
+    // Any other words to nudge Deepspeech towards, and how much to nudge it. These override the
+    // boosts above.
+    pub hot_words: HashMap<String, f32>,
+}
+
+impl Default for RecognitionSettings {
+    fn default() -> Self {
+        Self {
+            n_best: 5,
+            min_confidence: None,
+            max_confidence_gap: 10.0,
+            command_word_boost: 5.0,
+            name_boost: 10.0,
+            hot_words: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TtsSettings {
+    // Whether the tablet answers voice commands out loud.
+    pub enabled: bool,
+    pub engine: TtsEngineKind,
+    // The voice to speak with. For espeak-ng this is the name of the voice, and defaults to the
+    // voice for the current language. For piper, this is the path to the voice model.
+    pub voice: Option<String>,
+    // From 0.0 for silent to 1.0 for as loud as the engine makes it.
+    pub volume: f32,
+}
+
+impl Default for TtsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            engine: TtsEngineKind::EspeakNg,
+            voice: None,
+            volume: 1.0,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -90,9 +305,15 @@ impl Default for Settings {
         Self {
             weather_settings: WeatherSettings {
                 weather_source: WeatherSource::OpenWeather,
+                fallback_sources: vec![],
+                cache_ttl_secs: default_cache_ttl_secs(),
+                history_days: default_history_days(),
                 temp_units: TemperatureUnits::Celsius,
+                units: Some(Units::default()),
                 polling_rate: 3600,
                 api_key: String::new(),
+                locations: vec![],
+                online_geocoding: false,
                 lat: 0.0,
                 lon: 0.0,
             },
@@ -109,6 +330,15 @@ impl Default for Settings {
             voice_settings: VoiceSettings {
                 model_path: PathBuf::new(),
                 scorer_path: PathBuf::new(),
+                language_models: HashMap::new(),
+                audio_input: AudioInput::Microphone,
+                device_name: None,
+                vad_settings: VadSettings::default(),
+                recognition_settings: RecognitionSettings::default(),
+                debug_recording_path: None,
+                history_len: default_history_len(),
+                dialog_timeout_secs: default_dialog_timeout_secs(),
+                tts_settings: TtsSettings::default(),
             },
             language: Language::English,
         }
@@ -117,17 +347,54 @@ impl Default for Settings {
 
 // Update the settings. Currently, the client has to send all settings at once; perhaps in the
 // future we can allow for individual changes of settings if it makes sense.
+// Weather locations that were given only by name are looked up first.
 pub async fn change_settings(settings: web::Json<Settings>) -> HttpResponse {
-    let settings = settings.into_inner();
+    let mut settings = settings.into_inner();
+    let weather_settings = settings.weather_settings.clone();
+    for location in &mut settings.weather_settings.locations {
+        if location.lat.is_some() && location.lon.is_some() {
+            continue;
+        }
+        match geocode(&location.name, &weather_settings, settings.language)
+            .await
+            .first()
+        {
+            Some(place) => {
+                location.lat = Some(place.lat);
+                location.lon = Some(place.lon);
+            }
+            None => {
+                return HttpResponse::BadRequest().body(format!("Couldn't find {}", location.name))
+            }
+        }
+    }
+    save_settings(settings);
+    HttpResponse::Ok().finish()
+}
+
+// Writes the settings to the settings file and starts using them.
+pub fn save_settings(settings: Settings) {
+    let mut current = SETTINGS.write().unwrap();
+    write_settings_file(&settings);
+    *current = settings;
+}
+
+// Changes the settings in place and saves them. The settings stay locked the whole time, so
+// nothing else can change them in between and have that change lost.
+pub fn update_settings<F: FnOnce(&mut Settings)>(update: F) {
+    let mut settings = SETTINGS.write().unwrap();
+    update(&mut settings);
+    write_settings_file(&settings);
+}
+
+fn write_settings_file(settings: &Settings) {
     let mut file = OpenOptions::new()
         .write(true)
         .truncate(true)
         .open("settings.json")
         .expect("couldn't open settings file");
-    write!(file, "{}", serde_json::to_string_pretty(&settings).unwrap())
+    write!(file, "{}", serde_json::to_string_pretty(settings).unwrap())
         .expect("couldn't write to settings file");
-    *SETTINGS.write().unwrap() = settings;
-    HttpResponse::Ok().finish()
 }
 
 // Responds with all current settings in JSON format.
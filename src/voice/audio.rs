@@ -0,0 +1,446 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+/// The sample rate that every audio source hands out. Deepspeech only works with 16kHz data and
+/// the voice activity detection is configured for it as well.
+pub const SAMPLE_RATE: u32 = 16_000;
+
+/// The number of samples that file-based sources hand out at a time. Microphones give us whatever
+/// the audio driver gives us, so this is just meant to be in the same ballpark (100ms).
+const CHUNK_LEN: usize = 1600;
+
+/// Where the voice pipeline should be getting its audio from.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum AudioInput {
+    Microphone,
+    // Plays back a recorded WAV file once and then stops.
+    WavFile(PathBuf),
+    // Raw 16kHz mono little-endian i16 PCM piped in through stdin.
+    Stdin,
+}
+
+impl Default for AudioInput {
+    fn default() -> Self {
+        AudioInput::Microphone
+    }
+}
+
+impl AudioInput {
+    /// Opens the audio source described by this input. The device name only matters for the
+    /// microphone; without one, the system's default input device is used.
+    pub fn open(
+        &self,
+        device_name: Option<&str>,
+    ) -> Result<Box<dyn AudioSource>, Box<dyn std::error::Error>> {
+        let source: Box<dyn AudioSource> = match self {
+            AudioInput::Microphone => Box::new(MicrophoneSource::open(device_name)?),
+            AudioInput::WavFile(path) => Box::new(WavSource::open(path)?),
+            AudioInput::Stdin => Box::new(PcmSource::stdin()),
+        };
+        Ok(source)
+    }
+}
+
+/// A source of audio for the voice pipeline. All sources hand out 16kHz mono i16 samples in chunks
+/// of whatever size is convenient for the source.
+pub trait AudioSource {
+    /// Blocks until the next chunk of audio is available. Returns None once the source has run
+    /// out of audio to give.
+    fn next_chunk(&mut self) -> Option<Vec<i16>>;
+}
+
+/// An audio input device that the voice pipeline could listen on.
+#[derive(Serialize, Clone, Debug)]
+pub struct InputDevice {
+    pub name: String,
+    pub is_default: bool,
+    pub configs: Vec<InputConfig>,
+}
+
+/// A range of formats that an input device supports.
+#[derive(Serialize, Clone, Debug)]
+pub struct InputConfig {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub sample_format: String,
+}
+
+/// Lists all of the input devices on the default audio host, along with the formats they support.
+pub fn list_input_devices() -> Result<Vec<InputDevice>, Box<dyn std::error::Error>> {
+    let host = cpal::default_host();
+    let default_name = host.default_input_device().and_then(|d| d.name().ok());
+    let mut devices = vec![];
+    for device in host.input_devices()? {
+        // Some backends list devices that disappear or error out as soon as we poke at them, so
+        // just skip over anything that won't tell us about itself.
+        let name = match device.name() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let configs = match device.supported_input_configs() {
+            Ok(configs) => configs
+                .map(|c| InputConfig {
+                    channels: c.channels(),
+                    min_sample_rate: c.min_sample_rate().0,
+                    max_sample_rate: c.max_sample_rate().0,
+                    sample_format: format!("{:?}", c.sample_format()),
+                })
+                .collect(),
+            Err(_) => continue,
+        };
+        devices.push(InputDevice {
+            is_default: Some(&name) == default_name.as_ref(),
+            name,
+            configs,
+        });
+    }
+    Ok(devices)
+}
+
+/// Audio from one of the system's microphones.
+pub struct MicrophoneSource {
+    // The stream stops recording as soon as it's dropped, so we hold onto it even though we only
+    // ever read from the channel.
+    _stream: cpal::Stream,
+    rx: Receiver<Vec<i16>>,
+}
+
+impl MicrophoneSource {
+    /// Opens the input device with the given name, or the system's default input device if no
+    /// name is given.
+    pub fn open(device_name: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let host = cpal::default_host();
+        let device = match device_name {
+            Some(name) => host
+                .input_devices()?
+                .find(|d| d.name().ok().as_deref() == Some(name))
+                .ok_or_else(|| format!("no input device named {:?}", name))?,
+            None => host
+                .default_input_device()
+                .ok_or("no input device detected")?,
+        };
+
+        // Ideally, the device can hand us exactly what Deepspeech wants. Plenty of cheap USB
+        // microphones can only do stereo or 44.1/48kHz though, so otherwise we take whatever
+        // the device prefers and convert it ourselves.
+        let config = device
+            .supported_input_configs()?
+            .find(|c| {
+                c.channels() == 1
+                    && c.sample_format() == cpal::SampleFormat::I16
+                    && c.min_sample_rate().0 <= SAMPLE_RATE
+                    && c.max_sample_rate().0 >= SAMPLE_RATE
+            })
+            .map(|c| c.with_sample_rate(cpal::SampleRate(SAMPLE_RATE)));
+        let config = match config {
+            Some(config) => config,
+            None => device.default_input_config()?,
+        };
+        println!("config: {:?}", config);
+
+        let (tx, rx) = unbounded();
+        let converter = Converter::new(config.sample_rate().0, config.channels());
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::I16 => {
+                build_input_stream::<i16>(&device, &config.config(), tx, converter)?
+            }
+            cpal::SampleFormat::U16 => {
+                build_input_stream::<u16>(&device, &config.config(), tx, converter)?
+            }
+            cpal::SampleFormat::F32 => {
+                build_input_stream::<f32>(&device, &config.config(), tx, converter)?
+            }
+        };
+        stream.play()?;
+
+        Ok(Self {
+            _stream: stream,
+            rx,
+        })
+    }
+}
+
+impl AudioSource for MicrophoneSource {
+    fn next_chunk(&mut self) -> Option<Vec<i16>> {
+        self.rx.recv().ok()
+    }
+}
+
+/// Starts the input stream. In order to avoid issues with latency processing the samples, we have
+/// the stream just convert the data and send it out across a channel versus doing the processing
+/// in the callback itself.
+fn build_input_stream<T: cpal::Sample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    tx: Sender<Vec<i16>>,
+    mut converter: Converter,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let samples: Vec<f32> = data.iter().map(|samp| samp.to_f32()).collect();
+            tx.send(converter.convert(&samples))
+                .expect("Couldn't send audio data");
+        },
+        move |err| {
+            eprintln!("Error collecting data: {}", err);
+        },
+    )
+}
+
+/// Converts interleaved audio at an arbitrary sample rate and channel count into the 16kHz mono
+/// audio that the rest of the pipeline expects. Channels are averaged together and the result is
+/// linearly interpolated down (or up) to the right rate. It's not the fanciest resampler in the
+/// world, but speech doesn't have much going on above 8kHz anyhow.
+struct Converter {
+    channels: usize,
+    // How far to step through the input for every output sample.
+    step: f64,
+    // The position of the next output sample, relative to the last sample of the previous buffer.
+    position: f64,
+    last_sample: f32,
+}
+
+impl Converter {
+    fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            channels: channels.max(1) as usize,
+            step: sample_rate as f64 / SAMPLE_RATE as f64,
+            position: 1.0,
+            last_sample: 0.0,
+        }
+    }
+
+    fn convert(&mut self, data: &[f32]) -> Vec<i16> {
+        // Put the last sample from the previous buffer in front so we can interpolate across the
+        // boundary between buffers.
+        let mut mono = Vec::with_capacity(data.len() / self.channels + 1);
+        mono.push(self.last_sample);
+        mono.extend(
+            data.chunks_exact(self.channels)
+                .map(|frame| frame.iter().sum::<f32>() / self.channels as f32),
+        );
+
+        let len = mono.len() - 1;
+        let mut output = Vec::with_capacity((len as f64 / self.step) as usize + 1);
+        while self.position < len as f64 {
+            let index = self.position as usize;
+            let frac = (self.position - index as f64) as f32;
+            let samp = mono[index] * (1.0 - frac) + mono[index + 1] * frac;
+            output.push(cpal::Sample::to_i16(&samp.clamp(-1.0, 1.0)));
+            self.position += self.step;
+        }
+        self.position -= len as f64;
+        self.last_sample = mono[len];
+        output
+    }
+}
+
+/// Audio from a WAV file. The file has to already be in the format the pipeline expects.
+pub struct WavSource<R: Read> {
+    reader: hound::WavReader<R>,
+}
+
+impl WavSource<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> WavSource<R> {
+    pub fn new(reader: R) -> Result<Self, Box<dyn std::error::Error>> {
+        let reader = hound::WavReader::new(reader)?;
+        let spec = reader.spec();
+        if spec.channels != 1
+            || spec.sample_rate != SAMPLE_RATE
+            || spec.bits_per_sample != 16
+            || spec.sample_format != hound::SampleFormat::Int
+        {
+            return Err(format!("WAV files must be 16kHz mono 16-bit PCM, got {:?}", spec).into());
+        }
+        Ok(Self { reader })
+    }
+}
+
+impl<R: Read> AudioSource for WavSource<R> {
+    fn next_chunk(&mut self) -> Option<Vec<i16>> {
+        let chunk: Vec<i16> = self
+            .reader
+            .samples::<i16>()
+            .take(CHUNK_LEN)
+            .filter_map(|samp| samp.ok())
+            .collect();
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
+
+/// Raw 16kHz mono little-endian i16 PCM with no header, as you'd get from something like
+/// `arecord -f S16_LE -r 16000 -c 1 -t raw`.
+pub struct PcmSource<R: Read> {
+    reader: R,
+}
+
+impl PcmSource<io::Stdin> {
+    pub fn stdin() -> Self {
+        Self::new(io::stdin())
+    }
+}
+
+impl<R: Read> PcmSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: Read> AudioSource for PcmSource<R> {
+    fn next_chunk(&mut self) -> Option<Vec<i16>> {
+        // Pipes will happily hand us partial reads, so keep reading until we either have a full
+        // chunk or we've hit the end of the input.
+        let mut buffer = vec![0u8; CHUNK_LEN * 2];
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match self.reader.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(len) => filled += len,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    eprintln!("Error reading audio: {}", e);
+                    break;
+                }
+            }
+        }
+
+        // A trailing odd byte can't make up a full sample, so it gets dropped.
+        let chunk: Vec<i16> = buffer[..filled]
+            .chunks_exact(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn wav_bytes(spec: hound::WavSpec, samples: &[i16]) -> Vec<u8> {
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut writer = hound::WavWriter::new(&mut cursor, spec).unwrap();
+            for samp in samples {
+                writer.write_sample(*samp).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        cursor.into_inner()
+    }
+
+    fn mono_spec(sample_rate: u32) -> hound::WavSpec {
+        hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        }
+    }
+
+    #[test]
+    fn test_wav_source() {
+        let samples: Vec<i16> = (0..4000).map(|x| x as i16).collect();
+        let bytes = wav_bytes(mono_spec(SAMPLE_RATE), &samples);
+        let mut source = WavSource::new(Cursor::new(bytes)).unwrap();
+
+        let mut read = vec![];
+        while let Some(mut chunk) = source.next_chunk() {
+            assert!(chunk.len() <= CHUNK_LEN);
+            read.append(&mut chunk);
+        }
+        assert_eq!(read, samples);
+    }
+
+    #[test]
+    fn test_wav_source_wrong_format() {
+        let bytes = wav_bytes(mono_spec(44_100), &[0; 100]);
+        assert!(WavSource::new(Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn test_converter_passthrough() {
+        let samples: Vec<i16> = (0..1000).map(|x| (x * 13 - 6000) as i16).collect();
+        let data: Vec<f32> = samples.iter().map(cpal::Sample::to_f32).collect();
+        let mut converter = Converter::new(SAMPLE_RATE, 1);
+
+        // Audio that's already in the right format should come out the other end untouched, with
+        // at most a sample held over until the next buffer.
+        let mut output = converter.convert(&data[..500]);
+        output.append(&mut converter.convert(&data[500..]));
+        assert_eq!(output.len(), samples.len() - 1);
+        for (out, samp) in output.iter().zip(samples.iter()) {
+            assert!((out - samp).abs() <= 1, "{} != {}", out, samp);
+        }
+    }
+
+    #[test]
+    fn test_converter_downmix() {
+        // Left and right are opposites of each other, so they should average out to silence.
+        let data: Vec<f32> = (0..320)
+            .flat_map(|x| {
+                let samp = (x as f32 / 50.0).sin() * 0.5;
+                vec![samp, -samp]
+            })
+            .collect();
+        let mut converter = Converter::new(SAMPLE_RATE, 2);
+        let output = converter.convert(&data);
+        assert_eq!(output.len(), 319);
+        assert!(output.iter().all(|samp| *samp == 0));
+    }
+
+    #[test]
+    fn test_converter_resample() {
+        // A second of a 440Hz tone at 48kHz should come out as a second of the same tone at
+        // 16kHz, regardless of how the driver happens to chunk it up.
+        let tone =
+            |rate: f32, i: usize| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / rate).sin();
+        let data: Vec<f32> = (0..48_000).map(|i| tone(48_000.0, i) * 0.5).collect();
+        let mut converter = Converter::new(48_000, 1);
+        let output: Vec<i16> = data
+            .chunks(1024)
+            .flat_map(|chunk| converter.convert(chunk))
+            .collect();
+
+        assert!((output.len() as i64 - SAMPLE_RATE as i64).abs() <= 1);
+        for (i, samp) in output.iter().enumerate() {
+            let expected = tone(SAMPLE_RATE as f32, i) * 0.5 * i16::MAX as f32;
+            assert!((*samp as f32 - expected).abs() < 300.0, "sample {}", i);
+        }
+    }
+
+    #[test]
+    fn test_pcm_source() {
+        let samples: Vec<i16> = (0..2000).map(|x| (x * 7 - 5000) as i16).collect();
+        let mut bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        // A stray byte at the end shouldn't turn into a sample.
+        bytes.push(0xff);
+        let mut source = PcmSource::new(Cursor::new(bytes));
+
+        assert_eq!(source.next_chunk(), Some(samples[..CHUNK_LEN].to_vec()));
+        assert_eq!(source.next_chunk(), Some(samples[CHUNK_LEN..].to_vec()));
+        assert_eq!(source.next_chunk(), None);
+    }
+}
@@ -1,112 +1,171 @@
-use super::number::parse_number_from_voice;
+use super::{
+    grammar::{Grammar, Intent, IntentType, SlotValue},
+    number::NumberParser,
+    time::{self, TimeWords},
+};
 use crate::settings::Language;
+use chrono::{DateTime, Local, NaiveDate};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, convert::TryInto, fs::File, io::Read, time::Duration};
+use std::{fs::File, io::Read, time::Duration};
 
-/// An enumeration of all possible command types that the system knows how to execute from voice
-/// commands. This is definitely a non-exhausive list.
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
-pub enum CommandType {
-    Weather,
-    News,
-    Timer,
-}
-
-#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum Command {
-    Weather,
+    // The location asked about as it was heard and the day asked about, if there were any.
+    Weather(Option<String>, Option<NaiveDate>),
     News,
     Timer(Duration),
+    Alarm(DateTime<Local>),
+    // When to remind and what to remind about.
+    Reminder(DateTime<Local>, String),
+    // The place to add to the weather locations, as it was heard.
+    AddLocation(String),
+}
+
+impl Command {
+    /// Turns an intent into something we can actually execute. Returns None if the intent is
+    /// missing details we need, such as a timer without a duration. Times like "tomorrow at
+    /// noon" are worked out relative to `now`.
+    pub fn from_intent(intent: &Intent, now: &DateTime<Local>) -> Option<Self> {
+        let duration = match intent.slots.get("duration") {
+            Some(SlotValue::Duration(duration)) => Some(*duration),
+            _ => None,
+        };
+        let day = match intent.slots.get("day") {
+            Some(SlotValue::Day(day)) => Some(*day),
+            _ => None,
+        };
+        let time = match intent.slots.get("time") {
+            Some(SlotValue::Time(time)) => Some(*time),
+            _ => None,
+        };
+        let location = match intent.slots.get("location") {
+            Some(SlotValue::Location(location)) => Some(location.clone()),
+            _ => None,
+        };
+
+        match intent.intent {
+            IntentType::Weather => Some(Command::Weather(
+                location,
+                day.map(|day| day.date(now.date().naive_local())),
+            )),
+            IntentType::News => Some(Command::News),
+            IntentType::Timer => duration.map(Command::Timer),
+            IntentType::Alarm => time::resolve(day, time, now).map(Command::Alarm),
+            IntentType::Reminder => {
+                let text = match intent.slots.get("text") {
+                    Some(SlotValue::Text(text)) => text.clone(),
+                    _ => return None,
+                };
+                let at = match duration {
+                    Some(duration) => *now + chrono::Duration::from_std(duration).ok()?,
+                    None => time::resolve(day, time, now)?,
+                };
+                Some(Command::Reminder(at, text))
+            }
+            IntentType::AddLocation => location.map(Command::AddLocation),
+            // These only mean something on top of what was said before, which is up to the
+            // dialog to work out.
+            IntentType::FollowUp => None,
+        }
+    }
 }
 
 pub struct CommandParser {
-    command_map: HashMap<String, CommandType>,
+    grammar: Grammar,
 }
 
 impl CommandParser {
-    pub fn init(language: Language) -> Result<Self, Box<dyn std::error::Error>> {
-        let command_suffix = match language {
-            Language::English => "en",
-        };
+    /// Where the intents for the language are loaded from.
+    pub fn intents_path(language: Language) -> String {
+        format!("intents_{}.json", language.code())
+    }
 
-        let command_file_path: String = format!("commands_{}.json", command_suffix);
-        let mut command_file = File::open(command_file_path)?;
+    pub fn init(language: Language) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut intent_file = File::open(Self::intents_path(language))?;
         let mut data = String::new();
-        command_file.read_to_string(&mut data)?;
-        let command_map: HashMap<String, CommandType> = serde_json::from_str(&data)?;
-        Ok(Self { command_map })
+        intent_file.read_to_string(&mut data)?;
+        let grammar = Grammar::from_json(
+            &data,
+            NumberParser::init(language)?,
+            TimeWords::init(language)?,
+        )?;
+        Ok(Self { grammar })
     }
 
-    pub fn parse(&self, command: &str) -> Option<Command> {
-        // We're going to do a pretty naive lookup here. We're going to scan the received command
-        // for words that help us indicate what type of command this may be. Based on whether any
-        // of the words that we map to a command are present, we'll then handle the command
-        // specifically.
-        let command_type = command
-            .split_whitespace()
-            .find_map(|cmd| self.command_map.get(cmd));
-
-        println!("Command type: {:?}", command_type);
-        if let Some(cmd_type) = command_type {
-            match cmd_type {
-                CommandType::Weather => Some(Command::Weather),
-                CommandType::News => Some(Command::News),
-                CommandType::Timer => self.parse_timer(command),
-            }
-        } else {
-            None
-        }
+    /// The words that set one command apart from the others, which are the ones most worth
+    /// hearing right.
+    pub fn keywords(&self) -> Vec<String> {
+        self.grammar.keywords()
     }
 
-    // Again, this is going to be English centric for now until we figure out a sane way to keep
-    // the effective mapping between words and what they mean.
-    fn parse_timer(&self, command: &str) -> Option<Command> {
-        if let Some(number) = parse_number_from_voice(command) {
-            let number: u64 = number.try_into().unwrap();
-            if command.contains("second") {
-                Some(Command::Timer(Duration::from_secs(number)))
-            } else if command.contains("minute") {
-                Some(Command::Timer(Duration::from_secs(number * 60)))
-            } else if command.contains("hour") {
-                Some(Command::Timer(Duration::from_secs(number * 60 * 60)))
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+    /// Figures out what was asked for along with any details that came with it.
+    pub fn parse_intent(&self, command: &str) -> Option<Intent> {
+        self.grammar.parse(command)
     }
 }
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::voice::time::Day;
+    use chrono::TimeZone;
+
+    // Outside of a conversation, which is left to the dialog, an intent maps straight onto a
+    // command.
+    impl CommandParser {
+        fn parse(&self, command: &str) -> Option<Command> {
+            self.parse_at(command, &Local::now())
+        }
+
+        /// Parses a command as if it was said at the given time.
+        fn parse_at(&self, command: &str, now: &DateTime<Local>) -> Option<Command> {
+            self.parse_intent(command)
+                .and_then(|intent| Command::from_intent(&intent, now))
+        }
+    }
 
     #[test]
     fn test_basic_parsing() {
         let language = Language::English;
-        let command_parser = CommandParser::init(language).expect("No command.json file found");
+        let command_parser = CommandParser::init(language).expect("No intents file found");
         assert_eq!(
             command_parser.parse("what is the weather"),
-            Some(Command::Weather)
+            Some(Command::Weather(None, None))
+        );
+        assert_eq!(
+            command_parser.parse("weather"),
+            Some(Command::Weather(None, None))
         );
-        assert_eq!(command_parser.parse("weather"), Some(Command::Weather));
         assert_eq!(command_parser.parse("news"), Some(Command::News));
+        assert_eq!(
+            command_parser.parse("what's the weather in the cabin"),
+            Some(Command::Weather(Some("the cabin".into()), None))
+        );
+
+        // In this case, we have multiple commands as once. Neither covers more of what was said, so
+        // we should just pick up the first one.
+        assert_eq!(
+            command_parser.parse("weather news"),
+            Some(Command::Weather(None, None))
+        );
 
-        // In this case, we have multiple commands as once. Our naive parsing should just pick up
-        // the first one.
-        assert_eq!(command_parser.parse("weather news"), Some(Command::Weather));
+        // Here the weather is what the news is about, not a command of its own.
+        assert_eq!(
+            command_parser.parse("what's the news about the weather"),
+            Some(Command::News)
+        );
     }
 
     #[test]
     fn test_timer() {
         let language = Language::English;
-        let command_parser = CommandParser::init(language).expect("No command.json file found");
+        let command_parser = CommandParser::init(language).expect("No intents file found");
         assert_eq!(
             command_parser.parse("set timer for thirty hours"),
             Some(Command::Timer(Duration::from_secs(30 * 60 * 60)))
         );
         assert_eq!(
-            command_parser.parse("set timer for fourty two minutes"),
+            command_parser.parse("set timer for forty two minutes"),
             Some(Command::Timer(Duration::from_secs(42 * 60)))
         );
         assert_eq!(
@@ -114,5 +173,157 @@ mod tests {
                 .parse("set timer for one hundred and twelve thousand and sixty two seconds"),
             Some(Command::Timer(Duration::from_secs(112_062)))
         );
+        assert_eq!(
+            command_parser.parse("start a ten minute timer"),
+            Some(Command::Timer(Duration::from_secs(10 * 60)))
+        );
+
+        assert_eq!(
+            command_parser.parse("set a timer for an hour and a half"),
+            Some(Command::Timer(Duration::from_secs(90 * 60)))
+        );
+        assert_eq!(
+            command_parser.parse("set timer for one hour and thirty minutes"),
+            Some(Command::Timer(Duration::from_secs(90 * 60)))
+        );
+        assert_eq!(
+            command_parser.parse("start a half hour timer"),
+            Some(Command::Timer(Duration::from_secs(30 * 60)))
+        );
+
+        // Without a duration, there's nothing for the timer to do.
+        assert_eq!(command_parser.parse("set a timer"), None);
+    }
+
+    #[test]
+    fn test_intent_slots() {
+        let language = Language::English;
+        let command_parser = CommandParser::init(language).expect("No intents file found");
+        let intent = command_parser
+            .parse_intent("what's the weather like tomorrow in san francisco")
+            .unwrap();
+        assert_eq!(intent.intent, IntentType::Weather);
+        assert_eq!(
+            intent.slots.get("location"),
+            Some(&SlotValue::Location("san francisco".into()))
+        );
+        assert_eq!(
+            intent.slots.get("day"),
+            Some(&SlotValue::Day(Day::Tomorrow))
+        );
+    }
+
+    #[test]
+    fn test_alarms_and_reminders() {
+        let language = Language::English;
+        let command_parser = CommandParser::init(language).expect("No intents file found");
+        // Friday the 15th of March 2024 at 8:15am.
+        let now = Local.ymd(2024, 3, 15).and_hms(8, 15, 0);
+        let at =
+            |day: u32, hour: u32, minute: u32| Local.ymd(2024, 3, day).and_hms(hour, minute, 0);
+
+        assert_eq!(
+            command_parser.parse_at("set an alarm for seven thirty", &now),
+            Some(Command::Alarm(at(15, 19, 30)))
+        );
+        assert_eq!(
+            command_parser.parse_at("set an alarm for quarter past six pm", &now),
+            Some(Command::Alarm(at(15, 18, 15)))
+        );
+        assert_eq!(
+            command_parser.parse_at("wake me up tomorrow at noon", &now),
+            Some(Command::Alarm(at(16, 12, 0)))
+        );
+        assert_eq!(
+            command_parser.parse_at("set an alarm for next tuesday at 9", &now),
+            Some(Command::Alarm(at(19, 9, 0)))
+        );
+        assert_eq!(
+            command_parser.parse_at("remind me to call mom tomorrow at noon", &now),
+            Some(Command::Reminder(at(16, 12, 0), "call mom".into()))
+        );
+        assert_eq!(
+            command_parser.parse_at("remind me at seven thirty to take out the trash", &now),
+            Some(Command::Reminder(
+                at(15, 19, 30),
+                "take out the trash".into()
+            ))
+        );
+        assert_eq!(
+            command_parser.parse_at("remind me to check the oven in ten minutes", &now),
+            Some(Command::Reminder(at(15, 8, 25), "check the oven".into()))
+        );
+
+        // An alarm needs to know when to go off.
+        assert_eq!(command_parser.parse_at("set an alarm", &now), None);
+    }
+
+    #[test]
+    fn test_german() {
+        let command_parser = CommandParser::init(Language::German).expect("No intents file found");
+        // Friday the 15th of March 2024 at 8:15am.
+        let now = Local.ymd(2024, 3, 15).and_hms(8, 15, 0);
+        let at =
+            |day: u32, hour: u32, minute: u32| Local.ymd(2024, 3, day).and_hms(hour, minute, 0);
+
+        assert_eq!(
+            command_parser.parse_at("wie wird das wetter morgen", &now),
+            Some(Command::Weather(
+                None,
+                Some(NaiveDate::from_ymd(2024, 3, 16))
+            ))
+        );
+        assert_eq!(
+            command_parser.parse("was gibt es in den nachrichten"),
+            Some(Command::News)
+        );
+        assert_eq!(
+            command_parser.parse("stelle einen timer für fünf minuten"),
+            Some(Command::Timer(Duration::from_secs(5 * 60)))
+        );
+        assert_eq!(
+            command_parser.parse("timer für eine halbe stunde"),
+            Some(Command::Timer(Duration::from_secs(30 * 60)))
+        );
+        assert_eq!(
+            command_parser.parse("eine stunde und zehn minuten timer"),
+            Some(Command::Timer(Duration::from_secs(70 * 60)))
+        );
+        assert_eq!(
+            command_parser.parse_at("weck mich morgen um halb acht auf", &now),
+            Some(Command::Alarm(at(16, 7, 30)))
+        );
+        assert_eq!(
+            command_parser.parse_at("stell einen wecker für sieben uhr abends", &now),
+            Some(Command::Alarm(at(15, 19, 0)))
+        );
+        assert_eq!(
+            command_parser.parse_at("erinnere mich in zehn minuten an den ofen", &now),
+            Some(Command::Reminder(at(15, 8, 25), "den ofen".into()))
+        );
+        assert_eq!(
+            command_parser.parse_at(
+                "erinnere mich am dienstag um neun daran mama anzurufen",
+                &now
+            ),
+            Some(Command::Reminder(at(19, 9, 0), "mama anzurufen".into()))
+        );
+        assert_eq!(
+            command_parser.parse("füge hamburg zu den wetterorten hinzu"),
+            Some(Command::AddLocation("hamburg".into()))
+        );
+    }
+
+    #[test]
+    fn test_add_location() {
+        let command_parser = CommandParser::init(Language::English).expect("No intents file found");
+        assert_eq!(
+            command_parser.parse("add springfield missouri to the weather"),
+            Some(Command::AddLocation("springfield missouri".into()))
+        );
+        assert_eq!(
+            command_parser.parse("save a weather location for paris"),
+            Some(Command::AddLocation("paris".into()))
+        );
     }
 }
@@ -0,0 +1,273 @@
+use super::{
+    audio::SAMPLE_RATE,
+    command::Command,
+    recognition::{Candidate, Recognition},
+    vad::ChunkResult,
+};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// How often to grab an intermediate transcript while recording an utterance, in milliseconds of
+/// recorded audio.
+const INTERMEDIATE_INTERVAL_MS: u64 = 1000;
+
+/// A single voice activity detection decision made while an utterance was being recorded.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VadDecision {
+    // How far into the recording the chunk started.
+    pub offset_ms: u64,
+    pub length_ms: u64,
+    pub level: i16,
+    pub gate: i16,
+    pub is_speech: bool,
+}
+
+/// A transcript that Deepspeech gave us partway through the utterance.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IntermediateTranscript {
+    pub offset_ms: u64,
+    pub transcript: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct UtteranceTimings {
+    // How much audio was fed to Deepspeech.
+    pub audio_ms: u64,
+    // How long the final decode took once the utterance had ended.
+    pub decode_ms: u64,
+    // How long it took to turn the transcript into a command.
+    pub parse_us: u64,
+}
+
+/// Everything we know about how an utterance was handled. This is written out next to the audio
+/// of the utterance so we can figure out after the fact why something was misrecognized.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UtteranceRecord {
+    pub recorded_at: DateTime<Local>,
+    pub intermediate_transcripts: Vec<IntermediateTranscript>,
+    pub transcript: String,
+    #[serde(default)]
+    pub confidence: Option<f64>,
+    pub command: Option<Command>,
+    // Every transcript Deepspeech thought the utterance could be, most confident first.
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+    pub vad_decisions: Vec<VadDecision>,
+    pub timings: UtteranceTimings,
+}
+
+impl UtteranceRecord {
+    /// Loads the record that sits next to the given recording, if there is one.
+    pub fn load_for(wav_path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut file = File::open(wav_path.with_extension("json"))?;
+        let mut data = String::new();
+        file.read_to_string(&mut data)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+}
+
+/// Collects the audio and the decisions made about it over the course of an utterance.
+pub struct UtteranceRecorder {
+    directory: PathBuf,
+    audio: Vec<i16>,
+    // Where the next chunk starts in the recorded audio.
+    offset: usize,
+    vad_decisions: Vec<VadDecision>,
+    intermediate_transcripts: Vec<IntermediateTranscript>,
+}
+
+impl UtteranceRecorder {
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+            audio: vec![],
+            offset: 0,
+            vad_decisions: vec![],
+            intermediate_transcripts: vec![],
+        }
+    }
+
+    /// Records what the detector decided about a chunk. Chunks from before an utterance starts are
+    /// only kept as long as the detector holds on to their audio.
+    pub fn record_chunk(&mut self, len: usize, result: &ChunkResult) {
+        if self.audio.is_empty() && result.audio.is_empty() {
+            return;
+        }
+        if self.audio.is_empty() {
+            // This is the chunk that started the utterance. The audio we got includes whatever
+            // the detector held onto from before it, so line our offsets up with that.
+            self.offset = result.audio.len().saturating_sub(len);
+        }
+        self.audio.extend_from_slice(&result.audio);
+        self.vad_decisions.push(VadDecision {
+            offset_ms: samples_to_ms(self.offset),
+            length_ms: samples_to_ms(len),
+            level: result.level,
+            gate: result.gate,
+            is_speech: result.is_speech,
+        });
+        self.offset += len;
+    }
+
+    /// Whether enough audio has been recorded since the last intermediate transcript that we
+    /// should grab another one.
+    pub fn intermediate_due(&self) -> bool {
+        let last = self
+            .intermediate_transcripts
+            .last()
+            .map_or(0, |transcript| transcript.offset_ms);
+        self.recorded_ms() >= last + INTERMEDIATE_INTERVAL_MS
+    }
+
+    pub fn record_intermediate(&mut self, transcript: String) {
+        self.intermediate_transcripts.push(IntermediateTranscript {
+            offset_ms: samples_to_ms(self.audio.len()),
+            transcript,
+        });
+    }
+
+    /// How much audio has been recorded so far, in milliseconds.
+    pub fn recorded_ms(&self) -> u64 {
+        samples_to_ms(self.audio.len())
+    }
+
+    /// Throws away everything recorded for the current utterance.
+    pub fn discard(&mut self) {
+        self.audio.clear();
+        self.offset = 0;
+        self.vad_decisions.clear();
+        self.intermediate_transcripts.clear();
+    }
+
+    /// Writes the utterance out as a WAV file with a JSON file of the same name next to it, and
+    /// gets ready to record the next utterance.
+    pub fn finish(
+        &mut self,
+        recognition: &Recognition,
+        candidates: Vec<Candidate>,
+        mut timings: UtteranceTimings,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let recorded_at = Local::now();
+        let wav_path = self.directory.join(format!(
+            "utterance-{}.wav",
+            recorded_at.format("%Y%m%d-%H%M%S%.3f")
+        ));
+        timings.audio_ms = self.recorded_ms();
+
+        let record = UtteranceRecord {
+            recorded_at,
+            intermediate_transcripts: std::mem::take(&mut self.intermediate_transcripts),
+            transcript: recognition.transcript.clone(),
+            confidence: Some(recognition.confidence),
+            command: recognition.turn.command(),
+            candidates,
+            vad_decisions: std::mem::take(&mut self.vad_decisions),
+            timings,
+        };
+        let audio = std::mem::take(&mut self.audio);
+        self.offset = 0;
+
+        std::fs::create_dir_all(&self.directory)?;
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&wav_path, spec)?;
+        for samp in audio {
+            writer.write_sample(samp)?;
+        }
+        writer.finalize()?;
+
+        let mut file = File::create(wav_path.with_extension("json"))?;
+        write!(file, "{}", serde_json::to_string_pretty(&record)?)?;
+        Ok(wav_path)
+    }
+}
+
+fn samples_to_ms(samples: usize) -> u64 {
+    samples as u64 * 1000 / SAMPLE_RATE as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voice::{
+        audio::{AudioSource, WavSource},
+        dialog::{Outcome, Turn},
+    };
+    use std::{env, fs, time::Duration};
+
+    fn chunk(audio: Vec<i16>, is_speech: bool) -> ChunkResult {
+        ChunkResult {
+            audio,
+            is_speech,
+            level: 5000,
+            gate: 1000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_record_utterance() {
+        let directory = env::temp_dir().join(format!("smart_tablet_debug_{}", std::process::id()));
+        let mut recorder = UtteranceRecorder::new(&directory);
+
+        // Quiet chunks the detector didn't hand us any audio for shouldn't show up at all.
+        recorder.record_chunk(1600, &chunk(vec![], false));
+        // The detector hands over an extra 1600 samples from before the speech started.
+        recorder.record_chunk(1600, &chunk(vec![1; 3200], true));
+        recorder.record_intermediate("set".into());
+        recorder.record_chunk(1600, &chunk(vec![2; 1600], false));
+        assert_eq!(recorder.recorded_ms(), 300);
+
+        let command = Command::Timer(Duration::from_secs(60));
+        let recognition = Recognition {
+            transcript: "set timer one minute".into(),
+            confidence: -12.5,
+            turn: Turn {
+                intent: None,
+                outcome: Outcome::Command(command.clone()),
+            },
+        };
+        let candidates = vec![Candidate {
+            transcript: "set timer one minute".into(),
+            confidence: -12.5,
+        }];
+        let wav_path = recorder
+            .finish(
+                &recognition,
+                candidates.clone(),
+                UtteranceTimings::default(),
+            )
+            .unwrap();
+
+        let record = UtteranceRecord::load_for(&wav_path).unwrap();
+        assert_eq!(record.transcript, "set timer one minute");
+        assert_eq!(record.confidence, Some(-12.5));
+        assert_eq!(record.command, Some(command));
+        assert_eq!(record.candidates, candidates);
+        assert_eq!(record.timings.audio_ms, 300);
+        assert_eq!(record.intermediate_transcripts[0].offset_ms, 200);
+        let offsets: Vec<u64> = record.vad_decisions.iter().map(|d| d.offset_ms).collect();
+        assert_eq!(offsets, vec![100, 200]);
+
+        let mut source = WavSource::open(&wav_path).unwrap();
+        let mut audio = vec![];
+        while let Some(mut samps) = source.next_chunk() {
+            audio.append(&mut samps);
+        }
+        assert_eq!(audio.len(), 4800);
+        assert_eq!(audio[3200..], [2; 1600]);
+
+        // The recorder should be all set for the next utterance.
+        assert_eq!(recorder.recorded_ms(), 0);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,338 @@
+use super::{
+    command::Command,
+    grammar::{Intent, IntentType},
+};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Something we can ask when a command is missing a detail we need.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Question {
+    // "For how long?"
+    Duration,
+    // "For what time?"
+    Time,
+}
+
+/// Where the conversation is at.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DialogState {
+    // Nothing's going on, so whatever is said next has to stand on its own.
+    Idle,
+    // We asked a question about the intent and the next thing said should answer it.
+    Asking { intent: Intent, question: Question },
+    // A command was just carried out, so whatever is said next can build on its intent, like
+    // "and tomorrow?" after asking about the weather.
+    FollowingUp { intent: Intent },
+}
+
+/// What we make of a single utterance.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Command(Command),
+    Ask(Question),
+    NotUnderstood,
+}
+
+/// One turn of the conversation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Turn {
+    // What the utterance was understood as, with anything carried over from earlier in the
+    // conversation filled in.
+    pub intent: Option<Intent>,
+    pub outcome: Outcome,
+}
+
+impl Turn {
+    /// A turn where nothing was understood at all.
+    pub fn not_understood() -> Self {
+        Self {
+            intent: None,
+            outcome: Outcome::NotUnderstood,
+        }
+    }
+
+    pub fn command(&self) -> Option<Command> {
+        match &self.outcome {
+            Outcome::Command(command) => Some(command.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Keeps track of the conversation so that commands can be spread across several utterances.
+/// Utterances are interpreted without changing anything first, so that several candidate
+/// transcripts can be tried, and then the conversation is moved along with the one that was
+/// picked. If nothing is understood for long enough, the conversation goes back to idle.
+pub struct Dialog {
+    state: DialogState,
+    // When the conversation last moved along.
+    updated_at: Option<DateTime<Local>>,
+    timeout: Duration,
+}
+
+impl Dialog {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            state: DialogState::Idle,
+            updated_at: None,
+            timeout,
+        }
+    }
+
+    /// Where the conversation is at as of `now`.
+    pub fn state(&self, now: &DateTime<Local>) -> &DialogState {
+        let timed_out = match (self.updated_at, chrono::Duration::from_std(self.timeout)) {
+            (Some(updated_at), Ok(timeout)) => *now - updated_at > timeout,
+            _ => true,
+        };
+        if timed_out {
+            &DialogState::Idle
+        } else {
+            &self.state
+        }
+    }
+
+    /// Works out what an intent means given the conversation so far, without moving the
+    /// conversation along. Follow-ups are merged into the intent being talked about as long as
+    /// they only fill in details it uses, and intents that are missing something we need get a
+    /// question asked about them.
+    pub fn interpret(&self, intent: Option<Intent>, now: &DateTime<Local>) -> Turn {
+        let intent = match (intent, self.state(now)) {
+            (Some(follow_up), DialogState::Asking { intent, .. })
+            | (Some(follow_up), DialogState::FollowingUp { intent })
+                if follow_up.intent == IntentType::FollowUp
+                    && follow_up
+                        .slots
+                        .keys()
+                        .all(|slot| slot_names(intent.intent).contains(&slot.as_str())) =>
+            {
+                let mut intent = intent.clone();
+                intent.slots.extend(follow_up.slots);
+                intent
+            }
+            (Some(intent), _) if intent.intent != IntentType::FollowUp => intent,
+            _ => return Turn::not_understood(),
+        };
+
+        let outcome = match missing_detail(&intent) {
+            Some(question) => Outcome::Ask(question),
+            None => match Command::from_intent(&intent, now) {
+                Some(command) => Outcome::Command(command),
+                None => Outcome::NotUnderstood,
+            },
+        };
+        Turn {
+            intent: Some(intent),
+            outcome,
+        }
+    }
+
+    /// Moves the conversation along after a turn. Turns that weren't understood are ignored, so
+    /// a stray word picked up while we're waiting on an answer doesn't end the conversation.
+    pub fn advance(&mut self, turn: &Turn, now: &DateTime<Local>) {
+        let intent = match &turn.intent {
+            Some(intent) => intent.clone(),
+            None => return,
+        };
+        self.state = match turn.outcome {
+            Outcome::Command(_) => DialogState::FollowingUp { intent },
+            Outcome::Ask(question) => DialogState::Asking { intent, question },
+            Outcome::NotUnderstood => return,
+        };
+        self.updated_at = Some(*now);
+    }
+}
+
+/// The slots an intent makes use of, which are the only ones a follow-up can fill in for it.
+/// Anything else would just run the same command again, like a second timer after "and
+/// tomorrow?".
+fn slot_names(intent: IntentType) -> &'static [&'static str] {
+    match intent {
+        IntentType::Weather => &["day", "location"],
+        IntentType::Timer => &["duration"],
+        IntentType::Alarm => &["day", "time"],
+        IntentType::Reminder => &["day", "time", "duration", "text"],
+        IntentType::AddLocation => &["location"],
+        IntentType::News | IntentType::FollowUp => &[],
+    }
+}
+
+/// The question to ask if the intent is missing something that we can't do without.
+fn missing_detail(intent: &Intent) -> Option<Question> {
+    match intent.intent {
+        IntentType::Timer if !intent.slots.contains_key("duration") => Some(Question::Duration),
+        IntentType::Alarm if !intent.slots.contains_key("time") => Some(Question::Time),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{settings::Language, voice::command::CommandParser};
+    use chrono::{NaiveDate, TimeZone};
+
+    struct Conversation {
+        dialog: Dialog,
+        command_parser: CommandParser,
+        now: DateTime<Local>,
+    }
+
+    impl Conversation {
+        fn new() -> Self {
+            Self::in_language(Language::English)
+        }
+
+        fn in_language(language: Language) -> Self {
+            Self {
+                dialog: Dialog::new(Duration::from_secs(15)),
+                command_parser: CommandParser::init(language).expect("No intents file found"),
+                // Friday the 15th of March 2024 at 8:15am.
+                now: Local.ymd(2024, 3, 15).and_hms(8, 15, 0),
+            }
+        }
+
+        /// Says something some number of seconds after the last thing was said.
+        fn say_after(&mut self, secs: i64, text: &str) -> Turn {
+            self.now += chrono::Duration::seconds(secs);
+            let intent = self.command_parser.parse_intent(text);
+            let turn = self.dialog.interpret(intent, &self.now);
+            self.dialog.advance(&turn, &self.now);
+            turn
+        }
+
+        fn say(&mut self, text: &str) -> Outcome {
+            self.say_after(1, text).outcome
+        }
+
+        fn intent(&self) -> Option<IntentType> {
+            match self.dialog.state(&self.now) {
+                DialogState::Idle => None,
+                DialogState::Asking { intent, .. } | DialogState::FollowingUp { intent } => {
+                    Some(intent.intent)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_missing_details() {
+        let mut conversation = Conversation::new();
+        assert_eq!(
+            conversation.say("set a timer"),
+            Outcome::Ask(Question::Duration)
+        );
+        assert_eq!(
+            conversation.say("five minutes"),
+            Outcome::Command(Command::Timer(Duration::from_secs(5 * 60)))
+        );
+
+        assert_eq!(
+            conversation.say("set an alarm"),
+            Outcome::Ask(Question::Time)
+        );
+        // Not an answer, so we ask again.
+        assert_eq!(conversation.say("tomorrow"), Outcome::Ask(Question::Time));
+        let tomorrow = Local.ymd(2024, 3, 16).and_hms(7, 30, 0);
+        assert_eq!(
+            conversation.say("at seven thirty"),
+            Outcome::Command(Command::Alarm(tomorrow))
+        );
+    }
+
+    #[test]
+    fn test_follow_ups() {
+        let mut conversation = Conversation::new();
+        assert_eq!(
+            conversation.say("what's the weather"),
+            Outcome::Command(Command::Weather(None, None))
+        );
+        let turn = conversation.say_after(1, "and tomorrow?");
+        let tomorrow = NaiveDate::from_ymd(2024, 3, 16);
+        assert_eq!(
+            turn.outcome,
+            Outcome::Command(Command::Weather(None, Some(tomorrow)))
+        );
+        assert_eq!(turn.intent.unwrap().intent, IntentType::Weather);
+
+        assert_eq!(
+            conversation.say("set a timer for ten minutes"),
+            Outcome::Command(Command::Timer(Duration::from_secs(10 * 60)))
+        );
+        assert_eq!(
+            conversation.say("and another for two minutes"),
+            Outcome::Command(Command::Timer(Duration::from_secs(2 * 60)))
+        );
+        // A timer has no day, so asking about tomorrow doesn't start another one.
+        assert_eq!(conversation.say("and tomorrow?"), Outcome::NotUnderstood);
+        assert_eq!(conversation.say("and in paris"), Outcome::NotUnderstood);
+        assert_eq!(conversation.intent(), Some(IntentType::Timer));
+
+        // Follow-ups need something to follow.
+        let mut conversation = Conversation::new();
+        assert_eq!(conversation.say("and tomorrow?"), Outcome::NotUnderstood);
+    }
+
+    #[test]
+    fn test_changing_the_subject() {
+        let mut conversation = Conversation::new();
+        assert_eq!(
+            conversation.say("set a timer"),
+            Outcome::Ask(Question::Duration)
+        );
+        assert_eq!(
+            conversation.say("what's the news"),
+            Outcome::Command(Command::News)
+        );
+        assert_eq!(conversation.intent(), Some(IntentType::News));
+
+        // Mumbling doesn't end the conversation.
+        assert_eq!(
+            conversation.say("set a timer"),
+            Outcome::Ask(Question::Duration)
+        );
+        assert_eq!(conversation.say("hmm let me think"), Outcome::NotUnderstood);
+        assert_eq!(conversation.intent(), Some(IntentType::Timer));
+    }
+
+    #[test]
+    fn test_german() {
+        let mut conversation = Conversation::in_language(Language::German);
+        assert_eq!(
+            conversation.say("stell einen timer"),
+            Outcome::Ask(Question::Duration)
+        );
+        assert_eq!(
+            conversation.say("für fünf minuten"),
+            Outcome::Command(Command::Timer(Duration::from_secs(5 * 60)))
+        );
+        assert_eq!(
+            conversation.say("wie ist das wetter"),
+            Outcome::Command(Command::Weather(None, None))
+        );
+        assert_eq!(
+            conversation.say("und morgen?"),
+            Outcome::Command(Command::Weather(
+                None,
+                Some(NaiveDate::from_ymd(2024, 3, 16))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut conversation = Conversation::new();
+        assert_eq!(
+            conversation.say("set a timer"),
+            Outcome::Ask(Question::Duration)
+        );
+        assert_eq!(conversation.intent(), Some(IntentType::Timer));
+
+        // Too late, so it's not taken as the answer.
+        let turn = conversation.say_after(16, "five minutes");
+        assert_eq!(turn.outcome, Outcome::NotUnderstood);
+        assert_eq!(conversation.intent(), None);
+    }
+}
@@ -0,0 +1,218 @@
+use super::{number::NumberParser, time::TimeWords};
+use std::time::Duration;
+
+/// The next smaller unit, for when the unit is left off the end like in "one hour fifteen".
+fn smaller_unit_seconds(seconds: f64) -> Option<f64> {
+    if seconds > 60.0 {
+        Some(60.0)
+    } else if seconds > 1.0 {
+        Some(1.0)
+    } else {
+        None
+    }
+}
+
+/// Parses a whole number of units, such as "ninety" or the "an" in "an hour".
+fn parse_whole(words: &[&str], numbers: &NumberParser) -> Option<f64> {
+    match words {
+        [word] if numbers.is_article(word) => Some(1.0),
+        [word] if word.chars().all(|c| c.is_ascii_digit()) => word.parse().ok(),
+        _ => numbers
+            .parse_number_from_words(words)
+            .map(|number| number as f64),
+    }
+}
+
+/// Parses a fraction of a unit such as "half", "a quarter" or "three quarters".
+fn parse_fraction(words: &[&str], numbers: &NumberParser, times: &TimeWords) -> Option<f64> {
+    let (denom, numerator) = words.split_last()?;
+    let denom = times.denominator(denom)?;
+    let numerator = match numerator {
+        [] => 1.0,
+        _ => parse_whole(numerator, numbers)?,
+    };
+    Some(numerator / denom)
+}
+
+/// Parses how many of a unit were asked for from the words said before the unit, such as the
+/// "two and a half" in "two and a half hours" or the "three quarters of an" in "three quarters of
+/// an hour".
+fn parse_amount(words: &[&str], numbers: &NumberParser, times: &TimeWords) -> Option<f64> {
+    let words = match words {
+        [rest @ .., of, article] if times.is_fraction_of(of) && numbers.is_article(article) => rest,
+        [rest @ .., fraction, article]
+            if times.denominator(fraction).is_some() && numbers.is_article(article) =>
+        {
+            &words[..rest.len() + 1]
+        }
+        _ => words,
+    };
+
+    if let Some(and) = words.iter().rposition(|word| numbers.is_chain_word(word)) {
+        if let (Some(whole), Some(fraction)) = (
+            parse_whole(&words[..and], numbers),
+            parse_fraction(&words[and + 1..], numbers, times),
+        ) {
+            return Some(whole + fraction);
+        }
+    }
+    if let Some(fraction) = parse_fraction(words, numbers, times) {
+        return Some(fraction);
+    }
+    parse_whole(words, numbers)
+}
+
+/// Strips off the "and" joining one part of a duration to the next.
+fn strip_and<'a, 'b>(words: &'a [&'b str], numbers: &NumberParser) -> &'a [&'b str] {
+    match words {
+        [and, rest @ ..] if numbers.is_chain_word(and) => rest,
+        _ => words,
+    }
+}
+
+/// Parses a spoken duration out of exactly the given words. Durations can be made up of several
+/// amounts of different units ("one hour and thirty minutes"), fractions of units ("half an hour",
+/// "a minute and a half") and can leave off the last unit ("an hour fifteen").
+pub fn parse_duration(
+    words: &[&str],
+    numbers: &NumberParser,
+    times: &TimeWords,
+) -> Option<Duration> {
+    let mut seconds = 0.0;
+    let mut last_unit = None;
+    let mut rest = words;
+
+    while !rest.is_empty() {
+        match rest
+            .iter()
+            .position(|word| times.unit_seconds(word).is_some())
+        {
+            Some(unit_pos) => {
+                let unit = times.unit_seconds(rest[unit_pos])?;
+                let amount = strip_and(&rest[..unit_pos], numbers);
+                seconds += parse_amount(amount, numbers, times)? * unit;
+                last_unit = Some(unit);
+                rest = &rest[unit_pos + 1..];
+            }
+            None => {
+                // Anything left over after the last unit is either a fraction of that unit or an
+                // amount of the next smaller one.
+                let unit = last_unit?;
+                let leftover = strip_and(rest, numbers);
+                seconds += match parse_fraction(leftover, numbers, times) {
+                    Some(fraction) => fraction * unit,
+                    None => parse_whole(leftover, numbers)? * smaller_unit_seconds(unit)?,
+                };
+                break;
+            }
+        }
+    }
+
+    if seconds > 0.0 {
+        Some(Duration::from_millis((seconds * 1000.0).round() as u64))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Language;
+
+    fn parse(text: &str) -> Option<Duration> {
+        parse_in(Language::English, text)
+    }
+
+    fn parse_in(language: Language, text: &str) -> Option<Duration> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        parse_duration(
+            &words,
+            &NumberParser::init(language).unwrap(),
+            &TimeWords::init(language).unwrap(),
+        )
+    }
+
+    fn secs(secs: u64) -> Option<Duration> {
+        Some(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn test_single_units() {
+        assert_eq!(parse("one second"), secs(1));
+        assert_eq!(parse("ninety seconds"), secs(90));
+        assert_eq!(parse("a minute"), secs(60));
+        assert_eq!(parse("forty two minutes"), secs(42 * 60));
+        assert_eq!(parse("an hour"), secs(60 * 60));
+        assert_eq!(parse("thirty hours"), secs(30 * 60 * 60));
+        assert_eq!(parse("ten min"), secs(10 * 60));
+        assert_eq!(parse("5 minutes"), secs(5 * 60));
+        assert_eq!(
+            parse("one hundred and twelve thousand and sixty two seconds"),
+            secs(112_062)
+        );
+    }
+
+    #[test]
+    fn test_compound() {
+        assert_eq!(parse("one hour and thirty minutes"), secs(90 * 60));
+        assert_eq!(parse("one hour thirty minutes"), secs(90 * 60));
+        assert_eq!(parse("two minutes and ninety seconds"), secs(210));
+        assert_eq!(
+            parse("one hour twenty minutes and five seconds"),
+            secs(60 * 60 + 20 * 60 + 5)
+        );
+        assert_eq!(
+            parse("one hundred and twenty minutes and one hundred and twenty seconds"),
+            secs(122 * 60)
+        );
+    }
+
+    #[test]
+    fn test_fractions() {
+        assert_eq!(parse("half an hour"), secs(30 * 60));
+        assert_eq!(parse("half a minute"), secs(30));
+        assert_eq!(parse("half hour"), secs(30 * 60));
+        assert_eq!(parse("quarter hour"), secs(15 * 60));
+        assert_eq!(parse("a quarter of an hour"), secs(15 * 60));
+        assert_eq!(parse("three quarters of an hour"), secs(45 * 60));
+        assert_eq!(parse("a third of an hour"), secs(20 * 60));
+        assert_eq!(parse("two and a half hours"), secs(150 * 60));
+        assert_eq!(parse("one and three quarters minutes"), secs(105));
+    }
+
+    #[test]
+    fn test_idioms() {
+        assert_eq!(parse("a minute and a half"), secs(90));
+        assert_eq!(parse("an hour and a half"), secs(90 * 60));
+        assert_eq!(parse("an hour and a quarter"), secs(75 * 60));
+        assert_eq!(parse("an hour fifteen"), secs(75 * 60));
+        assert_eq!(parse("two hours and ten"), secs(130 * 60));
+        assert_eq!(parse("three minutes thirty"), secs(210));
+    }
+
+    #[test]
+    fn test_not_durations() {
+        assert_eq!(parse(""), None);
+        assert_eq!(parse("minutes"), None);
+        assert_eq!(parse("five"), None);
+        assert_eq!(parse("zero seconds"), None);
+        assert_eq!(parse("five bananas"), None);
+        assert_eq!(parse("five minutes please"), None);
+        assert_eq!(parse("one second thirty"), None);
+        assert_eq!(parse("the weather for ten minutes"), None);
+        // Only the language's own words count.
+        assert_eq!(parse("five minuten"), None);
+    }
+
+    #[test]
+    fn test_german() {
+        let parse = |text| parse_in(Language::German, text);
+        assert_eq!(parse("fünf minuten"), secs(5 * 60));
+        assert_eq!(parse("eine stunde und dreißig minuten"), secs(90 * 60));
+        assert_eq!(parse("eine halbe stunde"), secs(30 * 60));
+        assert_eq!(parse("eine viertel stunde"), secs(15 * 60));
+        assert_eq!(parse("zwei stunden"), secs(2 * 60 * 60));
+        assert_eq!(parse("five minutes"), None);
+    }
+}
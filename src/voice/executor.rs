@@ -0,0 +1,357 @@
+use super::{
+    command::Command,
+    dialog::{Dialog, Outcome, Question, Turn},
+    history::{History, HistoryEntry},
+    reply::Replies,
+    tts,
+};
+use crate::{
+    message::UpdateMessage,
+    news::NewsService,
+    service::ServiceHandler,
+    settings::{self, SETTINGS},
+    weather::{self, WeatherLocation, WeatherService},
+};
+use chrono::{DateTime, Local, NaiveDate};
+use erased_serde::Serialize;
+use futures::{channel::mpsc, executor::block_on, SinkExt};
+use serde::Deserialize;
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+type UpdateSender = mpsc::Sender<Box<dyn Serialize + Send + Sync>>;
+
+/// What happened when we tried to act on something that was heard.
+#[derive(serde::Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ExecutionResult {
+    Success,
+    Failed(String),
+    // The transcript didn't parse to any command we know of.
+    NotRecognized,
+    // The command was missing something, so we asked for it.
+    Asked(Question),
+}
+
+/// Carries out commands and keeps a history of everything that was heard, along with the state of
+/// the conversation. This is cheap to clone and all clones share the same history and dialog, so
+/// it can be handed out to whatever needs it.
+#[derive(Clone)]
+pub struct CommandExecutor {
+    service_handler: ServiceHandler,
+    history: Arc<Mutex<History>>,
+    dialog: Arc<Mutex<Dialog>>,
+    tx: Arc<Mutex<Option<UpdateSender>>>,
+    // Whether to answer commands out loud.
+    speech: bool,
+}
+
+impl CommandExecutor {
+    pub fn new(
+        service_handler: ServiceHandler,
+        history_len: usize,
+        dialog_timeout: Duration,
+    ) -> Self {
+        Self {
+            service_handler,
+            history: Arc::new(Mutex::new(History::new(history_len))),
+            dialog: Arc::new(Mutex::new(Dialog::new(dialog_timeout))),
+            tx: Arc::new(Mutex::new(None)),
+            speech: false,
+        }
+    }
+
+    /// Has the executor answer commands out loud, as long as speech is turned on in the settings.
+    pub fn with_speech(mut self) -> Self {
+        self.speech = true;
+        self
+    }
+
+    /// Sets where updates from executing commands (and the history entries themselves) are sent.
+    pub fn set_sender(&self, tx: UpdateSender) {
+        *self.tx.lock().unwrap() = Some(tx);
+    }
+
+    /// Returns the history of everything that was heard, oldest first.
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        self.history.lock().unwrap().entries()
+    }
+
+    /// The conversation that utterances should be interpreted in.
+    pub fn dialog(&self) -> Arc<Mutex<Dialog>> {
+        self.dialog.clone()
+    }
+
+    /// Moves the conversation along with a turn interpreted from a transcript at `now`, executes
+    /// the command (if there is one) and records the whole thing in the history. Since this blocks
+    /// until the command has been carried out, this shouldn't be called from async code.
+    pub fn handle_utterance(
+        &self,
+        transcript: &str,
+        confidence: Option<f64>,
+        turn: Turn,
+        now: &DateTime<Local>,
+    ) -> HistoryEntry {
+        self.dialog.lock().unwrap().advance(&turn, now);
+        let command = turn.command();
+        let result = match &turn.outcome {
+            Outcome::Command(command) => self.execute(command),
+            Outcome::Ask(question) => ExecutionResult::Asked(*question),
+            Outcome::NotUnderstood => ExecutionResult::NotRecognized,
+        };
+        let reply = if self.speech {
+            self.reply(command.as_ref(), &result, now)
+        } else {
+            None
+        };
+        let entry = HistoryEntry {
+            timestamp: *now,
+            transcript: transcript.to_string(),
+            confidence,
+            command,
+            result,
+            reply,
+        };
+        self.history.lock().unwrap().push(entry.clone());
+        self.send(UpdateMessage::Utterance(entry.clone()));
+        if let Some(reply) = &entry.reply {
+            tts::say_in_background(reply.clone());
+        }
+        entry
+    }
+
+    fn execute(&self, command: &Command) -> ExecutionResult {
+        match command {
+            Command::Weather(location, date) => self.check_weather(location.as_deref(), *date),
+            Command::News => self.check_available(NewsService::get_service_name()),
+            Command::Timer(duration) => {
+                self.send_after(*duration, UpdateMessage::TimerFinished(*duration));
+                ExecutionResult::Success
+            }
+            Command::Alarm(at) => self.send_at(*at, UpdateMessage::Alarm(*at)),
+            Command::Reminder(at, text) => self.send_at(*at, UpdateMessage::Reminder(text.clone())),
+            Command::AddLocation(name) => add_location(name),
+        }
+    }
+
+    /// Works out what to say back after carrying out a command or asking for more, in the current
+    /// language.
+    fn reply(
+        &self,
+        command: Option<&Command>,
+        result: &ExecutionResult,
+        now: &DateTime<Local>,
+    ) -> Option<String> {
+        let (language, units) = {
+            let settings = SETTINGS.read().unwrap();
+            (settings.language, settings.weather_settings.units())
+        };
+        let replies = match Replies::init(language) {
+            Ok(replies) => replies,
+            Err(e) => {
+                eprintln!("Couldn't load replies: {}", e);
+                return None;
+            }
+        };
+
+        match result {
+            ExecutionResult::Success => (),
+            ExecutionResult::Failed(_) => return Some(replies.failed()),
+            ExecutionResult::NotRecognized => return None,
+            ExecutionResult::Asked(question) => return Some(replies.question(*question)),
+        }
+        let reply = match command? {
+            Command::Weather(location, date) => {
+                match self.latest_result(WeatherService::get_service_name())? {
+                    UpdateMessage::Weather(reports) => {
+                        let (name, report) = reports.find(location.as_deref())?;
+                        let location = location.as_ref().map(|_| name.as_str());
+                        replies.weather(report, location, *date, now, &units.temperature)
+                    }
+                    _ => return None,
+                }
+            }
+            Command::News => match self.latest_result(NewsService::get_service_name())? {
+                UpdateMessage::News(news) => replies.news(&news),
+                _ => return None,
+            },
+            Command::Timer(duration) => replies.timer(*duration),
+            Command::Alarm(at) => replies.alarm(at, now),
+            Command::Reminder(at, text) => replies.reminder(at, text, now),
+            Command::AddLocation(name) => replies.location_added(name),
+        };
+        Some(reply)
+    }
+
+    /// The latest update a service has sent out, if it's sent one.
+    fn latest_result(&self, service_name: String) -> Option<UpdateMessage> {
+        let result = block_on(self.service_handler.get_latest_result(service_name))?;
+        serde_json::from_str(&result).ok()
+    }
+
+    /// Sends the message once the given time comes around, as long as it hasn't already passed.
+    fn send_at(&self, at: DateTime<Local>, message: UpdateMessage) -> ExecutionResult {
+        match (at - Local::now()).to_std() {
+            Ok(delay) => {
+                self.send_after(delay, message);
+                ExecutionResult::Success
+            }
+            Err(_) => ExecutionResult::Failed("That time has already passed".into()),
+        }
+    }
+
+    fn send_after(&self, delay: Duration, message: UpdateMessage) {
+        let executor = self.clone();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            executor.send(message);
+        });
+    }
+
+    /// The frontend already has the latest results from every service, so all there is to do
+    /// for commands that just show those results is make sure there's something to show.
+    fn check_available(&self, service_name: String) -> ExecutionResult {
+        match block_on(self.service_handler.get_latest_result(service_name.clone())) {
+            Some(_) => ExecutionResult::Success,
+            None => {
+                ExecutionResult::Failed(format!("No {} available", service_name.to_lowercase()))
+            }
+        }
+    }
+
+    /// Makes sure there's weather to show for the location that was asked about, or for the
+    /// primary location if none was.
+    fn check_weather(&self, location: Option<&str>, date: Option<NaiveDate>) -> ExecutionResult {
+        let today = Local::now().date().naive_local();
+        match self.latest_result(WeatherService::get_service_name()) {
+            Some(UpdateMessage::Weather(reports)) => match (reports.find(location), location) {
+                (Some((_, report)), _) => match date {
+                    Some(date) if date != today && report.forecast_for(date).is_none() => {
+                        ExecutionResult::Failed(format!("No forecast for {}", date))
+                    }
+                    _ => ExecutionResult::Success,
+                },
+                (None, Some(location)) => {
+                    ExecutionResult::Failed(format!("No weather for {}", location))
+                }
+                (None, None) => ExecutionResult::Failed("No weather available".into()),
+            },
+            _ => ExecutionResult::Failed("No weather available".into()),
+        }
+    }
+
+    fn send(&self, message: UpdateMessage) {
+        let tx = self.tx.lock().unwrap().clone();
+        match tx {
+            Some(mut tx) => {
+                if block_on(tx.send(Box::new(message))).is_err() {
+                    eprintln!("Command receiver has been closed.");
+                }
+            }
+            None => eprintln!("Command transmitter not set."),
+        }
+    }
+}
+
+/// Looks a place up and adds it to the weather locations, replacing any location with the same
+/// name. The weather service picks it up the next time it checks the weather.
+fn add_location(name: &str) -> ExecutionResult {
+    let (weather_settings, language) = {
+        let settings = SETTINGS.read().unwrap();
+        (settings.weather_settings.clone(), settings.language)
+    };
+    let mut places = weather::search_bundled(name);
+    if places.is_empty() && weather_settings.online_geocoding {
+        // Looking places up online needs a runtime of its own, since we aren't running on one.
+        places = match actix_rt::Runtime::new() {
+            Ok(runtime) => {
+                runtime.block_on(weather::geocode_online(name, &weather_settings, language))
+            }
+            Err(e) => return ExecutionResult::Failed(e.to_string()),
+        };
+    }
+    let place = match places.into_iter().next() {
+        Some(place) => place,
+        None => return ExecutionResult::Failed(format!("Couldn't find {}", name)),
+    };
+
+    settings::update_settings(|settings| {
+        let weather_settings = &mut settings.weather_settings;
+        // Hang on to the location from before there were named locations.
+        if weather_settings.locations.is_empty() {
+            weather_settings.locations = weather_settings.locations();
+        }
+        let locations = &mut weather_settings.locations;
+        let primary = locations
+            .iter()
+            .any(|location| location.name == place.name && location.primary);
+        locations.retain(|location| location.name != place.name);
+        locations.push(WeatherLocation {
+            name: place.name,
+            lat: Some(place.lat),
+            lon: Some(place.lon),
+            primary,
+        });
+    });
+    ExecutionResult::Success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[test]
+    fn test_handle_utterance() {
+        let executor = CommandExecutor::new(ServiceHandler::new(), 10, Duration::from_secs(15));
+        let (tx, mut rx) = mpsc::channel(10);
+        executor.set_sender(tx);
+        let turn = |outcome| Turn {
+            intent: None,
+            outcome,
+        };
+
+        // Nothing has reported any weather yet, so there's nothing to show.
+        let now = Local::now();
+        let entry = executor.handle_utterance(
+            "what is the weather",
+            None,
+            turn(Outcome::Command(Command::Weather(None, None))),
+            &now,
+        );
+        assert_eq!(
+            entry.result,
+            ExecutionResult::Failed("No weather available".into())
+        );
+        let entry = executor.handle_utterance(
+            "what is the wether",
+            Some(-12.5),
+            Turn::not_understood(),
+            &now,
+        );
+        assert_eq!(entry.result, ExecutionResult::NotRecognized);
+        let entry = executor.handle_utterance(
+            "set a timer",
+            None,
+            turn(Outcome::Ask(Question::Duration)),
+            &now,
+        );
+        assert_eq!(entry.result, ExecutionResult::Asked(Question::Duration));
+        assert_eq!(entry.command, None);
+
+        let history = executor.history();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[1].transcript, "what is the wether");
+        assert_eq!(history[1].confidence, Some(-12.5));
+
+        // All of those should have been sent out as updates too.
+        for _ in 0..3 {
+            let update = block_on(rx.next()).unwrap();
+            assert!(serde_json::to_string(&update)
+                .unwrap()
+                .starts_with("{\"utterance\":"));
+        }
+    }
+}
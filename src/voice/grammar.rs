@@ -0,0 +1,637 @@
+use super::{
+    duration::parse_duration,
+    number::NumberParser,
+    time::{parse_day, parse_time_of_day, Day, TimeOfDay, TimeWords},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+/// All of the things we know how to understand. Each intent may come with slots holding the
+/// details of what was asked for.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IntentType {
+    Weather,
+    News,
+    Timer,
+    Alarm,
+    Reminder,
+    AddLocation,
+    // Something that only makes sense as a follow-up to what was said before, like "and
+    // tomorrow?" after asking about the weather.
+    FollowUp,
+}
+
+/// The types of values a slot in a pattern can hold.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SlotType {
+    Number,
+    Duration,
+    Time,
+    Day,
+    Location,
+    Text,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SlotValue {
+    Number(i64),
+    Duration(Duration),
+    Time(TimeOfDay),
+    Day(Day),
+    Location(String),
+    Text(String),
+}
+
+/// What was understood from an utterance along with any details pulled out of it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Intent {
+    pub intent: IntentType,
+    pub slots: HashMap<String, SlotValue>,
+}
+
+/// The grammar file as it's written on disk.
+///
+/// Patterns are made up of words that have to be said, with a few bits of syntax mixed in:
+/// - `[a|b c]` is an optional choice between "a" and "b c".
+/// - `(a|b c)` is a required choice between "a" and "b c".
+/// - `<name>` is any one of the phrases listed under "name" in the synonyms.
+/// - `$name:type` is a slot named "name" holding a value of the given type.
+#[derive(Deserialize)]
+struct GrammarFile {
+    #[serde(default)]
+    synonyms: HashMap<String, Vec<String>>,
+    intents: Vec<IntentPatterns>,
+}
+
+#[derive(Deserialize)]
+struct IntentPatterns {
+    intent: IntentType,
+    patterns: Vec<String>,
+}
+
+/// A word or slot in a pattern.
+#[derive(Clone, Debug, PartialEq)]
+enum Element {
+    Word(String),
+    Slot(String, SlotType),
+}
+
+/// A piece of a pattern as it's written.
+#[derive(Clone, Debug)]
+enum Node {
+    Element(Element),
+    Choice(Vec<Vec<Node>>),
+}
+
+pub struct Grammar {
+    // The patterns for each intent, in the order they were listed in the file.
+    patterns: Vec<(IntentType, Vec<Node>)>,
+    numbers: NumberParser,
+    times: TimeWords,
+}
+
+/// How well a pattern matched an utterance. Matches that cover more of the utterance win, then
+/// those that matched more words exactly, then those that filled more slots (so free text doesn't
+/// swallow a day that could have been its own slot), then those that started earlier.
+#[derive(Debug)]
+struct Match {
+    start: usize,
+    end: usize,
+    literals: usize,
+    slots: Vec<(String, SlotValue)>,
+}
+
+impl Match {
+    fn is_better_than(&self, other: &Match) -> bool {
+        let coverage = self.end - self.start;
+        let other_coverage = other.end - other.start;
+        (
+            coverage,
+            self.literals,
+            self.slots.len(),
+            std::cmp::Reverse(self.start),
+        ) > (
+            other_coverage,
+            other.literals,
+            other.slots.len(),
+            std::cmp::Reverse(other.start),
+        )
+    }
+}
+
+impl Grammar {
+    pub fn from_json(
+        data: &str,
+        numbers: NumberParser,
+        times: TimeWords,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let file: GrammarFile = serde_json::from_str(data)?;
+        let mut patterns = vec![];
+        for intent in &file.intents {
+            for pattern in &intent.patterns {
+                let nodes = parse_pattern(pattern, &file.synonyms)
+                    .map_err(|e| format!("bad pattern {:?}: {}", pattern, e))?;
+                patterns.push((intent.intent, nodes));
+            }
+        }
+        Ok(Self {
+            patterns,
+            numbers,
+            times,
+        })
+    }
+
+    /// The words that only show up in the patterns of a single intent, which are what tell
+    /// intents apart. Short words are left out since they're almost always filler like "a" or
+    /// "in", even when they happen to only be used by one intent. Follow-ups are made up of
+    /// filler and slots, so their words aren't counted.
+    pub fn keywords(&self) -> Vec<String> {
+        let mut intents_by_word: HashMap<&str, HashSet<IntentType>> = HashMap::new();
+        for (intent, nodes) in &self.patterns {
+            if *intent == IntentType::FollowUp {
+                continue;
+            }
+            for word in pattern_words(nodes) {
+                intents_by_word.entry(word).or_default().insert(*intent);
+            }
+        }
+        let mut keywords: Vec<String> = intents_by_word
+            .into_iter()
+            .filter(|(word, intents)| intents.len() == 1 && word.chars().count() >= 3)
+            .map(|(word, _)| word.to_string())
+            .collect();
+        keywords.sort();
+        keywords
+    }
+
+    /// Finds the intent that best matches the utterance. The match doesn't have to cover the
+    /// whole utterance, so anything said before or after the command is ignored.
+    pub fn parse(&self, utterance: &str) -> Option<Intent> {
+        let words = tokenize(utterance);
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+
+        let matcher = Matcher {
+            words: &words,
+            grammar: self,
+        };
+        let mut best: Option<(IntentType, Match)> = None;
+        for (intent, nodes) in &self.patterns {
+            for start in 0..words.len() {
+                let mut on_match = |end, literals, slots: &[(String, SlotValue)]| {
+                    // Patterns where everything is optional can match without any words at all.
+                    if end == start {
+                        return;
+                    }
+                    let found = Match {
+                        start,
+                        end,
+                        literals,
+                        slots: slots.to_vec(),
+                    };
+                    if best.as_ref().map_or(true, |(_, b)| found.is_better_than(b)) {
+                        best = Some((*intent, found));
+                    }
+                };
+                matcher.walk(nodes, None, start, 0, &mut vec![], &mut on_match);
+            }
+        }
+
+        best.map(|(intent, found)| Intent {
+            intent,
+            slots: found
+                .slots
+                .into_iter()
+                .map(|(name, value)| match value {
+                    // Places can be given by their postal code, which only looks up in digits.
+                    SlotValue::Location(location) => {
+                        let words: Vec<&str> = location.split(' ').collect();
+                        (
+                            name,
+                            SlotValue::Location(self.numbers.write_numbers(&words)),
+                        )
+                    }
+                    value => (name, value),
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Splits an utterance up into lowercase words, dropping any punctuation other than apostrophes
+/// and the colons in times like "9:30".
+pub fn tokenize(utterance: &str) -> Vec<String> {
+    utterance
+        .split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || *c == '\'' || *c == ':')
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// The rest of a pattern, left to match after the option of a choice that's being matched.
+struct Rest<'a> {
+    nodes: &'a [Node],
+    next: Option<&'a Rest<'a>>,
+}
+
+/// Called with where a match ended, how many words it matched exactly and the slots it filled.
+type OnMatch<'a> = dyn FnMut(usize, usize, &[(String, SlotValue)]) + 'a;
+
+/// Matches patterns against the words of an utterance. Choices are tried as they come up rather
+/// than expanding every phrasing of a pattern out up front, so a word that doesn't match rules out
+/// every phrasing that starts the same way at once.
+struct Matcher<'a> {
+    words: &'a [&'a str],
+    grammar: &'a Grammar,
+}
+
+impl Matcher<'_> {
+    /// Tries every way the nodes and then the rest of the pattern can match the words starting at
+    /// the given position. Each match is handed to `on_match` along with where it ended, how many
+    /// words were matched exactly and the slots that were filled. Slots try taking as many words
+    /// as they can first.
+    fn walk(
+        &self,
+        nodes: &[Node],
+        rest: Option<&Rest>,
+        pos: usize,
+        literals: usize,
+        slots: &mut Vec<(String, SlotValue)>,
+        on_match: &mut OnMatch,
+    ) {
+        let (node, tail) = match nodes.split_first() {
+            Some(split) => split,
+            None => {
+                match rest {
+                    Some(rest) => self.walk(rest.nodes, rest.next, pos, literals, slots, on_match),
+                    None => on_match(pos, literals, slots),
+                }
+                return;
+            }
+        };
+        match node {
+            Node::Element(Element::Word(word)) => {
+                if self.words.get(pos) == Some(&word.as_str()) {
+                    self.walk(tail, rest, pos + 1, literals + 1, slots, on_match);
+                }
+            }
+            Node::Element(Element::Slot(name, slot_type)) => {
+                let (numbers, times) = (&self.grammar.numbers, &self.grammar.times);
+                for end in (pos + 1..=self.words.len()).rev() {
+                    if let Some(value) = slot_type.parse(&self.words[pos..end], numbers, times) {
+                        slots.push((name.clone(), value));
+                        self.walk(tail, rest, end, literals, slots, on_match);
+                        slots.pop();
+                    }
+                }
+            }
+            Node::Choice(options) => {
+                let after = Rest {
+                    nodes: tail,
+                    next: rest,
+                };
+                for option in options {
+                    self.walk(option, Some(&after), pos, literals, slots, on_match);
+                }
+            }
+        }
+    }
+}
+
+/// Every word that can be said in a pattern.
+fn pattern_words(nodes: &[Node]) -> Vec<&str> {
+    let mut words = vec![];
+    for node in nodes {
+        match node {
+            Node::Element(Element::Word(word)) => words.push(word.as_str()),
+            Node::Element(Element::Slot(..)) => (),
+            Node::Choice(options) => {
+                for option in options {
+                    words.extend(pattern_words(option));
+                }
+            }
+        }
+    }
+    words
+}
+
+impl SlotType {
+    /// Parses a slot value out of exactly the given words.
+    fn parse(
+        &self,
+        words: &[&str],
+        numbers: &NumberParser,
+        times: &TimeWords,
+    ) -> Option<SlotValue> {
+        match self {
+            SlotType::Number => numbers
+                .parse_spoken_number(words)
+                .and_then(|number| number.as_integer())
+                .map(SlotValue::Number),
+            SlotType::Duration => parse_duration(words, numbers, times).map(SlotValue::Duration),
+            SlotType::Time => parse_time_of_day(words, numbers, times).map(SlotValue::Time),
+            SlotType::Day => parse_day(words, times).map(SlotValue::Day),
+            SlotType::Location => Some(SlotValue::Location(words.join(" "))),
+            SlotType::Text => Some(SlotValue::Text(words.join(" "))),
+        }
+    }
+}
+
+/// Parses a pattern into a tree of words, slots and choices.
+fn parse_pattern(
+    pattern: &str,
+    synonyms: &HashMap<String, Vec<String>>,
+) -> Result<Vec<Node>, String> {
+    let tokens = lex_pattern(pattern);
+    let mut pos = 0;
+    let nodes = parse_sequence(&tokens, &mut pos, synonyms)?;
+    match tokens.get(pos) {
+        Some(token) => Err(format!("unexpected {:?}", token)),
+        None => Ok(nodes),
+    }
+}
+
+fn lex_pattern(pattern: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    for c in pattern.chars() {
+        match c {
+            '[' | ']' | '(' | ')' | '|' => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                tokens.push(c.to_string());
+            }
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.extend(c.to_lowercase()),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+fn parse_sequence(
+    tokens: &[String],
+    pos: &mut usize,
+    synonyms: &HashMap<String, Vec<String>>,
+) -> Result<Vec<Node>, String> {
+    let mut nodes = vec![];
+    while let Some(token) = tokens.get(*pos) {
+        match token.as_str() {
+            "]" | ")" | "|" => break,
+            "[" | "(" => {
+                let close = if token == "[" { "]" } else { ")" };
+                *pos += 1;
+                let mut options = vec![];
+                loop {
+                    options.push(parse_sequence(tokens, pos, synonyms)?);
+                    match tokens.get(*pos).map(String::as_str) {
+                        Some("|") => *pos += 1,
+                        Some(token) if token == close => break,
+                        _ => return Err(format!("missing {:?}", close)),
+                    }
+                }
+                if close == "]" {
+                    options.push(vec![]);
+                }
+                nodes.push(Node::Choice(options));
+            }
+            token if token.starts_with('<') && token.ends_with('>') => {
+                let name = &token[1..token.len() - 1];
+                let phrases = synonyms
+                    .get(name)
+                    .ok_or_else(|| format!("no synonyms named {:?}", name))?;
+                let options = phrases
+                    .iter()
+                    .map(|phrase| {
+                        phrase
+                            .split_whitespace()
+                            .map(|word| Node::Element(Element::Word(word.to_lowercase())))
+                            .collect()
+                    })
+                    .collect();
+                nodes.push(Node::Choice(options));
+            }
+            token if token.starts_with('$') => {
+                let mut parts = token[1..].splitn(2, ':');
+                let name = parts.next().unwrap_or_default();
+                let slot_type = parts
+                    .next()
+                    .ok_or_else(|| format!("slot {:?} has no type", name))?;
+                let slot_type: SlotType = serde_json::from_value(slot_type.into())
+                    .map_err(|_| format!("unknown slot type {:?}", slot_type))?;
+                nodes.push(Node::Element(Element::Slot(name.to_string(), slot_type)));
+            }
+            word => nodes.push(Node::Element(Element::Word(word.to_string()))),
+        }
+        *pos += 1;
+    }
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Language;
+    use chrono::{NaiveTime, Weekday};
+
+    fn numbers() -> NumberParser {
+        NumberParser::init(Language::English).expect("No numbers file found")
+    }
+
+    fn times() -> TimeWords {
+        TimeWords::init(Language::English).expect("No times file found")
+    }
+
+    fn grammar(synonyms: &str, intents: &str) -> Grammar {
+        Grammar::from_json(
+            &format!("{{\"synonyms\": {}, \"intents\": {}}}", synonyms, intents),
+            numbers(),
+            times(),
+        )
+        .unwrap()
+    }
+
+    fn slots(values: Vec<(&str, SlotValue)>) -> HashMap<String, SlotValue> {
+        values
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    #[test]
+    fn test_pattern_syntax() {
+        let grammar = grammar(
+            r#"{"weather": ["weather", "forecast"]}"#,
+            r#"[{"intent": "Weather", "patterns": ["[what is|what's] the <weather> (today|now)"]}]"#,
+        );
+        for utterance in &[
+            "what is the weather today",
+            "what's the forecast now",
+            "the weather now",
+            "hey tablet what's the weather today please",
+        ] {
+            assert_eq!(
+                grammar.parse(utterance).map(|intent| intent.intent),
+                Some(IntentType::Weather),
+                "{}",
+                utterance
+            );
+        }
+        assert_eq!(grammar.parse("what is the weather"), None);
+        assert_eq!(grammar.parse("what is the news today"), None);
+    }
+
+    #[test]
+    fn test_bad_patterns() {
+        for pattern in &["[what is", "what is)", "the <nope>", "$slot", "$slot:color"] {
+            let json = format!(
+                r#"{{"intents": [{{"intent": "News", "patterns": ["{}"]}}]}}"#,
+                pattern
+            );
+            assert!(
+                Grammar::from_json(&json, numbers(), times()).is_err(),
+                "{}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_slots() {
+        let grammar = grammar(
+            "{}",
+            r#"[
+                {"intent": "Weather", "patterns": [
+                    "weather [for|on] [$day:day] [in $location:location]"
+                ]},
+                {"intent": "Timer", "patterns": [
+                    "timer [for] $duration:duration",
+                    "timer at $time:time",
+                    "timer number $number:number"
+                ]}
+            ]"#,
+        );
+        assert_eq!(
+            grammar.parse("weather on tuesday in new york city"),
+            Some(Intent {
+                intent: IntentType::Weather,
+                slots: slots(vec![
+                    ("day", SlotValue::Day(Day::Weekday(Weekday::Tue))),
+                    ("location", SlotValue::Location("new york city".into())),
+                ]),
+            })
+        );
+        assert_eq!(
+            grammar.parse("weather in paris").unwrap().slots,
+            slots(vec![("location", SlotValue::Location("paris".into()))]),
+        );
+        // Postal codes are written out in digits so they can be looked up.
+        assert_eq!(
+            grammar
+                .parse("weather in one zero one one five")
+                .unwrap()
+                .slots,
+            slots(vec![("location", SlotValue::Location("10115".into()))]),
+        );
+        assert_eq!(
+            grammar
+                .parse("timer for twenty five minutes")
+                .unwrap()
+                .slots,
+            slots(vec![(
+                "duration",
+                SlotValue::Duration(Duration::from_secs(25 * 60))
+            )]),
+        );
+        assert_eq!(
+            grammar.parse("timer at seven thirty pm").unwrap().slots,
+            slots(vec![(
+                "time",
+                SlotValue::Time(TimeOfDay {
+                    time: NaiveTime::from_hms(19, 30, 0),
+                    ambiguous: false,
+                })
+            )]),
+        );
+        assert_eq!(
+            grammar.parse("timer number four hundred").unwrap().slots,
+            slots(vec![("number", SlotValue::Number(400))]),
+        );
+
+        // The slot has to parse for the pattern to match at all.
+        assert_eq!(grammar.parse("timer for twenty five bananas"), None);
+    }
+
+    #[test]
+    fn test_best_match() {
+        let grammar = grammar(
+            "{}",
+            r#"[
+                {"intent": "Weather", "patterns": ["[the] weather"]},
+                {"intent": "News", "patterns": ["[what's] [the] news [about $topic:text]"]}
+            ]"#,
+        );
+
+        // The news pattern covers the whole thing, so it wins out over the weather in it.
+        assert_eq!(
+            grammar.parse("what's the news about the weather"),
+            Some(Intent {
+                intent: IntentType::News,
+                slots: slots(vec![("topic", SlotValue::Text("the weather".into()))]),
+            })
+        );
+
+        // When both match just as well, the one said first wins.
+        let intent = grammar.parse("weather news").unwrap().intent;
+        assert_eq!(intent, IntentType::Weather);
+        let intent = grammar.parse("news weather").unwrap().intent;
+        assert_eq!(intent, IntentType::News);
+    }
+
+    #[test]
+    fn test_keywords() {
+        let grammar = grammar(
+            r#"{"weather": ["weather", "forecast"]}"#,
+            r#"[
+                {"intent": "Weather", "patterns": ["[what's] [the] <weather> [in $location:location]"]},
+                {"intent": "News", "patterns": ["[what's] [the] news", "read me the headlines"]},
+                {"intent": "Timer", "patterns": ["set a timer"]}
+            ]"#,
+        );
+        assert_eq!(
+            grammar.keywords(),
+            vec![
+                "forecast",
+                "headlines",
+                "news",
+                "read",
+                "set",
+                "timer",
+                "weather"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("What's the Weather, tablet?"),
+            vec!["what's", "the", "weather", "tablet"]
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use super::{command::Command, executor::ExecutionResult};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Everything we know about a single thing the tablet heard.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryEntry {
+    pub timestamp: DateTime<Local>,
+    pub transcript: String,
+    // How confident the speech recognition was in the transcript, if it told us.
+    pub confidence: Option<f64>,
+    pub command: Option<Command>,
+    pub result: ExecutionResult,
+    // What the tablet said back, if anything.
+    #[serde(default)]
+    pub reply: Option<String>,
+}
+
+/// A rolling history of recognized utterances. Once the history is full, the oldest entries are
+/// dropped to make room for new ones.
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, entry: HistoryEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Returns all entries in the history, oldest first.
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(transcript: &str) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: Local::now(),
+            transcript: transcript.into(),
+            confidence: None,
+            command: None,
+            result: ExecutionResult::NotRecognized,
+            reply: None,
+        }
+    }
+
+    #[test]
+    fn test_rolling_history() {
+        let mut history = History::new(3);
+        for transcript in &["one", "two", "three", "four", "five"] {
+            history.push(entry(transcript));
+        }
+        let transcripts: Vec<String> = history
+            .entries()
+            .into_iter()
+            .map(|entry| entry.transcript)
+            .collect();
+        assert_eq!(transcripts, vec!["three", "four", "five"]);
+
+        let mut history = History::new(0);
+        history.push(entry("one"));
+        assert!(history.entries().is_empty());
+    }
+}
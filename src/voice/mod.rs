@@ -1,45 +1,68 @@
 use crate::{
     service::Service,
-    settings::{Language, SETTINGS},
+    settings::{Language, ModelPaths, RecognitionSettings, VadSettings, SETTINGS},
 };
 use async_trait::async_trait;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use crossbeam::channel::{unbounded, Receiver};
+use audio::{AudioInput, AudioSource, WavSource};
+use chrono::{DateTime, Local};
+use command::{Command, CommandParser};
+use debug::{UtteranceRecord, UtteranceRecorder, UtteranceTimings};
+use dialog::Dialog;
 use erased_serde::Serialize;
 use futures::channel::mpsc;
+use grammar::Intent;
+use recognition::{Recognition, Recognizer};
 use std::{
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
-    thread,
+    time::{Duration, Instant, SystemTime},
 };
 use tokio::task;
-use webrtc_vad::Vad;
+use vad::UtteranceDetector;
 
+pub mod audio;
 mod command;
+mod debug;
+mod dialog;
+mod duration;
+mod executor;
+mod grammar;
+mod history;
 mod number;
+mod recognition;
+mod reply;
+mod time;
+pub mod tts;
+pub mod vad;
 
-// Wrap up the Deepspeech stream so we can send it to our thread.
-struct Model(deepspeech::Model);
-unsafe impl Send for Model {}
+pub use executor::CommandExecutor;
+pub use history::HistoryEntry;
+
+/// How often the settings and intents file are checked for changes while listening. Checking
+/// means taking the settings lock and looking at the intents file, which is too much to do for
+/// every chunk of audio.
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct CommandService {
-    tx: Option<mpsc::Sender<Box<dyn Serialize + Send + Sync>>>,
+    executor: CommandExecutor,
 }
 
 impl CommandService {
-    pub fn new() -> Self {
-        Self { tx: None }
+    pub fn new(executor: CommandExecutor) -> Self {
+        Self { executor }
     }
 }
 
 #[async_trait]
 impl Service for CommandService {
     fn set_sender(&mut self, tx: mpsc::Sender<Box<dyn Serialize + Send + Sync>>) {
-        self.tx = Some(tx);
+        self.executor.set_sender(tx);
     }
 
     async fn start_service(&mut self) {
+        let executor = self.executor.clone();
         task::spawn_blocking(move || {
-            listen().expect("Error handling voice");
+            listen(executor).expect("Error handling voice");
         })
         .await
         .unwrap();
@@ -50,167 +73,513 @@ impl Service for CommandService {
     }
 }
 
-fn listen() -> Result<(), Box<dyn std::error::Error>> {
-    // Configure the microphone for listening.
-    let host = cpal::default_host();
-    let device = host
-        .default_output_device()
-        .ok_or("no output device detected")?;
-    let (tx, rx) = unbounded();
-
-    // Once we know we have a microphone, load up the Deepspeech models. We need the expected
-    // sample rate for our model so we can confirm that the default microphone in this system
-    // can support it.
-    let model_path;
-    let scorer_path;
-    let language;
-    {
+/// Everything that the voice pipeline is built from. When any of it changes, the pipeline has to
+/// be rebuilt.
+#[derive(PartialEq)]
+struct PipelineConfig {
+    language: Language,
+    models: ModelPaths,
+    audio_input: AudioInput,
+    device_name: Option<String>,
+    vad_settings: VadSettings,
+    recognition_settings: RecognitionSettings,
+    debug_recording_path: Option<PathBuf>,
+    // The names of things the tablet knows about, which get boosted as hot-words.
+    names: Vec<String>,
+    // When the intents file was last changed, so edits to it get picked up without a restart.
+    intents_modified: Option<SystemTime>,
+}
+
+impl PipelineConfig {
+    fn current() -> Self {
         let settings = SETTINGS.read().unwrap();
-        model_path = settings.voice_settings.model_path.clone();
-        scorer_path = settings.voice_settings.scorer_path.clone();
-        language = settings.language;
+        let voice_settings = &settings.voice_settings;
+        let mut names: Vec<String> = settings
+            .news_settings
+            .news_sources
+            .iter()
+            .map(|source| source.name())
+            .chain(
+                settings
+                    .weather_settings
+                    .locations()
+                    .into_iter()
+                    .map(|location| location.name),
+            )
+            .collect();
+        names.sort();
+        let intents_modified = std::fs::metadata(CommandParser::intents_path(settings.language))
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        Self {
+            language: settings.language,
+            models: voice_settings.model_paths(settings.language),
+            audio_input: voice_settings.audio_input.clone(),
+            device_name: voice_settings.device_name.clone(),
+            vad_settings: voice_settings.vad_settings.clone(),
+            recognition_settings: voice_settings.recognition_settings.clone(),
+            debug_recording_path: voice_settings.debug_recording_path.clone(),
+            names,
+            intents_modified,
+        }
     }
-    let mut model = deepspeech::Model::load_from_files(&model_path)?;
-    model.enable_external_scorer(&scorer_path)?;
-    let expected_sample_rate = model.get_sample_rate() as u32;
-
-    // The Rust bindings of Deepspeech only support mono i16 samples according to the
-    // documentation for the model.
-    let mut supported_configs = device.supported_input_configs()?;
-    let config = supported_configs
-        .find(|c| c.channels() == 1 && c.sample_format() == cpal::SampleFormat::I16)
-        .ok_or("no supported format for microphone")?
-        .with_sample_rate(cpal::SampleRate(expected_sample_rate))
-        .config();
-    println!("config: {:?}", config);
-    let model = Arc::new(Mutex::new(Model(model)));
-
-    // Start the input stream. In order to avoid issues with latency processing the samples, we
-    // have the stream just send the data out across a channel versus doing the processing in the
-    // callback itself.
-    let input_stream = device.build_input_stream(
-        &config,
-        move |data: &[i16], _: &cpal::InputCallbackInfo| {
-            tx.send(data.to_vec()).expect("Couldn't send audio data");
-        },
-        move |err| {
-            eprintln!("Error collecting data: {}", err);
-        },
-    )?;
-    input_stream.play()?;
+}
+
+fn listen(executor: CommandExecutor) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = PipelineConfig::current();
+
+    // Open up our audio first so we know we have something to listen to before we bother loading
+    // up the Deepspeech models.
+    let mut source = config.audio_input.open(config.device_name.as_deref())?;
+    let mut model = load_model(&config.models.model_path, &config.models.scorer_path)?;
+    loop {
+        let command_parser = CommandParser::init(config.language)?;
+        let hot_words = recognition::hot_words(
+            &command_parser.keywords(),
+            &config.names,
+            &config.recognition_settings,
+        );
+        set_hot_words(&mut model, &hot_words);
+        let recognizer = Recognizer::new(
+            command_parser,
+            config.recognition_settings.clone(),
+            executor.dialog(),
+        );
+        let mut last_checked = Instant::now();
+        process_audio(
+            &mut model,
+            source.as_mut(),
+            &recognizer,
+            &config.vad_settings,
+            config.debug_recording_path.as_deref(),
+            || {
+                if last_checked.elapsed() < CONFIG_CHECK_INTERVAL {
+                    return false;
+                }
+                last_checked = Instant::now();
+                PipelineConfig::current() != config
+            },
+            tts::is_speaking,
+            |recognition| {
+                let entry = executor.handle_utterance(
+                    &recognition.transcript,
+                    Some(recognition.confidence),
+                    recognition.turn,
+                    &Local::now(),
+                );
+                println!(
+                    "Heard {:?} ({:.1}): {:?} ({:?})",
+                    entry.transcript, recognition.confidence, entry.command, entry.result
+                );
+            },
+        );
+
+        // If what the pipeline was built from changed out from under it, rebuild whatever needs
+        // rebuilding. Otherwise the audio source just ran dry and we're done.
+        let new_config = PipelineConfig::current();
+        if new_config == config {
+            return Ok(());
+        }
+        println!("Voice settings changed, reloading the voice pipeline");
+        if new_config.audio_input != config.audio_input
+            || new_config.device_name != config.device_name
+        {
+            source = new_config
+                .audio_input
+                .open(new_config.device_name.as_deref())?;
+        }
+        if new_config.models != config.models {
+            model = load_model(
+                &new_config.models.model_path,
+                &new_config.models.scorer_path,
+            )?;
+        }
+        config = new_config;
+    }
+}
 
-    let handle = thread::spawn(move || {
-        process_audio(model, rx, language);
-    });
-    handle.join().unwrap();
-    Ok(())
+/// Replaces all of the model's hot-words with the given words and boosts.
+fn set_hot_words(model: &mut deepspeech::Model, hot_words: &[(String, f32)]) {
+    if let Err(e) = model.clear_hot_words() {
+        eprintln!("Couldn't clear hot-words: {}", e);
+    }
+    for (word, boost) in hot_words {
+        if let Err(e) = model.add_hot_word(word, *boost) {
+            eprintln!("Couldn't add hot-word {:?}: {}", word, e);
+        }
+    }
 }
 
-/// Receive, process, and transcribe received audio from the microphone.
-///
-fn process_audio(model: Arc<Mutex<Model>>, rx: Receiver<Vec<i16>>, language: Language) {
-    // A constant that keeps track of the number of samples we're going to hold on to.
-    const SAMPLE_HISTORY_LEN: u32 = 3;
+/// What happened when a typed command was run.
+#[derive(serde::Serialize)]
+pub struct TextCommandResult {
+    // What the text was understood as, before it was turned into a command.
+    pub intent: Option<Intent>,
+    #[serde(flatten)]
+    pub entry: HistoryEntry,
+}
 
-    // A constant that keeps track of how long of silence do we wait before attempting to
-    // transcribe audio with Deepspeech.
-    const NUM_SILENT_SAMPLES: u32 = 3;
+/// Runs typed text through the same parsing and execution as a spoken command. Like a spoken
+/// command, it ends up in the history and takes part in the conversation, so typed text can
+/// answer a question that was asked out loud and the other way around. Days and times like
+/// "tomorrow" are worked out relative to `now`.
+pub fn run_text_command(
+    executor: &CommandExecutor,
+    text: &str,
+    language: Language,
+    now: &DateTime<Local>,
+) -> Result<TextCommandResult, Box<dyn std::error::Error>> {
+    let command_parser = CommandParser::init(language)?;
+    let intent = command_parser.parse_intent(text);
+    let turn = executor
+        .dialog()
+        .lock()
+        .unwrap()
+        .interpret(intent.clone(), now);
+    let entry = executor.handle_utterance(text, None, turn, now);
+    Ok(TextCommandResult { intent, entry })
+}
 
-    let command_parser =
-        command::CommandParser::init(language).expect("Can't load the command file");
+fn load_model(
+    model_path: &Path,
+    scorer_path: &Path,
+) -> Result<deepspeech::Model, Box<dyn std::error::Error>> {
+    let mut model = deepspeech::Model::load_from_files(model_path)?;
+    model.enable_external_scorer(scorer_path)?;
 
-    // Since this thread owns the model and will be using it exclusively, we'll just lock the mutex
-    // at the beginning and don't bother letting go.
-    let model = &mut model.lock().unwrap().0;
+    // All of our audio sources hand out 16kHz audio, so make sure that's what the model expects.
+    let model_sample_rate = model.get_sample_rate() as u32;
+    if model_sample_rate != audio::SAMPLE_RATE {
+        return Err(format!("unsupported model sample rate: {}", model_sample_rate).into());
+    }
+    Ok(model)
+}
 
+/// Receive, process, and transcribe audio from the given source until it runs dry or
+/// `should_stop` says to stop. Audio is thrown away while `is_muted` says to, along with any
+/// utterance that was in progress, so the tablet doesn't hear its own replies. Every utterance
+/// that Deepspeech manages to transcribe is handed to `on_utterance` along with the command it was
+/// recognized as, if any. If a debug recording path is given, every utterance is also saved there
+/// along with everything we decided about it.
+#[allow(clippy::too_many_arguments)]
+fn process_audio<S, M, F>(
+    model: &mut deepspeech::Model,
+    source: &mut dyn AudioSource,
+    recognizer: &Recognizer,
+    vad_settings: &VadSettings,
+    debug_recording_path: Option<&Path>,
+    mut should_stop: S,
+    is_muted: M,
+    mut on_utterance: F,
+) where
+    S: FnMut() -> bool,
+    M: Fn() -> bool,
+    F: FnMut(Recognition),
+{
+    let mut detector =
+        UtteranceDetector::new(vad::webrtc_classifier(vad_settings), vad_settings.clone());
+    let mut recorder = debug_recording_path.map(UtteranceRecorder::new);
     let mut stream = None;
 
-    // We know that Deepspeech only works with 16kHz data so we hard code it here.
-    let mut vad = Vad::new_with_rate_and_mode(
-        webrtc_vad::SampleRate::Rate16kHz,
-        webrtc_vad::VadMode::Aggressive,
-    );
+    while let Some(samps) = source.next_chunk() {
+        if should_stop() {
+            break;
+        }
+        if is_muted() {
+            detector.reset();
+            if stream.take().is_some() {
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.discard();
+                }
+            }
+            continue;
+        }
+        let result = detector.process(&samps);
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record_chunk(samps.len(), &result);
+        }
 
-    let mut silent_count = 0;
-    let mut speech_found = false;
+        // If speech has been found at all, start dumping data into the stream...even the silence.
+        // This results in a much better parse by Deepspeech.
+        if !result.audio.is_empty() {
+            if stream.is_none() {
+                stream = Some(model.create_stream().expect("couldn't create stream"));
+            }
+            let stream = stream.as_mut().unwrap();
+            stream.feed_audio(&result.audio);
 
-    let silence_level = 1000;
-    let mut prev_sample = vec![];
-    let mut num_samples = 0;
+            if let Some(recorder) = recorder.as_mut() {
+                if recorder.intermediate_due() {
+                    if let Ok(val) = stream.intermediate_decode() {
+                        recorder.record_intermediate(val);
+                    }
+                }
+            }
+        }
 
-    rx.iter().for_each(move |mut samps| {
-        // Since we're dropping the stream after we finish a decode, we need to check each
-        // iteration to see if the stream needs to be re-created.
-        if stream.is_none() {
-            stream = Some(model.create_stream().expect("couldn't create stream"));
+        if result.end_of_utterance {
+            finish_utterance(
+                stream.take(),
+                recognizer,
+                recorder.as_mut(),
+                &mut on_utterance,
+            );
         }
+    }
 
-        // Since short words seem to be missed here, we look for _any_ detection in our stream to
-        // decide whether or not this gets placed in the stream for processing.
-        let (min_amplitude, max_amplitude) = samps
-            .iter()
-            .fold((std::i16::MAX, std::i16::MIN), |acc, samp| {
-                (*samp.min(&acc.0), *samp.max(&acc.1))
-            });
-
-        // Don't bother trying voice activity detection unless we're over some amplitude.
-        if min_amplitude > -silence_level && max_amplitude < silence_level && !speech_found {
-            // Though we aren't going any voice activity detection, Deepspeech will very frequently
-            // drop the first word in a phrase if there's no silence preceeding it. To that end, we
-            // save off up to SAMPLE_HISTORY_LEN number of samples and append when we start picking
-            // up something worth trying voice detection on.
-            if num_samples == 0 {
-                prev_sample = samps.to_vec();
-                num_samples += 1;
-            } else if num_samples <= SAMPLE_HISTORY_LEN {
-                prev_sample.append(&mut samps);
-                num_samples += 1;
-            } else {
-                prev_sample.drain(0..samps.len());
-                prev_sample.append(&mut samps);
-            }
-            if silent_count <= NUM_SILENT_SAMPLES {
-                silent_count += 1;
+    // Files and pipes can end in the middle of speech, so give whatever is left in the stream a
+    // final decode rather than dropping it on the floor.
+    finish_utterance(
+        stream.take(),
+        recognizer,
+        recorder.as_mut(),
+        &mut on_utterance,
+    );
+}
+
+/// Does the final decode of an utterance and figures out what command it was.
+fn finish_utterance<F>(
+    stream: Option<deepspeech::Stream>,
+    recognizer: &Recognizer,
+    recorder: Option<&mut UtteranceRecorder>,
+    on_utterance: &mut F,
+) where
+    F: FnMut(Recognition),
+{
+    let stream = match stream {
+        Some(stream) => stream,
+        None => return,
+    };
+
+    let decode_start = Instant::now();
+    let candidates = match stream.finish_with_metadata(recognizer.n_best()) {
+        Ok(metadata) => recognition::candidates(&metadata),
+        Err(e) => {
+            eprintln!("Couldn't decode utterance: {}", e);
+            vec![]
+        }
+    };
+    let decode_ms = decode_start.elapsed().as_millis() as u64;
+
+    let parse_start = Instant::now();
+    let recognition = recognizer.choose(&candidates);
+    let parse_us = parse_start.elapsed().as_micros() as u64;
+
+    let recognition = match recognition {
+        Some(recognition) => recognition,
+        None => {
+            if let Some(recorder) = recorder {
+                recorder.discard();
             }
+            return;
+        }
+    };
+
+    if let Some(recorder) = recorder {
+        let timings = UtteranceTimings {
+            decode_ms,
+            parse_us,
+            ..Default::default()
+        };
+        match recorder.finish(&recognition, candidates, timings) {
+            Ok(path) => println!("Saved utterance to {:?}", path),
+            Err(e) => eprintln!("Couldn't save utterance: {}", e),
+        }
+    }
+    on_utterance(recognition);
+}
+
+/// What happened when a recorded utterance was run back through the pipeline.
+struct ReplayResult {
+    transcripts: Vec<String>,
+    commands: Vec<Option<Command>>,
+}
+
+/// Runs a recorded utterance through the pipeline, moving the conversation along with whatever
+/// was heard so that recordings of a conversation can be replayed in order.
+fn replay_utterance(
+    model: &mut deepspeech::Model,
+    recognizer: &Recognizer,
+    dialog: &Mutex<Dialog>,
+    vad_settings: &VadSettings,
+    wav_path: &Path,
+) -> Result<ReplayResult, Box<dyn std::error::Error>> {
+    let mut source = WavSource::open(wav_path)?;
+    let mut result = ReplayResult {
+        transcripts: vec![],
+        commands: vec![],
+    };
+    process_audio(
+        model,
+        &mut source,
+        recognizer,
+        vad_settings,
+        None,
+        || false,
+        || false,
+        |recognition| {
+            dialog
+                .lock()
+                .unwrap()
+                .advance(&recognition.turn, &Local::now());
+            result.transcripts.push(recognition.transcript);
+            result.commands.push(recognition.turn.command());
+        },
+    );
+    Ok(result)
+}
+
+/// Runs recorded utterances back through the voice pipeline using the current settings. Any
+/// directories are searched for WAV files, and everything is replayed as one conversation.
+/// Utterances that were saved with a debug recording are checked against the command they were
+/// originally parsed to; returns whether all of them still parse the same way.
+pub fn replay(paths: &[PathBuf]) -> Result<bool, Box<dyn std::error::Error>> {
+    let config = PipelineConfig::current();
+    let vad_settings = config.vad_settings;
+    let mut model = load_model(&config.models.model_path, &config.models.scorer_path)?;
+    let command_parser = CommandParser::init(config.language)?;
+    let hot_words = recognition::hot_words(
+        &command_parser.keywords(),
+        &config.names,
+        &config.recognition_settings,
+    );
+    set_hot_words(&mut model, &hot_words);
+    let dialog_timeout = SETTINGS.read().unwrap().voice_settings.dialog_timeout_secs;
+    let dialog = Arc::new(Mutex::new(Dialog::new(Duration::from_secs(dialog_timeout))));
+    let recognizer = Recognizer::new(command_parser, config.recognition_settings, dialog.clone());
+
+    let mut wav_paths = vec![];
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension() == Some("wav".as_ref()))
+                .collect();
+            entries.sort();
+            wav_paths.append(&mut entries);
         } else {
-            prev_sample.append(&mut samps);
-            let is_speech = prev_sample
-                .chunks_exact(160)
-                .any(|frame| vad.is_voice_segment(frame) == Ok(true));
-
-            if is_speech {
-                silent_count = 0;
-                speech_found = true;
-            } else if silent_count <= NUM_SILENT_SAMPLES {
-                silent_count += 1;
+            wav_paths.push(path.clone());
+        }
+    }
+
+    let mut all_matched = true;
+    for wav_path in wav_paths {
+        let result = replay_utterance(&mut model, &recognizer, &dialog, &vad_settings, &wav_path)?;
+        match UtteranceRecord::load_for(&wav_path) {
+            Ok(record) if result.commands == [record.command.clone()] => {
+                println!("PASS {:?}: {:?}", wav_path, result.transcripts);
             }
+            Ok(record) => {
+                all_matched = false;
+                println!(
+                    "FAIL {:?}: heard {:?} as {:?}, originally heard {:?} as {:?}",
+                    wav_path,
+                    result.transcripts,
+                    result.commands,
+                    record.transcript,
+                    record.command
+                );
+            }
+            Err(_) => println!(
+                "{:?}: heard {:?} as {:?}",
+                wav_path, result.transcripts, result.commands
+            ),
         }
+    }
+    Ok(all_matched)
+}
 
-        // If speech has been found at all, start dumping data into the stream...even the silence.
-        // This results in a much better parse by Deepspeech.
-        if speech_found {
-            stream.as_mut().unwrap().feed_audio(&prev_sample);
-            prev_sample.clear();
-            num_samples = 0;
-        }
-
-        // The silent count here is a magic number we're using that we found mostly through
-        // experimentation. At some point we need to figure out a better way to handle this.
-        if silent_count >= NUM_SILENT_SAMPLES && speech_found {
-            let mut stream_taken = stream.take().unwrap();
-
-            // Due to the rather shocking false positive rate of webrtc-vad we're running into,
-            // We have this as a stopgap. Do an intermediate decode and if Deepspeech says we've
-            // got nothing, just continue collecting data into the stream.
-            if let Ok(val) = stream_taken.intermediate_decode() {
-                if val != String::new() {
-                    println!("Decoded text: {:?}", val);
-                    println!("Command: {:?}", command_parser.parse(&val));
-                    drop(stream_taken);
-                    silent_count = 0;
-                }
-                speech_found = false;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::ServiceHandler;
+    use chrono::{NaiveDate, TimeZone};
+    use executor::ExecutionResult;
+    use grammar::IntentType;
+    use std::{env, fs};
+
+    #[test]
+    fn test_run_text_command() {
+        let executor = CommandExecutor::new(ServiceHandler::new(), 10, Duration::from_secs(15));
+        // Friday the 15th of March 2024 at 8:15am.
+        let now = Local.ymd(2024, 3, 15).and_hms(8, 15, 0);
+        let run = |text| run_text_command(&executor, text, Language::English, &now).unwrap();
+
+        let result = run("what's the weather tomorrow");
+        assert_eq!(result.intent.unwrap().intent, IntentType::Weather);
+        let tomorrow = NaiveDate::from_ymd(2024, 3, 16);
+        assert_eq!(
+            result.entry.command,
+            Some(Command::Weather(None, Some(tomorrow)))
+        );
+        assert_eq!(
+            result.entry.result,
+            ExecutionResult::Failed("No weather available".into())
+        );
+
+        let result = run("sing me a song");
+        assert!(result.intent.is_none());
+        assert_eq!(result.entry.result, ExecutionResult::NotRecognized);
+
+        // Numbers too big to work with aren't numbers at all.
+        let result = run("set a timer for nine hundred billion billion billion seconds");
+        assert_eq!(result.entry.command, None);
+        assert_eq!(
+            result.entry.result,
+            ExecutionResult::Asked(dialog::Question::Duration)
+        );
+
+        // Typed commands can carry on a conversation too.
+        let result = run("set a timer");
+        assert_eq!(
+            result.entry.result,
+            ExecutionResult::Asked(dialog::Question::Duration)
+        );
+        let result = run("for ten seconds");
+        assert_eq!(result.intent.unwrap().intent, IntentType::FollowUp);
+        assert_eq!(
+            result.entry.command,
+            Some(Command::Timer(Duration::from_secs(10)))
+        );
+
+        // Typed commands end up in the history just like spoken ones.
+        assert_eq!(executor.history().len(), 5);
+    }
+
+    // Runs every recorded utterance in $VOICE_TEST_UTTERANCES through the whole voice pipeline
+    // and checks it against the command in the debug record next to it. This needs real
+    // Deepspeech models, so point $DEEPSPEECH_MODEL and $DEEPSPEECH_SCORER at them and run with
+    // `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_recorded_utterances() {
+        let model_path = env::var("DEEPSPEECH_MODEL").expect("DEEPSPEECH_MODEL not set");
+        let scorer_path = env::var("DEEPSPEECH_SCORER").expect("DEEPSPEECH_SCORER not set");
+        let utterances = env::var("VOICE_TEST_UTTERANCES").expect("VOICE_TEST_UTTERANCES not set");
+
+        let mut model = load_model(model_path.as_ref(), scorer_path.as_ref()).unwrap();
+        let dialog = Arc::new(Mutex::new(Dialog::new(Duration::from_secs(15))));
+        let recognizer = Recognizer::new(
+            CommandParser::init(Language::English).unwrap(),
+            RecognitionSettings::default(),
+            dialog.clone(),
+        );
+
+        for entry in fs::read_dir(utterances).unwrap() {
+            let wav_path = entry.unwrap().path();
+            if wav_path.extension() != Some("wav".as_ref()) {
+                continue;
             }
+            let record = UtteranceRecord::load_for(&wav_path).unwrap();
+            let result = replay_utterance(
+                &mut model,
+                &recognizer,
+                &dialog,
+                &VadSettings::default(),
+                &wav_path,
+            )
+            .unwrap();
+            assert_eq!(result.commands, vec![record.command], "{:?}", wav_path);
         }
-    });
+    }
 }
@@ -1,273 +1,890 @@
-use lazy_static::lazy_static;
-use std::collections::HashMap;
+use crate::settings::Language;
+use serde::Deserialize;
+use std::{collections::HashMap, fs::File, io::Read, ops::Range};
 
-lazy_static! {
+/// How a number was said.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumberKind {
+    Cardinal,
+    // "third" or "twenty first".
+    Ordinal,
+    // "two point five".
+    Decimal,
+    // "three quarters" or "one and a half".
+    Fraction,
+    // "nineteen eighty four", which would be nonsense read as an ordinary number.
+    Year,
+}
+
+/// A number that was said, along with which of the words it was said with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpokenNumber {
+    pub value: f64,
+    pub kind: NumberKind,
+    // The range of words the number was made up of.
+    pub span: Range<usize>,
+}
+
+impl SpokenNumber {
+    /// The number as an integer, if it is one.
+    pub fn as_integer(&self) -> Option<i64> {
+        if self.value.fract() == 0.0 {
+            Some(self.value as i64)
+        } else {
+            None
+        }
+    }
+}
+
+/// Turns spoken numbers into actual numbers. All of the words for numbers in a language are
+/// loaded from the numbers_{LANG}.json file.
+#[derive(Deserialize)]
+pub struct NumberParser {
     // Holds a map for all unique numbers in the given language. This should hold all possible
     // prefixes for a number.
-    //
-    // TODO: In the future, this should be a file someplace that we load in based on the given
-    // language akin to the commands_{LANG}.json file.
-    static ref DIGIT_MAP: HashMap<String, i64> = {
-        let mut m = HashMap::new();
-        m.insert("zero".into(), 0);
-        m.insert("one".into(), 1);
-        m.insert("two".into(), 2);
-        m.insert("three".into(), 3);
-        m.insert("four".into(), 4);
-        m.insert("five".into(), 5);
-        m.insert("six".into(), 6);
-        m.insert("seven".into(), 7);
-        m.insert("eight".into(), 8);
-        m.insert("nine".into(), 9);
-        m
-    };
-
-    static ref NUMBER_MAP: HashMap<String, i64> = {
-        let mut m = HashMap::new();
-        m.insert("ten".into(), 10);
-        m.insert("eleven".into(), 11);
-        m.insert("twelve".into(), 12);
-        m.insert("thirteen".into(), 13);
-        m.insert("fourteen".into(), 14);
-        m.insert("fifteen".into(), 15);
-        m.insert("sixteen".into(), 16);
-        m.insert("seventeen".into(), 17);
-        m.insert("eighteen".into(), 18);
-        m.insert("ninteen".into(), 19);
-        m
-    };
-
+    digits: HashMap<String, i64>,
+    numbers: HashMap<String, i64>,
     // These are numbers that can chain into another number. We need to handle these cases
     // specifically when we're doing numbers that chain in between magnitudes, such as
     // "five hundred thirty six thousand".
-    static ref CHAINS_MAP: HashMap<String, i64> = {
-        let mut m = HashMap::new();
-        m.insert("twenty".into(), 20);
-        m.insert("thirty".into(), 30);
-        m.insert("fourty".into(), 40);
-        m.insert("fifty".into(), 50);
-        m.insert("sixty".into(), 60);
-        m.insert("seventy".into(), 70);
-        m.insert("eighty".into(), 80);
-        m.insert("ninety".into(), 90);
-        m
-    };
-
-    static ref MAGNITUDE_MAP: HashMap<String, i64> = {
-        let mut m = HashMap::new();
-        m.insert("hundred".into(), 100);
-        m.insert("thousand".into(), 1e3 as i64);
-        m.insert("million".into(), 1e6 as i64);
-        m.insert("billion".into(), 1e9 as i64);
-        m
-    };
-
-    // A vector of words that can be used to chain numbers together. In English, there's only "and"
-    // but I'm not sure if there are other languages that have more than this. Hence, why this is
-    // going to be a vector.
-    static ref NUMBER_CHAIN_WORDS: Vec<String> = vec!["and".into()];
+    chains: HashMap<String, i64>,
+    magnitudes: HashMap<String, i64>,
+    // Words that can be used to chain numbers together, like "and" in English or the "und" in the
+    // German "zweiundzwanzig".
+    chain_words: Vec<String>,
+    // Words that stand in for "one", like the "a" in "a hundred" or "a half".
+    #[serde(default)]
+    articles: Vec<String>,
+    // Maps ordinals to the number they're the ordinal of. Only the last word of an ordinal is
+    // different ("twenty first"), so swapping it out lets us parse the rest like any other
+    // number.
+    #[serde(default)]
+    ordinals: HashMap<String, String>,
+    // Endings that turn any number into an ordinal, for languages where ordinals are regular
+    // (the German "zwanzigste"). Irregular ordinals still go in the ordinals map.
+    #[serde(default)]
+    ordinal_suffixes: Vec<String>,
+    // Words for fractions along with what they divide by.
+    #[serde(default)]
+    fractions: HashMap<String, i64>,
+    // Whether any ordinal can be used as a fraction, like the "thirds" in "two thirds". Ordinals
+    // listed as exceptions are left alone, such as "a second" which is a unit of time.
+    #[serde(default)]
+    ordinal_fractions: bool,
+    #[serde(default)]
+    ordinal_fraction_exceptions: Vec<String>,
+    #[serde(default)]
+    negative_words: Vec<String>,
+    #[serde(default)]
+    decimal_point_words: Vec<String>,
+    // Words that stand in for zero when reading out digits, like the "oh" in "nineteen oh five".
+    #[serde(default)]
+    zero_words: Vec<String>,
+    // Whether years are said as two pairs of digits, like "nineteen eighty four".
+    #[serde(default)]
+    years_in_pairs: bool,
+    // Whether numbers are written as one long word made up of smaller ones, like the German
+    // "dreihundertzweiundzwanzig".
+    #[serde(default)]
+    compound_words: bool,
 }
 
-/// Runs through a voice command and translates any numbers said into an actual number. This
-/// implementation is likely going to be heavily biases towards English as I'm not familiar with
-/// the number structure of every language.
-pub fn parse_number_from_voice(command: &str) -> Option<i64> {
-    let mut total = None;
-    let mut curr_number = None;
-    let mut found_prefix = false;
-    let mut number_chain = false;
-
-    let mut last_prefix = None;
-    let mut last_magnitude = None;
-    let mut last_number_was_digit = false;
-
-    for word in command.split_whitespace() {
-        if let Some(val) = DIGIT_MAP.get(word) {
-            // If we have two digits in a row, that very likely means that the user is saying each
-            // individual digit. We're going to parse it as such since otherwise it doesn't make
-            // sense.
-            if last_number_was_digit {
-                return parse_digits_from_command(command);
+impl NumberParser {
+    pub fn init(language: Language) -> Result<Self, Box<dyn std::error::Error>> {
+        let number_file_path = format!("numbers_{}.json", language.code());
+        let mut number_file = File::open(number_file_path)?;
+        let mut data = String::new();
+        number_file.read_to_string(&mut data)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Whether the word stands in for "one", like "a" in English.
+    pub fn is_article(&self, word: &str) -> bool {
+        self.articles.iter().any(|article| article == word)
+    }
+
+    /// Whether the word stands in for zero when reading out digits, like "oh" in English.
+    pub fn is_zero_word(&self, word: &str) -> bool {
+        self.zero_words.iter().any(|zero| zero == word)
+    }
+
+    /// Whether the word is used to join the parts of a number together, like "and" in English.
+    pub fn is_chain_word(&self, word: &str) -> bool {
+        self.chain_words.iter().any(|chain_word| chain_word == word)
+    }
+
+    /// Whether the word is one of the basic building blocks of numbers.
+    fn is_vocabulary_word(&self, word: &str) -> bool {
+        self.digits.contains_key(word)
+            || self.numbers.contains_key(word)
+            || self.chains.contains_key(word)
+            || self.magnitudes.contains_key(word)
+            || self.chain_words.iter().any(|chain_word| chain_word == word)
+    }
+
+    /// Breaks the words up into the pieces the parser understands. Hyphenated numbers like
+    /// "twenty-one" are split apart, and so are compound numbers in languages that have them.
+    fn split_words<'a, I: IntoIterator<Item = &'a str>>(&self, words: I) -> Vec<String> {
+        let mut split = vec![];
+        for word in words.into_iter().flat_map(|word| word.split('-')) {
+            if word.is_empty() {
+                continue;
+            }
+            match self.split_compound(word).filter(|_| self.compound_words) {
+                Some(mut pieces) => split.append(&mut pieces),
+                None => split.push(word.to_string()),
             }
+        }
+        split
+    }
 
-            // These are numbers that are prefixes but not the start of a chain, i.e. we shouldn't
-            // expect two of these types of numbers in a row.
-            if !number_chain {
-                total = total.or(Some(0)).map(|x| x + curr_number.unwrap_or(0));
-                curr_number = Some(*val);
-            } else {
-                curr_number = curr_number.or(Some(0)).map(|x| x + *val);
+    /// Splits a compound number into its pieces, preferring the longest pieces first. Returns
+    /// None if the word can't be made entirely out of number words.
+    fn split_compound(&self, word: &str) -> Option<Vec<String>> {
+        if word.is_empty() {
+            return Some(vec![]);
+        }
+        if self.is_vocabulary_word(word) {
+            return Some(vec![word.to_string()]);
+        }
+        let boundaries: Vec<usize> = word.char_indices().map(|(i, _)| i).skip(1).collect();
+        boundaries.into_iter().rev().find_map(|i| {
+            if !self.is_vocabulary_word(&word[..i]) {
+                return None;
             }
-            found_prefix = true;
-            last_prefix = Some(*val);
-            last_number_was_digit = true;
-        } else if let Some(val) = NUMBER_MAP.get(word) {
-            // These are numbers that are prefixes but not the start of a chain, i.e. we shouldn't
-            // expect two of these types of numbers in a row.
-            if !number_chain {
-                total = total.or(Some(0)).map(|x| x + curr_number.unwrap_or(0));
-                curr_number = Some(*val);
+            let mut rest = self.split_compound(&word[i..])?;
+            rest.insert(0, word[..i].to_string());
+            Some(rest)
+        })
+    }
+
+    /// Runs through a voice command and translates any numbers said into an actual number. This
+    /// implementation is likely going to be heavily biases towards English as I'm not familiar with
+    /// the number structure of every language.
+    ///
+    /// Numbers too big to fit in an i64 aren't numbers as far as we're concerned.
+    pub fn parse_number_from_voice(&self, command: &str) -> Option<i64> {
+        let mut total: Option<i64> = None;
+        let mut curr_number = None;
+        let mut found_prefix = false;
+        let mut number_chain = false;
+
+        let mut last_prefix = None;
+        let mut last_magnitude = None;
+        let mut last_number_was_digit = false;
+
+        for word in self.split_words(command.split_whitespace()) {
+            let word = word.as_str();
+            if let Some(val) = self.digits.get(word) {
+                // If we have two digits in a row, that very likely means that the user is saying
+                // each individual digit. We're going to parse it as such since otherwise it doesn't
+                // make sense.
+                if last_number_was_digit {
+                    return self.parse_digits_from_command(command);
+                }
+
+                // These are numbers that are prefixes but not the start of a chain, i.e. we
+                // shouldn't expect two of these types of numbers in a row.
+                if !number_chain {
+                    total = Some(total.unwrap_or(0).checked_add(curr_number.unwrap_or(0))?);
+                    curr_number = Some(*val);
+                } else {
+                    curr_number = Some(curr_number.unwrap_or(0).checked_add(*val)?);
+                }
+                found_prefix = true;
+                last_prefix = Some(*val);
+                last_number_was_digit = true;
+            } else if let Some(val) = self.numbers.get(word) {
+                // These are numbers that are prefixes but not the start of a chain, i.e. we
+                // shouldn't expect two of these types of numbers in a row.
+                if !number_chain {
+                    total = Some(total.unwrap_or(0).checked_add(curr_number.unwrap_or(0))?);
+                    curr_number = Some(*val);
+                } else {
+                    curr_number = Some(curr_number.unwrap_or(0).checked_add(*val)?);
+                }
+                found_prefix = true;
+                last_prefix = Some(*val);
+            } else if let Some(val) = self.chains.get(word) {
+                // This is reserved for numbers that aren't magnitudes but should be treated as
+                // chains for the purposes of construction. In English, these are the -ty numbers.
+                curr_number = Some(curr_number.unwrap_or(0).checked_add(*val)?);
+                number_chain = true;
+                last_number_was_digit = false;
+            } else if let Some(val) = self.magnitudes.get(word) {
+                let mut val = *val;
+                if last_magnitude.is_none() {
+                    last_magnitude = Some(val);
+                }
+
+                // This is to handle the special case when users may chain numbers together without
+                // chain words. This is most common in cases like "one hundred twenty thousand". So
+                // we keep track of the magnitudes in the numbers and note if the magnitudes have
+                // grown in the number. This is an indicator that our parse so far is wrong so we
+                // need to correct for it now. Again, this may be a English-specific issue so keep
+                // an eye on this.
+                if last_magnitude.as_ref() < Some(&val) && !number_chain {
+                    let lp = last_prefix.unwrap_or(0);
+                    let lm = last_magnitude.unwrap_or(0);
+                    total = Some(total.unwrap_or(0).checked_sub(lp.checked_mul(lm)?)?);
+                    val = val.checked_mul(lm)?;
+                    curr_number = Some(curr_number.unwrap_or(0).checked_add(lp.checked_mul(val)?)?);
+                } else if found_prefix {
+                    curr_number = Some(curr_number.unwrap_or(0).checked_mul(val)?);
+                // In English, we'll often say something like "a thousand" to indicate one thousand.
+                // So we need to handle that special case here where a magnitude is used without a
+                // preceeding number.
+                } else {
+                    curr_number = Some(val);
+                    last_prefix = Some(1);
+                    found_prefix = true;
+                }
+
+                last_magnitude = Some(val);
+                number_chain = false;
+                last_number_was_digit = false;
+            } else if self.chain_words.iter().any(|chain_word| chain_word == word) {
+                // Since sometimes, numbers are chained together with special words ("and" in
+                // English), we handle that case here to know that we're not done constructing this
+                // part of the number.
+                number_chain = true;
+                last_number_was_digit = false;
             } else {
-                curr_number = curr_number.or(Some(0)).map(|x| x + *val);
+                found_prefix = false;
+                number_chain = false;
+                last_number_was_digit = false;
             }
-            found_prefix = true;
-            last_prefix = Some(*val);
-        } else if let Some(val) = CHAINS_MAP.get(word) {
-            // This is reserved for numbers that aren't magnitudes but should be treated as chains
-            // for the purposes of construction. In English, these are the -ty numbers.
-            curr_number = curr_number.or(Some(0)).map(|x| x + *val);
-            number_chain = true;
-            last_number_was_digit = false;
-        } else if let Some(val) = MAGNITUDE_MAP.get(word) {
-            let mut val = *val;
-            if last_magnitude.is_none() {
-                last_magnitude = Some(val);
+        }
+
+        // Since prefix numbers are their own number themselves, we'll terminate the above loop
+        // without adding the last number we parsed to the total.
+        if let Some(curr_number) = curr_number {
+            total = Some(total.unwrap_or(0).checked_add(curr_number)?);
+        }
+
+        total
+    }
+
+    /// Whether the word can make up part of a spoken number, like "twenty", "thousand" or the "and"
+    /// in "one hundred and six". Articles count too since they stand in for "one" in "a hundred".
+    fn is_number_word(&self, word: &str) -> bool {
+        self.articles.iter().any(|article| article == word)
+            || self
+                .split_words(Some(word))
+                .iter()
+                .all(|piece| self.is_vocabulary_word(piece))
+    }
+
+    /// Parses a number out of exactly the given words. Unlike `parse_number_from_voice`, every word
+    /// has to be part of the number.
+    pub fn parse_number_from_words(&self, words: &[&str]) -> Option<i64> {
+        if words.iter().all(|word| self.is_number_word(word)) {
+            self.parse_number_from_voice(&words.join(" "))
+        } else {
+            None
+        }
+    }
+
+    /// The digit a word stands for when reading a number out a digit at a time, including words
+    /// like "oh" that only mean zero there.
+    fn digit(&self, word: &str) -> Option<i64> {
+        match self.digits.get(word) {
+            Some(digit) => Some(*digit),
+            None if self.is_zero_word(word) => Some(0),
+            None => None,
+        }
+    }
+
+    /// Finds every number in the words, in the order they were said. Each number takes up as many
+    /// words as it can, so "one hundred and five apples and two pears" has two numbers in it.
+    pub fn find_numbers(&self, words: &[&str]) -> Vec<SpokenNumber> {
+        let mut numbers = vec![];
+        let mut start = 0;
+        while start < words.len() {
+            let found = self.find_digits(&words[start..]).or_else(|| {
+                (start + 1..=words.len())
+                    .rev()
+                    .find_map(|end| self.parse_spoken_number(&words[start..end]))
+            });
+            match found {
+                Some(mut number) => {
+                    number.span = start + number.span.start..start + number.span.end;
+                    start = number.span.end;
+                    numbers.push(number);
+                }
+                None => start += 1,
             }
+        }
+        numbers
+    }
 
-            // This is to handle the special case when users may chain numbers together without
-            // chain words. This is most common in cases like "one hundred twenty thousand". So we
-            // keep track of the magnitudes in the numbers and note if the magnitudes have grown in
-            // the number. This is an indicator that our parse so far is wrong so we need to correct
-            // for it now. Again, this may be a English-specific issue so keep an eye on this.
-            if last_magnitude.as_ref() < Some(&val) && !number_chain {
-                let lp = last_prefix.unwrap_or(0);
-                let lm = last_magnitude.unwrap_or(0);
-                total = total.or(Some(0)).map(|x| x - (lp * lm));
-                val *= lm;
-                curr_number = curr_number.or(Some(0)).map(|x| x + (lp * val));
-            } else if found_prefix {
-                curr_number = curr_number.or(Some(0)).map(|x| x * val);
-            // In English, we'll often say something like "a thousand" to indicate one thousand. So
-            // we need to handle that special case here where a magnitude is used without a
-            // preceeding number.
-            } else {
-                curr_number = Some(val);
-                last_prefix = Some(1);
-                found_prefix = true;
+    /// Finds a number read out a digit at a time at the start of the words, like "nine oh two one
+    /// oh". These can have zeros in them that don't make sense in any other kind of number.
+    fn find_digits(&self, words: &[&str]) -> Option<SpokenNumber> {
+        let digits: Vec<i64> = words.iter().map_while(|word| self.digit(word)).collect();
+        if digits.len() < 2 {
+            return None;
+        }
+        let value = digits.iter().try_fold(0i64, |value, digit| {
+            value.checked_mul(10)?.checked_add(*digit)
+        })?;
+        Some(SpokenNumber {
+            value: value as f64,
+            kind: NumberKind::Cardinal,
+            span: 0..digits.len(),
+        })
+    }
+
+    /// Writes out the whole numbers in the words in digits, leaving every other word as it is.
+    /// Numbers read out a digit at a time keep any leading zeros, so postal codes like "zero two
+    /// one oh eight" come out as "02108".
+    pub fn write_numbers(&self, words: &[&str]) -> String {
+        let mut written = vec![];
+        let mut pos = 0;
+        for number in self.find_numbers(words) {
+            written.extend(
+                words[pos..number.span.start]
+                    .iter()
+                    .map(|word| word.to_string()),
+            );
+            let spoken = &words[number.span.clone()];
+            let digits: Option<String> = spoken
+                .iter()
+                .map(|word| self.digit(word).map(|digit| digit.to_string()))
+                .collect();
+            written.push(match (digits, number.as_integer()) {
+                (Some(digits), _) if spoken.len() > 1 => digits,
+                (_, Some(value)) if number.kind == NumberKind::Cardinal => value.to_string(),
+                _ => spoken.join(" "),
+            });
+            pos = number.span.end;
+        }
+        written.extend(words[pos..].iter().map(|word| word.to_string()));
+        written.join(" ")
+    }
+
+    /// Parses a number of any kind out of exactly the given words.
+    pub fn parse_spoken_number(&self, words: &[&str]) -> Option<SpokenNumber> {
+        // Hyphenated numbers like "forty-second" are treated as separate words, but the span still
+        // covers the words as they were given to us.
+        let split: Vec<&str> = words
+            .iter()
+            .flat_map(|word| match word.find('-') {
+                Some(i) if i > 0 => word.split('-').collect(),
+                _ => vec![*word],
+            })
+            .collect();
+        self.parse_value(&split).map(|(value, kind)| SpokenNumber {
+            value,
+            kind,
+            span: 0..words.len(),
+        })
+    }
+
+    fn parse_value(&self, words: &[&str]) -> Option<(f64, NumberKind)> {
+        // A trailing chain word or article belongs to whatever comes after the number, not the
+        // number itself.
+        let (first, last) = (words.first()?, words.last()?);
+        if self
+            .chain_words
+            .iter()
+            .any(|word| word == first || word == last)
+            || self.articles.iter().any(|article| article == last)
+        {
+            return None;
+        }
+
+        if self.negative_words.iter().any(|word| word == first) {
+            return match self.parse_value(&words[1..])? {
+                (_, NumberKind::Ordinal) | (_, NumberKind::Year) => None,
+                (value, kind) => Some((-value, kind)),
+            };
+        }
+
+        if let [word] = words {
+            if let Some(number) = parse_written_number(word) {
+                return Some(number);
             }
+        }
+        if let Some(point) = words
+            .iter()
+            .position(|word| self.decimal_point_words.iter().any(|point| point == word))
+        {
+            return self
+                .parse_decimal(&words[..point], &words[point + 1..])
+                .map(|value| (value, NumberKind::Decimal));
+        }
+        if let Some(value) = self.parse_fraction(words) {
+            return Some((value, NumberKind::Fraction));
+        }
+        if let Some(value) = self.parse_ordinal(words) {
+            return Some((value as f64, NumberKind::Ordinal));
+        }
+        if let Some(value) = self.parse_year(words) {
+            return Some((value as f64, NumberKind::Year));
+        }
+        self.parse_number_from_words(words)
+            .map(|value| (value as f64, NumberKind::Cardinal))
+    }
 
-            last_magnitude = Some(val);
-            number_chain = false;
-            last_number_was_digit = false;
-        } else if NUMBER_CHAIN_WORDS.contains(&word.to_string()) {
-            // Since sometimes, numbers are chained together with special words ("and" in English),
-            // we handle that case here to know that we're not done constructing this part of the
-            // number.
-            number_chain = true;
-            last_number_was_digit = false;
+    fn parse_decimal(&self, whole: &[&str], fraction: &[&str]) -> Option<f64> {
+        let whole = if whole.is_empty() {
+            0
         } else {
-            found_prefix = false;
-            number_chain = false;
-            last_number_was_digit = false;
+            self.parse_number_from_words(whole)?
+        };
+        if fraction.is_empty() {
+            return None;
         }
+        // Everything after the point is read out one digit at a time.
+        let mut digits = String::from("0.");
+        for word in fraction {
+            let digit = match self.digits.get(*word) {
+                Some(digit) => *digit,
+                None if self.is_zero_word(word) => 0,
+                None => return None,
+            };
+            digits.push_str(&digit.to_string());
+        }
+        Some(whole as f64 + digits.parse::<f64>().ok()?)
     }
 
-    // Since prefix numbers are their own number themselves, we'll terminate the above loop without
-    // adding the last number we parsed to the total.
-    if curr_number.is_some() {
-        total = total.or(Some(0)).map(|x| x + curr_number.unwrap_or(0));
+    /// Parses fractions like "a half", "three quarters", "two thirds" and "one and a half".
+    fn parse_fraction(&self, words: &[&str]) -> Option<f64> {
+        // Whole numbers with a fraction on the end, like "one and a half".
+        if let Some(and) = words
+            .iter()
+            .rposition(|word| self.chain_words.iter().any(|chain_word| chain_word == word))
+        {
+            if let (Some(whole), Some(fraction)) = (
+                self.parse_number_from_words(&words[..and]),
+                self.parse_fraction(&words[and + 1..]),
+            ) {
+                return Some(whole as f64 + fraction);
+            }
+        }
+
+        let (denominator, numerator) = words.split_last()?;
+        let is_article = |word: &&str| self.articles.iter().any(|article| article == word);
+        let single = match numerator {
+            [word] => is_article(word) || self.digits.get(*word) == Some(&1),
+            _ => false,
+        };
+        let denominator = match self.fractions.get(*denominator) {
+            Some(denominator) => *denominator,
+            // Ordinals only make sense as fractions when they're plural ("two thirds") or there's
+            // only one of them ("a third").
+            None if self.ordinal_fractions => {
+                let ordinal = denominator.strip_suffix('s').filter(|_| !single);
+                let ordinal = ordinal.or(Some(*denominator).filter(|_| single))?;
+                if self
+                    .ordinal_fraction_exceptions
+                    .iter()
+                    .any(|exception| exception == ordinal)
+                {
+                    return None;
+                }
+                self.parse_ordinal(&[ordinal])?
+            }
+            None => return None,
+        };
+        let numerator = match numerator {
+            [] => return None,
+            [word] if is_article(word) => 1,
+            _ => self.parse_number_from_words(numerator)?,
+        };
+        Some(numerator as f64 / denominator as f64)
     }
 
-    total
-}
+    fn parse_ordinal(&self, words: &[&str]) -> Option<i64> {
+        let (last, rest) = words.split_last()?;
+        // "a third" is a fraction, and "a second" is a unit of time.
+        if let [word] = rest {
+            if self.articles.iter().any(|article| article == word) {
+                return None;
+            }
+        }
 
-/// A parsing function for when users say digits in order, such as "one two three" instead of
-/// something like "one hundred and twenty three".
-fn parse_digits_from_command(command: &str) -> Option<i64> {
-    let mut digit_str = String::new();
-    for word in command.split_whitespace() {
-        if let Some(val) = DIGIT_MAP.get(word) {
-            digit_str.push_str(&val.to_string());
+        let cardinal = match self.ordinals.get(*last) {
+            Some(cardinal) => cardinal.clone(),
+            None => self
+                .ordinal_suffixes
+                .iter()
+                .filter_map(|suffix| last.strip_suffix(suffix.as_str()))
+                .find(|cardinal| !cardinal.is_empty() && self.is_number_word(cardinal))?
+                .to_string(),
+        };
+        let mut words = rest.to_vec();
+        words.push(&cardinal);
+        self.parse_number_from_words(&words)
+    }
+
+    /// Parses years said as two pairs of digits, like "nineteen eighty four", "twenty twenty",
+    /// "nineteen hundred" or "nineteen oh five".
+    fn parse_year(&self, words: &[&str]) -> Option<i64> {
+        if !self.years_in_pairs {
+            return None;
         }
+        (1..words.len()).find_map(|split| {
+            let century = self.parse_two_digits(&words[..split])?;
+            let year = match &words[split..] {
+                [word] if self.magnitudes.get(*word) == Some(&100) => 0,
+                [zero, digit] if self.is_zero_word(zero) => *self.digits.get(*digit)?,
+                rest => self.parse_two_digits(rest)?,
+            };
+            Some(century * 100 + year)
+        })
     }
-    if digit_str.is_empty() {
-        None
-    } else {
-        digit_str.parse::<i64>().ok()
+
+    /// Parses a number from ten to ninety nine said without any chain words or magnitudes.
+    fn parse_two_digits(&self, words: &[&str]) -> Option<i64> {
+        let number = match words {
+            [teen] => self
+                .numbers
+                .get(*teen)
+                .or_else(|| self.chains.get(*teen))
+                .copied()?,
+            [tens, digit] => self.chains.get(*tens)? + self.digits.get(*digit)?,
+            _ => return None,
+        };
+        Some(number)
     }
+
+    /// A parsing function for when users say digits in order, such as "one two three" instead of
+    /// something like "one hundred and twenty three".
+    fn parse_digits_from_command(&self, command: &str) -> Option<i64> {
+        let mut digit_str = String::new();
+        for word in self.split_words(command.split_whitespace()) {
+            let word = word.as_str();
+            if let Some(val) = self.digits.get(word) {
+                digit_str.push_str(&val.to_string());
+            }
+        }
+        if digit_str.is_empty() {
+            None
+        } else {
+            digit_str.parse::<i64>().ok()
+        }
+    }
+}
+
+/// Parses numbers that were written out rather than spoken, like "42", "2.5" or "21st".
+fn parse_written_number(word: &str) -> Option<(f64, NumberKind)> {
+    if !word.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        return None;
+    }
+    if let Ok(value) = word.parse::<i64>() {
+        return Some((value as f64, NumberKind::Cardinal));
+    }
+    if let Ok(value) = word.parse::<f64>() {
+        return Some((value, NumberKind::Decimal));
+    }
+    ["st", "nd", "rd", "th"].iter().find_map(|suffix| {
+        let value = word.strip_suffix(suffix)?.parse::<u64>().ok()?;
+        Some((value as f64, NumberKind::Ordinal))
+    })
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn english() -> NumberParser {
+        NumberParser::init(Language::English).expect("No numbers file found")
+    }
+
     #[test]
     fn parse_simple_numbers() {
-        assert_eq!(parse_number_from_voice("six"), Some(6));
-        assert_eq!(parse_number_from_voice("seventeen"), Some(17));
-        assert_eq!(parse_number_from_voice("ninety"), Some(90));
+        let numbers = english();
+        assert_eq!(numbers.parse_number_from_voice("six"), Some(6));
+        assert_eq!(numbers.parse_number_from_voice("seventeen"), Some(17));
+        assert_eq!(numbers.parse_number_from_voice("ninety"), Some(90));
     }
 
     #[test]
     fn parse_combo_numbers() {
-        assert_eq!(parse_number_from_voice("seventy six"), Some(76));
-        assert_eq!(parse_number_from_voice("fifty two"), Some(52));
+        let numbers = english();
+        assert_eq!(numbers.parse_number_from_voice("seventy six"), Some(76));
+        assert_eq!(numbers.parse_number_from_voice("fifty two"), Some(52));
         assert_eq!(
-            parse_number_from_voice("nine hundred ninety nine"),
+            numbers.parse_number_from_voice("nine hundred ninety nine"),
             Some(999)
         );
     }
 
+    #[test]
+    fn parse_too_big() {
+        let numbers = english();
+        assert_eq!(
+            numbers.parse_number_from_voice("nine hundred billion billion billion"),
+            None
+        );
+        assert_eq!(
+            numbers.parse_number_from_voice("nine billion billion"),
+            Some(9_000_000_000_000_000_000)
+        );
+    }
+
     #[test]
     fn parse_no_prefix() {
-        assert_eq!(parse_number_from_voice("a hundred thousand"), Some(100_000));
+        let numbers = english();
         assert_eq!(
-            parse_number_from_voice("a million and six"),
+            numbers.parse_number_from_voice("a hundred thousand"),
+            Some(100_000)
+        );
+        assert_eq!(
+            numbers.parse_number_from_voice("a million and six"),
             Some(1e6 as i64 + 6)
         );
     }
 
     #[test]
     fn parse_num_with_mag() {
-        assert_eq!(parse_number_from_voice("five hundred"), Some(500));
+        let numbers = english();
+        assert_eq!(numbers.parse_number_from_voice("five hundred"), Some(500));
         assert_eq!(
-            parse_number_from_voice("five hundred and thirty"),
+            numbers.parse_number_from_voice("five hundred and thirty"),
             Some(530)
         );
         assert_eq!(
-            parse_number_from_voice("five hundred thirty six"),
+            numbers.parse_number_from_voice("five hundred thirty six"),
             Some(536)
         );
     }
 
     #[test]
     fn parse_flipped_magnitudes() {
+        let numbers = english();
         assert_eq!(
-            parse_number_from_voice(
+            numbers.parse_number_from_voice(
                 "one million five hundred and thirty six thousand one hundred and two"
             ),
             Some(1_536_102)
         );
 
         assert_eq!(
-            parse_number_from_voice(
+            numbers.parse_number_from_voice(
                 "one million five hundred thirty six thousand one hundred and two"
             ),
             Some(1_536_102)
         );
 
         assert_eq!(
-            parse_number_from_voice("five hundred thirty thousand"),
+            numbers.parse_number_from_voice("five hundred thirty thousand"),
             Some(530_000)
         );
 
         assert_eq!(
-            parse_number_from_voice("one hundred million and five"),
+            numbers.parse_number_from_voice("one hundred million and five"),
             Some(1e8 as i64 + 5)
         );
     }
 
     #[test]
     fn parse_without_chain_words() {
+        let numbers = english();
         assert_eq!(
-            parse_number_from_voice("one hundred twenty thousand five hundred six"),
+            numbers.parse_number_from_voice("one hundred twenty thousand five hundred six"),
             Some(120_506)
         );
     }
 
     #[test]
     fn parse_digits() {
-        assert_eq!(parse_number_from_voice("one two three four"), Some(1234));
-        assert_eq!(parse_number_from_voice("zero zero zero seven"), Some(7));
+        let numbers = english();
+        assert_eq!(
+            numbers.parse_number_from_voice("one two three four"),
+            Some(1234)
+        );
+        assert_eq!(
+            numbers.parse_number_from_voice("zero zero zero seven"),
+            Some(7)
+        );
+    }
+
+    fn spoken(text: &str) -> Option<(f64, NumberKind)> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        english()
+            .parse_spoken_number(&words)
+            .map(|number| (number.value, number.kind))
+    }
+
+    #[test]
+    fn parse_corrected_spellings() {
+        let numbers = english();
+        assert_eq!(numbers.parse_number_from_voice("nineteen"), Some(19));
+        assert_eq!(numbers.parse_number_from_voice("forty two"), Some(42));
+    }
+
+    #[test]
+    fn parse_ordinals() {
+        assert_eq!(spoken("third"), Some((3.0, NumberKind::Ordinal)));
+        assert_eq!(spoken("twenty first"), Some((21.0, NumberKind::Ordinal)));
+        assert_eq!(spoken("forty second"), Some((42.0, NumberKind::Ordinal)));
+        assert_eq!(
+            spoken("one hundred and twelfth"),
+            Some((112.0, NumberKind::Ordinal))
+        );
+        assert_eq!(spoken("thousandth"), Some((1000.0, NumberKind::Ordinal)));
+        assert_eq!(spoken("23rd"), Some((23.0, NumberKind::Ordinal)));
+    }
+
+    #[test]
+    fn parse_decimals() {
+        assert_eq!(spoken("two point five"), Some((2.5, NumberKind::Decimal)));
+        assert_eq!(
+            spoken("three point two five"),
+            Some((3.25, NumberKind::Decimal))
+        );
+        assert_eq!(spoken("point oh five"), Some((0.05, NumberKind::Decimal)));
+        assert_eq!(
+            spoken("twenty one point seven"),
+            Some((21.7, NumberKind::Decimal))
+        );
+        assert_eq!(spoken("two point"), None);
+        assert_eq!(spoken("two point twenty"), None);
+    }
+
+    #[test]
+    fn parse_negatives() {
+        assert_eq!(spoken("minus ten"), Some((-10.0, NumberKind::Cardinal)));
+        assert_eq!(
+            spoken("negative two point five"),
+            Some((-2.5, NumberKind::Decimal))
+        );
+        assert_eq!(spoken("minus third"), None);
+        assert_eq!(spoken("minus"), None);
+    }
+
+    #[test]
+    fn parse_fractions() {
+        assert_eq!(spoken("a half"), Some((0.5, NumberKind::Fraction)));
+        assert_eq!(spoken("three quarters"), Some((0.75, NumberKind::Fraction)));
+        assert_eq!(
+            spoken("two thirds"),
+            Some((2.0 / 3.0, NumberKind::Fraction))
+        );
+        assert_eq!(spoken("a fifth"), Some((0.2, NumberKind::Fraction)));
+        assert_eq!(spoken("one and a half"), Some((1.5, NumberKind::Fraction)));
+        assert_eq!(
+            spoken("two and three quarters"),
+            Some((2.75, NumberKind::Fraction))
+        );
+
+        // Without "a" or "one" in front of it, it's just an ordinal.
+        assert_eq!(spoken("fifth"), Some((5.0, NumberKind::Ordinal)));
+        assert_eq!(spoken("a second"), None);
+    }
+
+    #[test]
+    fn parse_years() {
+        assert_eq!(
+            spoken("nineteen eighty four"),
+            Some((1984.0, NumberKind::Year))
+        );
+        assert_eq!(spoken("twenty twenty"), Some((2020.0, NumberKind::Year)));
+        assert_eq!(
+            spoken("twenty twenty one"),
+            Some((2021.0, NumberKind::Year))
+        );
+        assert_eq!(spoken("nineteen hundred"), Some((1900.0, NumberKind::Year)));
+        assert_eq!(spoken("nineteen oh five"), Some((1905.0, NumberKind::Year)));
+
+        // These are ordinary numbers.
+        assert_eq!(spoken("twenty one"), Some((21.0, NumberKind::Cardinal)));
+        assert_eq!(spoken("five hundred"), Some((500.0, NumberKind::Cardinal)));
+    }
+
+    #[test]
+    fn find_multiple_numbers() {
+        let numbers = english();
+        let words: Vec<&str> = "set the temperature to minus five point five on the third of may \
+            nineteen ninety nine and add two and a half cups"
+            .split_whitespace()
+            .collect();
+        let numbers = numbers.find_numbers(&words);
+        let found: Vec<(f64, NumberKind, Range<usize>)> = numbers
+            .into_iter()
+            .map(|number| (number.value, number.kind, number.span))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (-5.5, NumberKind::Decimal, 4..8),
+                (3.0, NumberKind::Ordinal, 10..11),
+                (1999.0, NumberKind::Year, 13..16),
+                (2.5, NumberKind::Fraction, 18..22),
+            ]
+        );
+    }
+
+    #[test]
+    fn write_numbers() {
+        let numbers = english();
+        let write = |text: &str| {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            numbers.write_numbers(&words)
+        };
+        assert_eq!(write("one zero one one five"), "10115");
+        assert_eq!(write("zero two one oh eight"), "02108");
+        assert_eq!(write("nine oh two one oh"), "90210");
+        assert_eq!(
+            write("one hundred and five apples and the third pear"),
+            "105 apples and the third pear"
+        );
+        assert_eq!(write("springfield"), "springfield");
+    }
+
+    #[test]
+    fn parse_german() {
+        let numbers = NumberParser::init(Language::German).expect("No numbers file found");
+        let spoken = |text: &str| {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            numbers
+                .parse_spoken_number(&words)
+                .map(|number| (number.value, number.kind))
+        };
+
+        assert_eq!(numbers.parse_number_from_voice("siebzehn"), Some(17));
+        assert_eq!(numbers.parse_number_from_voice("zweiundzwanzig"), Some(22));
+        assert_eq!(numbers.parse_number_from_voice("hundertzwei"), Some(102));
+        assert_eq!(
+            numbers.parse_number_from_voice("dreihundertzweiundzwanzig"),
+            Some(322)
+        );
+        assert_eq!(
+            numbers.parse_number_from_voice("dreitausendzweihundert"),
+            Some(3200)
+        );
+        assert_eq!(
+            numbers.parse_number_from_voice("neunzehnhundertvierundachtzig"),
+            Some(1984)
+        );
+        assert_eq!(
+            numbers.parse_number_from_voice("eine million"),
+            Some(1_000_000)
+        );
+
+        assert_eq!(spoken("dritte"), Some((3.0, NumberKind::Ordinal)));
+        assert_eq!(
+            spoken("einundzwanzigste"),
+            Some((21.0, NumberKind::Ordinal))
+        );
+        assert_eq!(spoken("drei viertel"), Some((0.75, NumberKind::Fraction)));
+        assert_eq!(spoken("zwei komma fünf"), Some((2.5, NumberKind::Decimal)));
+        assert_eq!(spoken("minus zehn"), Some((-10.0, NumberKind::Cardinal)));
+        assert_eq!(spoken("zwiebel"), None);
+    }
+
+    #[test]
+    fn parse_hyphenated() {
+        let numbers = english();
+        assert_eq!(numbers.parse_number_from_voice("twenty-one"), Some(21));
+        assert_eq!(
+            numbers
+                .parse_spoken_number(&["forty-second"])
+                .map(|n| n.value),
+            Some(42.0)
+        );
     }
 }
@@ -0,0 +1,284 @@
+use super::{
+    command::CommandParser,
+    dialog::{Dialog, Outcome, Turn},
+    grammar::tokenize,
+};
+use crate::settings::RecognitionSettings;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// One of the transcripts Deepspeech thinks an utterance could be.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Candidate {
+    pub transcript: String,
+    // How confident Deepspeech is in the transcript. This is roughly a log probability, so it's
+    // always negative and gets lower the longer the utterance is.
+    pub confidence: f64,
+}
+
+/// Pulls the candidate transcripts out of what Deepspeech gave us, most confident first.
+pub fn candidates(metadata: &deepspeech::Metadata) -> Vec<Candidate> {
+    metadata
+        .transcripts()
+        .iter()
+        .map(|transcript| Candidate {
+            transcript: transcript.to_string(),
+            confidence: transcript.confidence(),
+        })
+        .collect()
+}
+
+/// Works out which words Deepspeech should be nudged towards hearing and by how much: the
+/// keywords of the commands, the words in the names of things the tablet knows about, and any
+/// hot-words from the settings. Words that aren't boosted at all are left out.
+pub fn hot_words(
+    keywords: &[String],
+    names: &[String],
+    settings: &RecognitionSettings,
+) -> Vec<(String, f32)> {
+    let mut hot_words = BTreeMap::new();
+    for keyword in keywords {
+        hot_words.insert(keyword.clone(), settings.command_word_boost);
+    }
+    // Deepspeech only boosts single words, so names get boosted a word at a time.
+    for word in names.iter().flat_map(|name| tokenize(name)) {
+        hot_words.insert(word, settings.name_boost);
+    }
+    for (word, boost) in &settings.hot_words {
+        hot_words.insert(word.to_lowercase(), *boost);
+    }
+    hot_words
+        .into_iter()
+        .filter(|(_, boost)| *boost != 0.0)
+        .collect()
+}
+
+/// What we decided an utterance was.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recognition {
+    pub transcript: String,
+    pub confidence: f64,
+    pub turn: Turn,
+}
+
+/// Turns candidate transcripts into turns of the conversation.
+pub struct Recognizer {
+    command_parser: CommandParser,
+    settings: RecognitionSettings,
+    dialog: Arc<Mutex<Dialog>>,
+}
+
+impl Recognizer {
+    pub fn new(
+        command_parser: CommandParser,
+        settings: RecognitionSettings,
+        dialog: Arc<Mutex<Dialog>>,
+    ) -> Self {
+        Self {
+            command_parser,
+            settings,
+            dialog,
+        }
+    }
+
+    /// How many candidate transcripts to ask Deepspeech for.
+    pub fn n_best(&self) -> u32 {
+        self.settings.n_best
+    }
+
+    /// Picks what an utterance was out of the candidate transcripts. The most confident
+    /// candidate that makes sense in the conversation so far wins, as long as it's confident
+    /// enough and not too far behind the most confident candidate. If none of them make sense,
+    /// the most confident candidate is taken without being understood. Returns None if Deepspeech
+    /// thinks there wasn't anything said at all. This doesn't move the conversation along; that's
+    /// left to whatever acts on the recognition.
+    pub fn choose(&self, candidates: &[Candidate]) -> Option<Recognition> {
+        let settings = &self.settings;
+        let best = candidates.first()?;
+        let confident_enough = |candidate: &&Candidate| {
+            settings
+                .min_confidence
+                .map_or(true, |min| candidate.confidence >= min)
+                && best.confidence - candidate.confidence <= settings.max_confidence_gap
+        };
+
+        let now = Local::now();
+        let dialog = self.dialog.lock().unwrap();
+        let understood = candidates
+            .iter()
+            .filter(|candidate| !candidate.transcript.is_empty())
+            .filter(confident_enough)
+            .find_map(|candidate| {
+                let intent = self.command_parser.parse_intent(&candidate.transcript);
+                let turn = dialog.interpret(intent, &now);
+                match turn.outcome {
+                    Outcome::NotUnderstood => None,
+                    _ => Some((candidate, turn)),
+                }
+            });
+        match understood {
+            Some((candidate, turn)) => Some(Recognition {
+                transcript: candidate.transcript.clone(),
+                confidence: candidate.confidence,
+                turn,
+            }),
+            // Due to the rather shocking false positive rate of webrtc-vad, Deepspeech will often
+            // tell us that there was nothing there. Just drop those on the floor.
+            None if best.transcript.is_empty() => None,
+            None => Some(Recognition {
+                transcript: best.transcript.clone(),
+                confidence: best.confidence,
+                turn: Turn::not_understood(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{settings::Language, voice::command::Command};
+    use std::time::Duration;
+
+    fn candidate(transcript: &str, confidence: f64) -> Candidate {
+        Candidate {
+            transcript: transcript.into(),
+            confidence,
+        }
+    }
+
+    fn recognizer(settings: RecognitionSettings) -> Recognizer {
+        let command_parser = CommandParser::init(Language::English).expect("No intents file found");
+        let dialog = Arc::new(Mutex::new(Dialog::new(Duration::from_secs(15))));
+        Recognizer::new(command_parser, settings, dialog)
+    }
+
+    // The transcript that was picked, in parentheses if it wasn't understood.
+    fn choose_transcript(candidates: &[Candidate], recognizer: &Recognizer) -> Option<String> {
+        recognizer.choose(candidates).map(|recognition| {
+            if recognition.turn.outcome != Outcome::NotUnderstood {
+                recognition.transcript
+            } else {
+                format!("({})", recognition.transcript)
+            }
+        })
+    }
+
+    #[test]
+    fn test_choose() {
+        let recognizer = recognizer(RecognitionSettings::default());
+
+        // The first candidate that parses wins, even if it's not the most confident.
+        let candidates = [
+            candidate("what is the wether", -10.0),
+            candidate("what is the weather", -11.0),
+            candidate("what is the news", -12.0),
+        ];
+        assert_eq!(
+            choose_transcript(&candidates, &recognizer),
+            Some("what is the weather".into())
+        );
+        let recognition = recognizer.choose(&candidates).unwrap();
+        assert_eq!(recognition.confidence, -11.0);
+        assert_eq!(
+            recognition.turn.command(),
+            Some(Command::Weather(None, None))
+        );
+
+        // Nothing parses, so the best guess gets passed along without a command.
+        let candidates = [
+            candidate("what is the wether", -10.0),
+            candidate("what", -11.0),
+        ];
+        assert_eq!(
+            choose_transcript(&candidates, &recognizer),
+            Some("(what is the wether)".into())
+        );
+
+        // Deepspeech thinks it was most likely nothing at all.
+        assert_eq!(choose_transcript(&[candidate("", -5.0)], &recognizer), None);
+        assert_eq!(choose_transcript(&[], &recognizer), None);
+    }
+
+    #[test]
+    fn test_choose_in_conversation() {
+        let recognizer = recognizer(RecognitionSettings::default());
+        let candidates = [
+            candidate("fine minutes", -10.0),
+            candidate("five minutes", -11.0),
+        ];
+        assert_eq!(
+            choose_transcript(&candidates, &recognizer),
+            Some("(fine minutes)".into())
+        );
+
+        // Once we've asked how long a timer should be, a duration on its own is an answer.
+        let question = recognizer
+            .choose(&[candidate("set a timer", -5.0)])
+            .unwrap();
+        recognizer
+            .dialog
+            .lock()
+            .unwrap()
+            .advance(&question.turn, &Local::now());
+        let recognition = recognizer.choose(&candidates).unwrap();
+        assert_eq!(recognition.transcript, "five minutes");
+        assert_eq!(
+            recognition.turn.command(),
+            Some(Command::Timer(Duration::from_secs(5 * 60)))
+        );
+    }
+
+    #[test]
+    fn test_hot_words() {
+        let keywords = vec!["timer".to_string(), "weather".to_string()];
+        let names = vec!["NPR".to_string(), "Hacker News".to_string()];
+        let mut settings = RecognitionSettings {
+            command_word_boost: 5.0,
+            name_boost: 10.0,
+            ..Default::default()
+        };
+        settings.hot_words.insert("Tablet".into(), 15.0);
+        settings.hot_words.insert("timer".into(), 0.0);
+
+        assert_eq!(
+            hot_words(&keywords, &names, &settings),
+            vec![
+                ("hacker".to_string(), 10.0),
+                ("news".to_string(), 10.0),
+                ("npr".to_string(), 10.0),
+                ("tablet".to_string(), 15.0),
+                ("weather".to_string(), 5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_choose_rejects_unconfident() {
+        let recognizer = recognizer(RecognitionSettings {
+            min_confidence: Some(-20.0),
+            max_confidence_gap: 5.0,
+            ..Default::default()
+        });
+
+        // Too unconfident to act on, even though it's the best there is.
+        assert_eq!(
+            choose_transcript(&[candidate("what is the weather", -25.0)], &recognizer),
+            Some("(what is the weather)".into())
+        );
+
+        // Noise that Deepspeech is fairly sure was nothing shouldn't be turned into a command
+        // just because a long shot happened to parse.
+        let candidates = [candidate("", -8.0), candidate("news", -15.0)];
+        assert_eq!(choose_transcript(&candidates, &recognizer), None);
+        let candidates = [candidate("", -8.0), candidate("news", -12.0)];
+        assert_eq!(
+            choose_transcript(&candidates, &recognizer),
+            Some("news".into())
+        );
+    }
+}
@@ -0,0 +1,388 @@
+use super::dialog::Question;
+use crate::{
+    news::NewsItem,
+    settings::Language,
+    weather::{TemperatureUnits, WeatherReport},
+};
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use serde::Deserialize;
+use std::{fs::File, io::Read, time::Duration};
+
+/// How many headlines get read out when someone asks for the news.
+const HEADLINE_COUNT: usize = 3;
+
+/// What the tablet says back after a command, in one language. Each reply is a template with the
+/// details of the command filled in where the `{name}` placeholders are. These are loaded from
+/// replies_{code}.json.
+#[derive(Deserialize)]
+pub struct Replies {
+    // Has {temp} and {description}.
+    weather: String,
+    // The weather somewhere other than the primary location, with {location} as well.
+    weather_in: String,
+    // The forecast for another day, with {day}, {low}, {high} and {description}.
+    forecast: String,
+    // The forecast somewhere other than the primary location, with {location} as well.
+    forecast_in: String,
+    // Has {headlines}.
+    news: String,
+    // Has {duration}.
+    timer: String,
+    // Has {day} and {time}.
+    alarm: String,
+    // Has {text}, {day} and {time}.
+    reminder: String,
+    // Has {location}.
+    location_added: String,
+    failed: String,
+    // The questions asked when a command is missing something.
+    ask_duration: String,
+    ask_time: String,
+    // A chrono format string for the time of an alarm or reminder.
+    time_format: String,
+    today: String,
+    tomorrow: String,
+    // Starting from Monday, including whatever goes in front of them, like the "on" in "on
+    // Monday".
+    weekdays: Vec<String>,
+    // The singular and plural names of units, like ["hour", "hours"].
+    hours: [String; 2],
+    minutes: [String; 2],
+    seconds: [String; 2],
+    // Joins the last part of a list onto the rest.
+    and: String,
+}
+
+/// Fills in the `{name}` placeholders in a template.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+impl Replies {
+    pub fn init(language: Language) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut file = File::open(format!("replies_{}.json", language.code()))?;
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer)?;
+        Ok(serde_json::from_str(&buffer)?)
+    }
+
+    /// Describes the weather in the given temperature units, saying where it's for if a location
+    /// was asked about. That's the forecast when a day other than today was asked about and the
+    /// report has a forecast for it, and the current weather otherwise.
+    pub fn weather(
+        &self,
+        report: &WeatherReport,
+        location: Option<&str>,
+        date: Option<NaiveDate>,
+        now: &DateTime<Local>,
+        units: &TemperatureUnits,
+    ) -> String {
+        let today = now.date().naive_local();
+        let forecast = date
+            .filter(|date| *date != today)
+            .and_then(|date| report.forecast_for(date));
+        if let Some(forecast) = forecast {
+            let template = match location {
+                Some(_) => &self.forecast_in,
+                None => &self.forecast,
+            };
+            let temp = |temp: f32| (units.convert_celsius(temp).round() as i32).to_string();
+            return fill(
+                template,
+                &[
+                    ("location", location.unwrap_or_default()),
+                    ("day", &self.day(forecast.date, today)),
+                    ("low", &temp(forecast.min_temp)),
+                    ("high", &temp(forecast.max_temp)),
+                    ("description", &forecast.description),
+                ],
+            );
+        }
+
+        let temp = units.convert_celsius(report.current_weather.temp);
+        let template = match location {
+            Some(_) => &self.weather_in,
+            None => &self.weather,
+        };
+        fill(
+            template,
+            &[
+                ("location", location.unwrap_or_default()),
+                ("temp", &(temp.round() as i32).to_string()),
+                ("description", &report.current_weather.description),
+            ],
+        )
+    }
+
+    pub fn news(&self, news: &[NewsItem]) -> String {
+        let headlines: Vec<&str> = news
+            .iter()
+            .filter_map(|item| item.title.as_deref())
+            .take(HEADLINE_COUNT)
+            .collect();
+        fill(&self.news, &[("headlines", &headlines.join(". "))])
+    }
+
+    pub fn timer(&self, duration: Duration) -> String {
+        fill(&self.timer, &[("duration", &self.duration(duration))])
+    }
+
+    pub fn alarm(&self, at: &DateTime<Local>, now: &DateTime<Local>) -> String {
+        fill(
+            &self.alarm,
+            &[("day", &self.day_of(at, now)), ("time", &self.time(at))],
+        )
+    }
+
+    pub fn reminder(&self, at: &DateTime<Local>, text: &str, now: &DateTime<Local>) -> String {
+        fill(
+            &self.reminder,
+            &[
+                ("text", text),
+                ("day", &self.day_of(at, now)),
+                ("time", &self.time(at)),
+            ],
+        )
+    }
+
+    pub fn location_added(&self, location: &str) -> String {
+        fill(&self.location_added, &[("location", location)])
+    }
+
+    pub fn failed(&self) -> String {
+        self.failed.clone()
+    }
+
+    pub fn question(&self, question: Question) -> String {
+        match question {
+            Question::Duration => self.ask_duration.clone(),
+            Question::Time => self.ask_time.clone(),
+        }
+    }
+
+    /// Spells out a duration like "1 hour and 30 minutes".
+    fn duration(&self, duration: Duration) -> String {
+        let secs = duration.as_secs();
+        let parts: Vec<String> = [
+            (secs / 3600, &self.hours),
+            (secs / 60 % 60, &self.minutes),
+            (secs % 60, &self.seconds),
+        ]
+        .iter()
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, names)| {
+            let name = if *amount == 1 { &names[0] } else { &names[1] };
+            format!("{} {}", amount, name)
+        })
+        .collect();
+        match parts.split_last() {
+            Some((last, rest)) if !rest.is_empty() => {
+                format!("{} {} {}", rest.join(", "), self.and, last)
+            }
+            Some((last, _)) => last.clone(),
+            None => format!("0 {}", self.seconds[1]),
+        }
+    }
+
+    fn day(&self, date: NaiveDate, today: NaiveDate) -> String {
+        match (date - today).num_days() {
+            0 => self.today.clone(),
+            1 => self.tomorrow.clone(),
+            _ => self.weekdays[date.weekday().num_days_from_monday() as usize].clone(),
+        }
+    }
+
+    fn day_of(&self, at: &DateTime<Local>, now: &DateTime<Local>) -> String {
+        self.day(at.date().naive_local(), now.date().naive_local())
+    }
+
+    fn time(&self, at: &DateTime<Local>) -> String {
+        at.format(&self.time_format).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// A report with the given current weather and a forecast for Saturday the 16th of March 2024
+    /// of 2 to 9 degrees and light snow.
+    fn weather_report(temp: f32, description: &str) -> WeatherReport {
+        serde_json::from_value(serde_json::json!({
+            "current_weather": {
+                "temp": temp,
+                "feels_like": temp,
+                "humidity": 80.0,
+                "pressure": 1013.0,
+                "dew_point": 5.0,
+                "uv_index": 0.0,
+                "cloudiness": 100.0,
+                "wind_speed": 3.0,
+                "wind_direction": 180.0,
+                "condition_code": 500,
+                "icon": "10d",
+                "description": description,
+            },
+            "forecast": [{
+                "date": "2024-03-16",
+                "min_temp": 1.6,
+                "max_temp": 8.7,
+                "humidity": 70.0,
+                "rain_chance": 0.6,
+                "cloudiness": 90.0,
+                "description": "light snow",
+                "wind_speed": 4.0,
+                "wind_direction": 270.0,
+                "condition_code": 600,
+                "icon": "13d",
+                "moon_phase": 0.2,
+            }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_english_replies() {
+        let replies = Replies::init(Language::English).expect("No replies file found");
+        // Friday the 15th of March 2024 at 8:15am.
+        let now = Local.ymd(2024, 3, 15).and_hms(8, 15, 0);
+        let at =
+            |day: u32, hour: u32, minute: u32| Local.ymd(2024, 3, day).and_hms(hour, minute, 0);
+        let celsius = TemperatureUnits::Celsius;
+
+        assert_eq!(
+            replies.weather(
+                &weather_report(17.6, "light rain"),
+                None,
+                None,
+                &now,
+                &celsius
+            ),
+            "It's 18 degrees and light rain."
+        );
+        assert_eq!(
+            replies.weather(
+                &weather_report(17.6, "light rain"),
+                None,
+                None,
+                &now,
+                &TemperatureUnits::Fahrenheit
+            ),
+            "It's 64 degrees and light rain."
+        );
+        assert_eq!(
+            replies.weather(
+                &weather_report(9.0, "overcast clouds"),
+                Some("Cabin"),
+                None,
+                &now,
+                &celsius
+            ),
+            "In Cabin it's 9 degrees and overcast clouds."
+        );
+        let saturday = NaiveDate::from_ymd(2024, 3, 16);
+        assert_eq!(
+            replies.weather(
+                &weather_report(9.0, "overcast clouds"),
+                None,
+                Some(saturday),
+                &now,
+                &celsius
+            ),
+            "It'll be 2 to 9 degrees and light snow tomorrow."
+        );
+        assert_eq!(
+            replies.weather(
+                &weather_report(9.0, "overcast clouds"),
+                Some("Cabin"),
+                Some(saturday),
+                &(now - chrono::Duration::days(2)),
+                &celsius
+            ),
+            "In Cabin it'll be 2 to 9 degrees and light snow on Saturday."
+        );
+        // Today, or a day there's no forecast for, gets the current weather.
+        assert_eq!(
+            replies.weather(
+                &weather_report(9.0, "overcast clouds"),
+                None,
+                Some(now.date().naive_local()),
+                &now,
+                &celsius
+            ),
+            "It's 9 degrees and overcast clouds."
+        );
+        assert_eq!(
+            replies.weather(
+                &weather_report(9.0, "overcast clouds"),
+                None,
+                Some(NaiveDate::from_ymd(2024, 4, 1)),
+                &now,
+                &celsius
+            ),
+            "It's 9 degrees and overcast clouds."
+        );
+        assert_eq!(
+            replies.timer(Duration::from_secs(5 * 60)),
+            "Timer set for 5 minutes."
+        );
+        assert_eq!(
+            replies.timer(Duration::from_secs(60 * 60 + 30 * 60 + 1)),
+            "Timer set for 1 hour, 30 minutes and 1 second."
+        );
+        assert_eq!(
+            replies.alarm(&at(15, 19, 30), &now),
+            "Alarm set for 7:30 PM today."
+        );
+        assert_eq!(
+            replies.alarm(&at(16, 7, 0), &now),
+            "Alarm set for 7:00 AM tomorrow."
+        );
+        assert_eq!(
+            replies.reminder(&at(19, 9, 0), "call mom", &now),
+            "I'll remind you to call mom on Tuesday at 9:00 AM."
+        );
+        assert_eq!(replies.question(Question::Duration), "For how long?");
+    }
+
+    #[test]
+    fn test_german_replies() {
+        let replies = Replies::init(Language::German).expect("No replies file found");
+        let now = Local.ymd(2024, 3, 15).and_hms(8, 15, 0);
+
+        assert_eq!(
+            replies.weather(
+                &weather_report(-2.2, "Schneefall"),
+                None,
+                None,
+                &now,
+                &TemperatureUnits::Celsius
+            ),
+            "Es sind -2 Grad, Schneefall."
+        );
+        assert_eq!(
+            replies.weather(
+                &weather_report(-2.2, "Schneefall"),
+                None,
+                Some(NaiveDate::from_ymd(2024, 3, 16)),
+                &now,
+                &TemperatureUnits::Celsius
+            ),
+            "Es werden morgen 2 bis 9 Grad, light snow."
+        );
+        assert_eq!(
+            replies.timer(Duration::from_secs(90)),
+            "Timer für 1 Minute und 30 Sekunden gestellt."
+        );
+        assert_eq!(
+            replies.alarm(&Local.ymd(2024, 3, 16).and_hms(7, 30, 0), &now),
+            "Der Wecker klingelt morgen um 07:30 Uhr."
+        );
+        assert_eq!(replies.question(Question::Time), "Für wann?");
+    }
+}
@@ -0,0 +1,547 @@
+use super::number::NumberParser;
+use crate::settings::Language;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Weekday};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs::File, io::Read};
+
+/// When someone gives us a day without a time, like "remind me tomorrow to call mom", this is the
+/// time we go with.
+const DEFAULT_HOUR: u32 = 9;
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+/// A day relative to today.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Day {
+    Today,
+    Tomorrow,
+    // The next time this day of the week comes around, which could be today.
+    Weekday(Weekday),
+    // "next Tuesday", which is never today.
+    NextWeekday(Weekday),
+}
+
+impl Day {
+    /// The date this day falls on, counting from the given date.
+    pub fn date(&self, today: NaiveDate) -> NaiveDate {
+        let days_until = |weekday: Weekday| {
+            (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7
+        };
+        let days = match self {
+            Day::Today => 0,
+            Day::Tomorrow => 1,
+            Day::Weekday(weekday) => days_until(*weekday),
+            Day::NextWeekday(weekday) => match days_until(*weekday) {
+                0 => 7,
+                days => days,
+            },
+        };
+        today + chrono::Duration::days(days as i64)
+    }
+}
+
+/// A time of day as it was said.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TimeOfDay {
+    pub time: NaiveTime,
+    // Whether it's unclear if this is in the morning or the evening, like "seven thirty". The time
+    // is always the morning one in that case.
+    pub ambiguous: bool,
+}
+
+enum Meridiem {
+    Am,
+    Pm,
+}
+
+/// The words for days, times of day and durations in one language, which are loaded from the
+/// times_{LANG}.json file. Anything listed as a phrase can be more than one word, like "in the
+/// morning".
+#[derive(Deserialize)]
+pub struct TimeWords {
+    // How many seconds each unit of time is.
+    units: HashMap<String, f64>,
+    // Words for fractions of a unit along with what they divide it by.
+    fractions: HashMap<String, f64>,
+    // Words that join a fraction onto its unit, like the "of" in "a quarter of an hour".
+    #[serde(default)]
+    fraction_of: Vec<String>,
+    // Phrases said after a time to say it's in the morning or the afternoon and evening.
+    am: Vec<String>,
+    pm: Vec<String>,
+    // Words for the hours that have names.
+    noon: Vec<String>,
+    midnight: Vec<String>,
+    // Said after the hour, like "o'clock". The minutes can come after it, like in the German
+    // "sieben uhr dreißig".
+    oclock: Vec<String>,
+    // Said before an hour to mean half an hour before it, like the German "halb acht".
+    #[serde(default)]
+    half_before: Vec<String>,
+    // Said between the minutes and the hour they're past or to, like "ten past six".
+    past: Vec<String>,
+    to: Vec<String>,
+    // Phrases that stand in for a number of minutes past or to the hour, like "quarter".
+    minute_offsets: HashMap<String, u32>,
+    // Words for minutes that can come after how many of them there are, like "ten minutes to
+    // eight".
+    minutes: Vec<String>,
+    today: Vec<String>,
+    tomorrow: Vec<String>,
+    // Said before a weekday to mean the next one that isn't today, like "next".
+    next: Vec<String>,
+    // Said before a weekday to mean the next one, which could be today, like "on".
+    this: Vec<String>,
+    // The names of each day of the week, starting from Monday.
+    weekdays: Vec<Vec<String>>,
+}
+
+/// Whether the words are exactly the phrase.
+fn is_phrase(words: &[&str], phrase: &str) -> bool {
+    phrase.split_whitespace().eq(words.iter().copied())
+}
+
+/// Whether the words are exactly one of the phrases.
+fn is_any_phrase(words: &[&str], phrases: &[String]) -> bool {
+    phrases.iter().any(|phrase| is_phrase(words, phrase))
+}
+
+/// The words before whichever of the phrases they end with, if they end with one.
+fn strip_phrase<'a, 'b>(words: &'a [&'b str], phrases: &[String]) -> Option<&'a [&'b str]> {
+    phrases.iter().find_map(|phrase| {
+        let len = phrase.split_whitespace().count();
+        let split = words.len().checked_sub(len)?;
+        if is_phrase(&words[split..], phrase) {
+            Some(&words[..split])
+        } else {
+            None
+        }
+    })
+}
+
+impl TimeWords {
+    pub fn init(language: Language) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut file = File::open(format!("times_{}.json", language.code()))?;
+        let mut data = String::new();
+        file.read_to_string(&mut data)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// How many seconds a unit of time is, if the word is one.
+    pub fn unit_seconds(&self, word: &str) -> Option<f64> {
+        self.units.get(word).copied()
+    }
+
+    /// What a fraction of a unit divides it by, if the word is one.
+    pub fn denominator(&self, word: &str) -> Option<f64> {
+        self.fractions.get(word).copied()
+    }
+
+    /// Whether the word joins a fraction onto its unit.
+    pub fn is_fraction_of(&self, word: &str) -> bool {
+        self.fraction_of.iter().any(|of| of == word)
+    }
+
+    /// The hour the words name, like 12 for "noon".
+    fn named_hour(&self, words: &[&str]) -> Option<u32> {
+        if is_any_phrase(words, &self.noon) {
+            Some(12)
+        } else if is_any_phrase(words, &self.midnight) {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn weekday(&self, word: &str) -> Option<Weekday> {
+        self.weekdays
+            .iter()
+            .zip(&WEEKDAYS)
+            .find(|(names, _)| names.iter().any(|name| name == word))
+            .map(|(_, weekday)| *weekday)
+    }
+}
+
+/// Parses a small number that's either spoken or written out in digits.
+fn parse_small(words: &[&str], numbers: &NumberParser) -> Option<u32> {
+    match words {
+        [word] if word.chars().all(|c| c.is_ascii_digit()) => word.parse().ok(),
+        _ => numbers.parse_number_from_words(words).and_then(|number| {
+            if (0..=1000).contains(&number) {
+                Some(number as u32)
+            } else {
+                None
+            }
+        }),
+    }
+}
+
+/// Parses the hour that a time like "quarter past six" is relative to.
+fn parse_hour(words: &[&str], numbers: &NumberParser, times: &TimeWords) -> Option<u32> {
+    times
+        .named_hour(words)
+        .or_else(|| parse_small(words, numbers).filter(|hour| *hour < 24))
+}
+
+/// Parses how many minutes past or to the hour a time is, like the "quarter" in "quarter past
+/// six" or the "ten minutes" in "ten minutes to eight".
+fn parse_minutes_offset(words: &[&str], numbers: &NumberParser, times: &TimeWords) -> Option<u32> {
+    let words = strip_phrase(words, &times.minutes).unwrap_or(words);
+    times
+        .minute_offsets
+        .iter()
+        .find(|(phrase, _)| is_phrase(words, phrase))
+        .map(|(_, minutes)| *minutes)
+        .or_else(|| parse_small(words, numbers).filter(|minutes| (1..60).contains(minutes)))
+}
+
+/// Parses a spoken time of day out of exactly the given words, such as "seven thirty", "nine am",
+/// "quarter past six in the evening", "ten to eight" or "noon", or however they're said in the
+/// language of the time words, like "halb acht" and "viertel nach sechs" in German.
+pub fn parse_time_of_day(
+    words: &[&str],
+    numbers: &NumberParser,
+    times: &TimeWords,
+) -> Option<TimeOfDay> {
+    let (words, meridiem) = if let Some(rest) = strip_phrase(words, &times.am) {
+        (rest, Some(Meridiem::Am))
+    } else if let Some(rest) = strip_phrase(words, &times.pm) {
+        (rest, Some(Meridiem::Pm))
+    } else {
+        (words, None)
+    };
+
+    let named = times.named_hour(words);
+    let (hour, minute) = match words {
+        _ if named.is_some() && meridiem.is_none() => {
+            return NaiveTime::from_hms_opt(named?, 0, 0).map(|time| TimeOfDay {
+                time,
+                ambiguous: false,
+            })
+        }
+        [time] if time.contains(':') => {
+            let mut parts = time.splitn(2, ':');
+            let hour = parts.next()?.parse().ok()?;
+            let minute = parts.next()?.parse().ok()?;
+            (hour, minute)
+        }
+        [hour, oclock, minutes @ ..] if times.oclock.iter().any(|word| word == oclock) => (
+            parse_hour(&[hour], numbers, times)?,
+            if minutes.is_empty() {
+                0
+            } else {
+                parse_small(minutes, numbers)? as i64
+            },
+        ),
+        // "halb acht" is half way to eight rather than half past it.
+        [half, hour @ ..] if times.half_before.iter().any(|word| word == half) => {
+            (parse_hour(hour, numbers, times)?, -30)
+        }
+        [hour, zero, minute] if numbers.is_zero_word(zero) => (
+            parse_hour(&[hour], numbers, times)?,
+            parse_small(&[minute], numbers)? as i64,
+        ),
+        _ => {
+            // "quarter past six" or "ten to eight".
+            let past = |word: &&str| times.past.iter().any(|past| past == word);
+            let to = |word: &&str| times.to.iter().any(|to| to == word);
+            match words.iter().position(|word| past(word) || to(word)) {
+                Some(pos) => {
+                    let minutes = parse_minutes_offset(&words[..pos], numbers, times)? as i64;
+                    let hour = parse_hour(&words[pos + 1..], numbers, times)?;
+                    if past(&words[pos]) {
+                        (hour, minutes)
+                    } else {
+                        (hour, -minutes)
+                    }
+                }
+                // Otherwise it's an hour optionally followed by the minutes, like "seven thirty".
+                None => {
+                    let (hour, minutes) = words.split_first()?;
+                    let hour = parse_hour(&[hour], numbers, times)?;
+                    let minute = if minutes.is_empty() {
+                        0
+                    } else {
+                        parse_small(minutes, numbers)?
+                    };
+                    (hour, minute as i64)
+                }
+            }
+        }
+    };
+
+    if hour >= 24 || minute >= 60 {
+        return None;
+    }
+    let named = words.iter().any(|word| times.named_hour(&[word]).is_some());
+    let (hour, ambiguous) = match meridiem {
+        Some(_) if named || hour == 0 || hour > 12 => return None,
+        Some(Meridiem::Am) => (hour % 12, false),
+        Some(Meridiem::Pm) => (hour % 12 + 12, false),
+        None => (hour, !named && (1..=12).contains(&hour)),
+    };
+    // Noon and midnight said as "twelve" are just as ambiguous, but the morning time for twelve is
+    // midnight so flip it around.
+    let hour = if ambiguous && hour == 12 { 0 } else { hour };
+    let time = NaiveTime::from_hms_opt(hour, 0, 0)? + chrono::Duration::minutes(minute);
+    Some(TimeOfDay { time, ambiguous })
+}
+
+/// Parses a spoken day out of exactly the given words, such as "tomorrow", "on Tuesday" or "next
+/// Tuesday".
+pub fn parse_day(words: &[&str], times: &TimeWords) -> Option<Day> {
+    let is = |word: &str, words: &[String]| words.iter().any(|other| other == word);
+    let day = match words {
+        _ if is_any_phrase(words, &times.today) => Day::Today,
+        _ if is_any_phrase(words, &times.tomorrow) => Day::Tomorrow,
+        [next, weekday] if is(next, &times.next) => Day::NextWeekday(times.weekday(weekday)?),
+        [this, weekday] if is(this, &times.this) => Day::Weekday(times.weekday(weekday)?),
+        [weekday] => Day::Weekday(times.weekday(weekday)?),
+        _ => return None,
+    };
+    Some(day)
+}
+
+/// Works out exactly when a day and time refer to, relative to now. Times without a day are the
+/// next time that time comes around, and times that could be either the morning or the evening
+/// are whichever comes first. Returns None if there's neither a day nor a time, or the time
+/// doesn't exist on that day (thanks to daylight savings).
+pub fn resolve<Tz: TimeZone>(
+    day: Option<Day>,
+    time: Option<TimeOfDay>,
+    now: &DateTime<Tz>,
+) -> Option<DateTime<Tz>> {
+    let time = match (day, time) {
+        (_, Some(time)) => time,
+        (Some(_), None) => TimeOfDay {
+            time: NaiveTime::from_hms(DEFAULT_HOUR, 0, 0),
+            ambiguous: false,
+        },
+        (None, None) => return None,
+    };
+
+    let mut candidates = vec![time.time];
+    if time.ambiguous {
+        candidates.push(time.time + chrono::Duration::hours(12));
+    }
+    let at = |date: NaiveDate, time: &NaiveTime| {
+        now.timezone()
+            .from_local_datetime(&date.and_time(*time))
+            .earliest()
+    };
+    // The first of the candidate times on the given day that's still to come.
+    let upcoming = |date: NaiveDate| {
+        candidates
+            .iter()
+            .filter_map(|time| at(date, time))
+            .find(|at| at > now)
+    };
+
+    let today = now.naive_local().date();
+    let in_days = |days: i64| today + chrono::Duration::days(days);
+
+    match day {
+        None => upcoming(today).or_else(|| upcoming(in_days(1))),
+        Some(Day::Weekday(weekday)) if weekday == today.weekday() => {
+            upcoming(today).or_else(|| at(in_days(7), &candidates[0]))
+        }
+        Some(day) => {
+            let date = day.date(today);
+            upcoming(date).or_else(|| at(date, &candidates[0]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    fn parse_time_in(language: Language, text: &str) -> Option<TimeOfDay> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let numbers = NumberParser::init(language).expect("No numbers file found");
+        let times = TimeWords::init(language).expect("No times file found");
+        parse_time_of_day(&words, &numbers, &times)
+    }
+
+    fn time(text: &str) -> Option<NaiveTime> {
+        parse_time_in(Language::English, text).map(|time| time.time)
+    }
+
+    fn german_time(text: &str) -> Option<NaiveTime> {
+        parse_time_in(Language::German, text).map(|time| time.time)
+    }
+
+    fn hms(hour: u32, minute: u32) -> Option<NaiveTime> {
+        Some(NaiveTime::from_hms(hour, minute, 0))
+    }
+
+    fn parse_day_in(language: Language, text: &str) -> Option<Day> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        parse_day(
+            &words,
+            &TimeWords::init(language).expect("No times file found"),
+        )
+    }
+
+    fn day(text: &str) -> Option<Day> {
+        parse_day_in(Language::English, text)
+    }
+
+    // Friday the 15th of March 2024 at 8:15am.
+    fn now() -> DateTime<Utc> {
+        Utc.ymd(2024, 3, 15).and_hms(8, 15, 0)
+    }
+
+    fn resolve_text(day_text: &str, time_text: &str) -> Option<DateTime<Utc>> {
+        resolve(
+            if day_text.is_empty() {
+                None
+            } else {
+                day(day_text)
+            },
+            parse_time_in(Language::English, time_text),
+            &now(),
+        )
+    }
+
+    #[test]
+    fn test_times() {
+        assert_eq!(time("seven"), hms(7, 0));
+        assert_eq!(time("seven o'clock"), hms(7, 0));
+        assert_eq!(time("seven thirty"), hms(7, 30));
+        assert_eq!(time("seven oh five"), hms(7, 5));
+        assert_eq!(time("eleven fifty five"), hms(11, 55));
+        assert_eq!(time("seven thirty pm"), hms(19, 30));
+        assert_eq!(time("nine a m"), hms(9, 0));
+        assert_eq!(time("twelve pm"), hms(12, 0));
+        assert_eq!(time("twelve am"), hms(0, 0));
+        assert_eq!(time("twelve"), hms(0, 0));
+        assert_eq!(time("noon"), hms(12, 0));
+        assert_eq!(time("midnight"), hms(0, 0));
+        assert_eq!(time("9"), hms(9, 0));
+        assert_eq!(time("9:30"), hms(9, 30));
+        assert_eq!(time("17:45"), hms(17, 45));
+        assert_eq!(time("eighteen"), hms(18, 0));
+    }
+
+    #[test]
+    fn test_relative_times() {
+        assert_eq!(time("quarter past six"), hms(6, 15));
+        assert_eq!(time("quarter past six pm"), hms(18, 15));
+        assert_eq!(time("a quarter past six in the evening"), hms(18, 15));
+        assert_eq!(time("half past seven"), hms(7, 30));
+        assert_eq!(time("ten past six"), hms(6, 10));
+        assert_eq!(time("twenty five minutes past two"), hms(2, 25));
+        assert_eq!(time("quarter to eight"), hms(7, 45));
+        assert_eq!(time("ten to eight in the evening"), hms(19, 50));
+        assert_eq!(time("quarter to twelve"), hms(23, 45));
+        assert_eq!(time("five to midnight"), hms(23, 55));
+        assert_eq!(time("quarter past noon"), hms(12, 15));
+    }
+
+    #[test]
+    fn test_german_times() {
+        assert_eq!(german_time("sieben uhr"), hms(7, 0));
+        assert_eq!(german_time("sieben uhr dreißig"), hms(7, 30));
+        assert_eq!(german_time("achtzehn uhr fünfzehn"), hms(18, 15));
+        assert_eq!(german_time("halb acht"), hms(7, 30));
+        assert_eq!(german_time("halb acht abends"), hms(19, 30));
+        assert_eq!(german_time("viertel nach sechs"), hms(6, 15));
+        assert_eq!(german_time("zehn vor acht"), hms(7, 50));
+        assert_eq!(german_time("mittag"), hms(12, 0));
+        assert_eq!(german_time("halb acht pm"), None);
+        assert_eq!(time("halb acht"), None);
+    }
+
+    #[test]
+    fn test_not_times() {
+        assert_eq!(time(""), None);
+        assert_eq!(time("seven thirty bananas"), None);
+        assert_eq!(time("seven seventy"), None);
+        assert_eq!(time("eighteen pm"), None);
+        assert_eq!(time("sixty past six"), None);
+        assert_eq!(time("noon pm"), None);
+    }
+
+    #[test]
+    fn test_ambiguity() {
+        let ambiguous = |text: &str| parse_time_in(Language::English, text).unwrap().ambiguous;
+        assert!(ambiguous("seven thirty"));
+        assert!(ambiguous("quarter past six"));
+        assert!(!ambiguous("seven thirty am"));
+        assert!(!ambiguous("seventeen thirty"));
+        assert!(!ambiguous("noon"));
+    }
+
+    #[test]
+    fn test_days() {
+        assert_eq!(day("today"), Some(Day::Today));
+        assert_eq!(day("tomorrow"), Some(Day::Tomorrow));
+        assert_eq!(day("tuesday"), Some(Day::Weekday(Weekday::Tue)));
+        assert_eq!(day("on tuesday"), Some(Day::Weekday(Weekday::Tue)));
+        assert_eq!(day("next tuesday"), Some(Day::NextWeekday(Weekday::Tue)));
+        assert_eq!(day("sunday"), Some(Day::Weekday(Weekday::Sun)));
+        assert_eq!(day("next"), None);
+        assert_eq!(day("yesterday"), None);
+        assert_eq!(day("morgen"), None);
+
+        let german_day = |text| parse_day_in(Language::German, text);
+        assert_eq!(german_day("morgen"), Some(Day::Tomorrow));
+        assert_eq!(german_day("am dienstag"), Some(Day::Weekday(Weekday::Tue)));
+        assert_eq!(
+            german_day("nächsten dienstag"),
+            Some(Day::NextWeekday(Weekday::Tue))
+        );
+        assert_eq!(german_day("sonnabend"), Some(Day::Weekday(Weekday::Sat)));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let at =
+            |day: u32, hour: u32, minute: u32| Some(Utc.ymd(2024, 3, day).and_hms(hour, minute, 0));
+
+        // Later this morning, then this evening once the morning time has passed, then tomorrow
+        // morning once both have.
+        assert_eq!(resolve_text("", "nine"), at(15, 9, 0));
+        assert_eq!(resolve_text("", "seven thirty"), at(15, 19, 30));
+        assert_eq!(resolve_text("", "eight fifteen"), at(15, 20, 15));
+        assert_eq!(resolve_text("", "seven am"), at(16, 7, 0));
+        assert_eq!(resolve_text("", "quarter past six pm"), at(15, 18, 15));
+
+        assert_eq!(resolve_text("today", "noon"), at(15, 12, 0));
+        assert_eq!(resolve_text("tomorrow", "noon"), at(16, 12, 0));
+        assert_eq!(resolve_text("tomorrow", "seven"), at(16, 7, 0));
+        assert_eq!(resolve_text("tomorrow", ""), at(16, 9, 0));
+        assert_eq!(resolve_text("tuesday", "nine"), at(19, 9, 0));
+        assert_eq!(resolve_text("next tuesday", "9"), at(19, 9, 0));
+
+        // It's Friday, so "Friday" is today as long as the time hasn't passed yet.
+        assert_eq!(resolve_text("friday", "nine"), at(15, 9, 0));
+        assert_eq!(resolve_text("friday", "eight am"), at(22, 8, 0));
+        assert_eq!(resolve_text("next friday", "nine"), at(22, 9, 0));
+
+        assert_eq!(resolve_text("", ""), None);
+    }
+
+    #[test]
+    fn test_resolve_keeps_timezone() {
+        let now = FixedOffset::west(5 * 60 * 60)
+            .ymd(2024, 3, 15)
+            .and_hms(22, 0, 0);
+        let time = parse_time_in(Language::English, "seven");
+        let resolved = resolve(None, time, &now).unwrap();
+        assert_eq!(
+            resolved.naive_local(),
+            NaiveDate::from_ymd(2024, 3, 16).and_hms(7, 0, 0)
+        );
+    }
+}
@@ -0,0 +1,403 @@
+use crate::settings::{Language, TtsSettings, SETTINGS};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crossbeam::channel::{bounded, Sender};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Cursor, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long after speech finishes playing the tablet still counts as speaking, so the microphone
+/// doesn't pick up the end of a reply echoing around the room.
+const SPEECH_TAIL: Duration = Duration::from_millis(500);
+
+lazy_static! {
+    // Only one thing gets said at a time so replies don't talk over each other.
+    static ref SPEAKING: Mutex<()> = Mutex::new(());
+    static ref PLAYBACK: Mutex<Playback> = Mutex::new(Playback::default());
+}
+
+/// Whether something is being said, and when the last thing said finished.
+#[derive(Default)]
+struct Playback {
+    playing: bool,
+    finished_at: Option<Instant>,
+}
+
+impl Playback {
+    fn start(&mut self) {
+        self.playing = true;
+    }
+
+    fn finish(&mut self, now: Instant) {
+        self.playing = false;
+        self.finished_at = Some(now);
+    }
+
+    fn is_speaking(&self, now: Instant) -> bool {
+        let in_tail = match self.finished_at {
+            Some(finished_at) => now.duration_since(finished_at) < SPEECH_TAIL,
+            None => false,
+        };
+        self.playing || in_tail
+    }
+}
+
+/// Whether the tablet is saying something, or only just finished saying something. Whatever the
+/// microphone hears in the meantime is most likely the tablet itself, so it should be ignored.
+pub fn is_speaking() -> bool {
+    PLAYBACK.lock().unwrap().is_speaking(Instant::now())
+}
+
+/// The text-to-speech engines we know how to drive. Both are separate programs that need to be
+/// installed and on the path.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TtsEngineKind {
+    // https://github.com/espeak-ng/espeak-ng
+    EspeakNg,
+    // https://github.com/rhasspy/piper
+    Piper,
+}
+
+impl TtsEngineKind {
+    /// Sets up the engine to speak with the given voice, or the default voice for the language if
+    /// the engine has one.
+    pub fn engine(
+        &self,
+        voice: Option<&str>,
+        language: Language,
+    ) -> Result<Box<dyn TtsEngine>, Box<dyn std::error::Error>> {
+        let engine: Box<dyn TtsEngine> = match self {
+            TtsEngineKind::EspeakNg => Box::new(EspeakNg {
+                voice: voice.unwrap_or_else(|| language.code()).to_string(),
+            }),
+            TtsEngineKind::Piper => Box::new(Piper {
+                model: voice.ok_or("piper needs a voice model to be set")?.into(),
+            }),
+        };
+        Ok(engine)
+    }
+}
+
+/// Mono audio of something being said.
+pub struct Speech {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+/// Something that can turn text into speech.
+pub trait TtsEngine {
+    fn synthesize(&self, text: &str) -> Result<Speech, Box<dyn std::error::Error>>;
+}
+
+pub struct EspeakNg {
+    voice: String,
+}
+
+impl TtsEngine for EspeakNg {
+    fn synthesize(&self, text: &str) -> Result<Speech, Box<dyn std::error::Error>> {
+        let output = Command::new("espeak-ng")
+            .arg("-v")
+            .arg(&self.voice)
+            .arg("--stdout")
+            // So text starting with a dash isn't taken for an option.
+            .arg("--")
+            .arg(text)
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "espeak-ng failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        read_wav(Cursor::new(output.stdout))
+    }
+}
+
+pub struct Piper {
+    model: PathBuf,
+}
+
+// Numbers Piper's temporary files, so things being synthesized at the same time don't overwrite
+// each other's audio.
+static PIPER_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary file that's removed when it's dropped, whether or not anything was ever written to
+/// it.
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // It's only a temporary file, so there's nothing worth doing if it can't be removed.
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+impl TtsEngine for Piper {
+    fn synthesize(&self, text: &str) -> Result<Speech, Box<dyn std::error::Error>> {
+        // Piper can only write WAV files to disk; to stdout it only gives raw audio without the
+        // sample rate, so go through a temporary file.
+        let wav_file = TempFile(std::env::temp_dir().join(format!(
+            "smart_tablet_tts_{}_{}.wav",
+            std::process::id(),
+            PIPER_FILE_COUNT.fetch_add(1, Ordering::Relaxed)
+        )));
+        let mut child = Command::new("piper")
+            .arg("--model")
+            .arg(&self.model)
+            .arg("--output_file")
+            .arg(&wav_file.0)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        child
+            .stdin
+            .take()
+            .ok_or("couldn't write to piper")?
+            .write_all(text.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "piper failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        read_wav(std::fs::File::open(&wav_file.0)?)
+    }
+}
+
+/// Reads 16-bit PCM speech out of a WAV file, mixing it down to mono.
+fn read_wav<R: Read>(reader: R) -> Result<Speech, Box<dyn std::error::Error>> {
+    let mut reader = hound::WavReader::new(reader)?;
+    let spec = reader.spec();
+    if spec.bits_per_sample != 16 || spec.sample_format != hound::SampleFormat::Int {
+        return Err(format!("speech must be 16-bit PCM, got {:?}", spec).into());
+    }
+
+    // espeak-ng doesn't know how long the audio will be when it writes the header to stdout, so
+    // the header claims there's more audio than there is. Take whatever is actually there.
+    let mut samples = vec![];
+    for samp in reader.samples::<i16>() {
+        match samp {
+            Ok(samp) => samples.push(cpal::Sample::to_f32(&samp)),
+            Err(_) => break,
+        }
+    }
+    let channels = spec.channels.max(1) as usize;
+    let samples = samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok(Speech {
+        samples,
+        sample_rate: spec.sample_rate,
+    })
+}
+
+/// Linearly interpolates mono audio from one sample rate to another.
+fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || samples.is_empty() {
+        return samples.to_vec();
+    }
+    let step = from as f64 / to as f64;
+    let len = (samples.len() as f64 / step) as usize;
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let frac = (position - index as f64) as f32;
+            let next = samples.get(index + 1).copied().unwrap_or(samples[index]);
+            samples[index] * (1.0 - frac) + next * frac
+        })
+        .collect()
+}
+
+/// Plays speech through the system's default output device, blocking until it's done.
+fn play(speech: &Speech, volume: f32) -> Result<(), Box<dyn std::error::Error>> {
+    let host = cpal::default_host();
+    let device = host
+        .default_output_device()
+        .ok_or("no output device detected")?;
+    let config = device.default_output_config()?;
+    let sample_rate = config.sample_rate().0;
+
+    // The stream is stopped as soon as we've handed out the last sample, which can cut off
+    // whatever the device still has buffered. A bit of silence on the end gives it time to drain.
+    let mut samples = resample(&speech.samples, speech.sample_rate, sample_rate);
+    samples.resize(samples.len() + sample_rate as usize / 5, 0.0);
+    let samples = samples
+        .into_iter()
+        .map(move |samp| (samp * volume).clamp(-1.0, 1.0));
+
+    let (tx, rx) = bounded(1);
+    let stream = match config.sample_format() {
+        cpal::SampleFormat::I16 => {
+            build_output_stream::<i16, _>(&device, &config.config(), samples, tx)?
+        }
+        cpal::SampleFormat::U16 => {
+            build_output_stream::<u16, _>(&device, &config.config(), samples, tx)?
+        }
+        cpal::SampleFormat::F32 => {
+            build_output_stream::<f32, _>(&device, &config.config(), samples, tx)?
+        }
+    };
+    stream.play()?;
+    rx.recv()?;
+    Ok(())
+}
+
+/// Starts an output stream that plays the samples on every channel and lets us know once it's run
+/// out of them.
+fn build_output_stream<T, I>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut samples: I,
+    done: Sender<()>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::Sample,
+    I: Iterator<Item = f32> + Send + 'static,
+{
+    let channels = config.channels.max(1) as usize;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            for frame in data.chunks_mut(channels) {
+                let samp = match samples.next() {
+                    Some(samp) => samp,
+                    None => {
+                        let _ = done.try_send(());
+                        0.0
+                    }
+                };
+                for out in frame.iter_mut() {
+                    *out = cpal::Sample::from(&samp);
+                }
+            }
+        },
+        move |err| {
+            eprintln!("Error playing audio: {}", err);
+        },
+    )
+}
+
+/// Says the text out loud with the given settings, blocking until it's been said.
+pub fn say(
+    text: &str,
+    settings: &TtsSettings,
+    language: Language,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let engine = settings
+        .engine
+        .engine(settings.voice.as_deref(), language)?;
+    let speech = engine.synthesize(text)?;
+    let _speaking = SPEAKING.lock().unwrap();
+    PLAYBACK.lock().unwrap().start();
+    let result = play(&speech, settings.volume);
+    PLAYBACK.lock().unwrap().finish(Instant::now());
+    result
+}
+
+/// Says the text out loud on another thread using the current settings, if speech is turned on.
+pub fn say_in_background(text: String) {
+    let tts_settings;
+    let language;
+    {
+        let settings = SETTINGS.read().unwrap();
+        tts_settings = settings.voice_settings.tts_settings.clone();
+        language = settings.language;
+    }
+    if !tts_settings.enabled {
+        return;
+    }
+    thread::spawn(move || {
+        if let Err(e) = say(&text, &tts_settings, language) {
+            eprintln!("Couldn't say {:?}: {}", text, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_wav() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 22_050,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut cursor = Cursor::new(Vec::new());
+        {
+            let mut writer = hound::WavWriter::new(&mut cursor, spec).unwrap();
+            for samp in &[i16::MAX, 0, i16::MIN, i16::MIN, 0, 0] {
+                writer.write_sample(*samp).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+
+        // Stereo gets mixed down to mono.
+        let speech = read_wav(Cursor::new(cursor.into_inner())).unwrap();
+        assert_eq!(speech.sample_rate, 22_050);
+        assert_eq!(speech.samples.len(), 3);
+        assert!((speech.samples[0] - 0.5).abs() < 0.001);
+        assert!((speech.samples[1] + 1.0).abs() < 0.001);
+        assert_eq!(speech.samples[2], 0.0);
+    }
+
+    #[test]
+    fn test_temp_file() {
+        let path =
+            std::env::temp_dir().join(format!("smart_tablet_test_tts_{}.wav", std::process::id()));
+        std::fs::write(&path, b"speech").unwrap();
+        drop(TempFile(path.clone()));
+        assert!(!path.exists());
+
+        // Files that never got written are fine too.
+        drop(TempFile(path.clone()));
+    }
+
+    #[test]
+    fn test_playback() {
+        let start = Instant::now();
+        let mut playback = Playback::default();
+        assert!(!playback.is_speaking(start));
+
+        playback.start();
+        assert!(playback.is_speaking(start + Duration::from_secs(10)));
+
+        // The tail after the speech still counts, but not long after that.
+        let finished_at = start + Duration::from_secs(2);
+        playback.finish(finished_at);
+        assert!(playback.is_speaking(finished_at + Duration::from_millis(100)));
+        assert!(!playback.is_speaking(finished_at + SPEECH_TAIL));
+    }
+
+    #[test]
+    fn test_resample() {
+        let samples: Vec<f32> = (0..100).map(|x| x as f32 / 100.0).collect();
+        assert_eq!(resample(&samples, 16_000, 16_000), samples);
+
+        // Doubling the rate fills in the halfway points between samples.
+        let doubled = resample(&samples, 16_000, 32_000);
+        assert_eq!(doubled.len(), 200);
+        assert!((doubled[3] - 0.015).abs() < 0.0001);
+
+        let halved = resample(&samples, 32_000, 16_000);
+        assert_eq!(halved.len(), 50);
+        assert!((halved[10] - 0.2).abs() < 0.0001);
+    }
+}
@@ -0,0 +1,339 @@
+use super::audio::SAMPLE_RATE;
+use crate::settings::VadSettings;
+use serde::{Deserialize, Serialize};
+use webrtc_vad::Vad;
+
+/// The length of the frames handed to voice activity detection. webrtc-vad only accepts 10, 20 or
+/// 30ms frames, so we go with the shortest one (10ms).
+const FRAME_LEN: usize = (SAMPLE_RATE / 100) as usize;
+
+/// How quickly the noise floor follows changes in the ambient noise, in milliseconds. This is long
+/// enough that a cough or a door closing doesn't throw it off, but short enough that turning on a
+/// fan is accounted for within a few seconds.
+const NOISE_FLOOR_TIME_CONSTANT_MS: f32 = 2000.0;
+
+/// How picky webrtc-vad is about what it considers to be speech, from least to most aggressive
+/// about filtering out things that aren't speech.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum VadAggressiveness {
+    Quality,
+    LowBitrate,
+    Aggressive,
+    VeryAggressive,
+}
+
+impl From<VadAggressiveness> for webrtc_vad::VadMode {
+    fn from(mode: VadAggressiveness) -> Self {
+        match mode {
+            VadAggressiveness::Quality => webrtc_vad::VadMode::Quality,
+            VadAggressiveness::LowBitrate => webrtc_vad::VadMode::LowBitrate,
+            VadAggressiveness::Aggressive => webrtc_vad::VadMode::Aggressive,
+            VadAggressiveness::VeryAggressive => webrtc_vad::VadMode::VeryAggressive,
+        }
+    }
+}
+
+/// Something that can decide whether a 10ms frame of audio contains speech.
+pub trait SpeechClassifier {
+    fn is_speech(&mut self, frame: &[i16]) -> bool;
+}
+
+impl SpeechClassifier for Vad {
+    fn is_speech(&mut self, frame: &[i16]) -> bool {
+        self.is_voice_segment(frame) == Ok(true)
+    }
+}
+
+/// Creates the webrtc-vad classifier for the given settings.
+pub fn webrtc_classifier(settings: &VadSettings) -> Vad {
+    Vad::new_with_rate_and_mode(webrtc_vad::SampleRate::Rate16kHz, settings.mode.into())
+}
+
+/// What the detector decided about a chunk of audio.
+#[derive(Debug, Default)]
+pub struct ChunkResult {
+    // Audio that should be fed to the speech-to-text stream. This also includes any audio that
+    // was held onto from before the speech started.
+    pub audio: Vec<i16>,
+    // Whether this chunk ended the utterance that was in progress.
+    pub end_of_utterance: bool,
+    // Whether voice activity detection thought this chunk had speech in it.
+    pub is_speech: bool,
+    // The peak amplitude of the chunk and the gate it had to get over to be checked for speech.
+    pub level: i16,
+    pub gate: i16,
+}
+
+/// Keeps a running estimate of how loud the room is when nobody's talking.
+struct NoiseFloor {
+    level: f32,
+}
+
+impl NoiseFloor {
+    fn new() -> Self {
+        Self { level: 0.0 }
+    }
+
+    fn update(&mut self, level: i16, num_samples: usize) {
+        // Weight the update by how much audio we're looking at so that the estimate moves at the
+        // same speed no matter how big of chunks the audio driver hands us.
+        let duration_ms = num_samples as f32 * 1000.0 / SAMPLE_RATE as f32;
+        let alpha = 1.0 - (-duration_ms / NOISE_FLOOR_TIME_CONSTANT_MS).exp();
+        self.level += alpha * (level as f32 - self.level);
+    }
+}
+
+/// Splits incoming audio up into utterances. Quiet audio is never checked for speech, but some of
+/// it is held onto so it can be fed in ahead of the speech once it starts. Deepspeech will very
+/// frequently drop the first word in a phrase if there's no silence preceeding it. Once speech has
+/// been found, everything is fed through (even the silence) until there's been enough silence to
+/// call the utterance finished.
+pub struct UtteranceDetector<C: SpeechClassifier> {
+    classifier: C,
+    settings: VadSettings,
+    noise_floor: NoiseFloor,
+    history: Vec<i16>,
+    speech_found: bool,
+    silent_samples: usize,
+    // The start of a frame that didn't fit in the last chunk, to be finished by the next one.
+    partial_frame: Vec<i16>,
+    // Whether the last chunk was speech, which goes for chunks too short to finish a frame.
+    last_is_speech: bool,
+}
+
+impl<C: SpeechClassifier> UtteranceDetector<C> {
+    pub fn new(classifier: C, settings: VadSettings) -> Self {
+        Self {
+            classifier,
+            settings,
+            noise_floor: NoiseFloor::new(),
+            history: vec![],
+            speech_found: false,
+            silent_samples: 0,
+            partial_frame: vec![],
+            last_is_speech: false,
+        }
+    }
+
+    /// Forgets about any utterance in progress and the audio held onto from before it, as if
+    /// nothing had been heard. The noise floor is kept.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.speech_found = false;
+        self.silent_samples = 0;
+        self.partial_frame.clear();
+        self.last_is_speech = false;
+    }
+
+    /// The amplitude audio has to get over before we bother checking it for speech.
+    pub fn gate(&self) -> i16 {
+        if self.settings.adaptive_noise_floor {
+            let adaptive = self.noise_floor.level * self.settings.noise_floor_margin;
+            adaptive
+                .max(self.settings.silence_level as f32)
+                .min(i16::MAX as f32) as i16
+        } else {
+            self.settings.silence_level
+        }
+    }
+
+    pub fn process(&mut self, samps: &[i16]) -> ChunkResult {
+        let level = samps
+            .iter()
+            .map(|samp| samp.saturating_abs())
+            .max()
+            .unwrap_or(0);
+        let gate = self.gate();
+        let mut result = ChunkResult {
+            level,
+            gate,
+            ..Default::default()
+        };
+
+        // Chunks don't have to line up with frames, so frames are carried over from one chunk to
+        // the next.
+        let mut frames = std::mem::take(&mut self.partial_frame);
+        frames.extend_from_slice(samps);
+        let whole_frames_len = frames.len() - frames.len() % FRAME_LEN;
+        self.partial_frame = frames.split_off(whole_frames_len);
+
+        // Don't bother trying voice activity detection unless we're over the gate. Since short
+        // words seem to be missed otherwise, a single frame of speech is enough for the chunk to
+        // count as speech.
+        if level >= gate {
+            let classifier = &mut self.classifier;
+            result.is_speech = if frames.is_empty() {
+                self.last_is_speech
+            } else {
+                frames
+                    .chunks_exact(FRAME_LEN)
+                    .any(|frame| classifier.is_speech(frame))
+            };
+        }
+        self.last_is_speech = result.is_speech;
+
+        if !result.is_speech {
+            self.noise_floor.update(level, samps.len());
+        }
+
+        if self.speech_found {
+            result.audio = samps.to_vec();
+            if result.is_speech {
+                self.silent_samples = 0;
+            } else {
+                self.silent_samples += samps.len();
+                if self.silent_samples >= ms_to_samples(self.settings.end_of_utterance_ms) {
+                    result.end_of_utterance = true;
+                    self.speech_found = false;
+                    self.silent_samples = 0;
+                }
+            }
+        } else {
+            self.history.extend_from_slice(samps);
+            if result.is_speech {
+                self.speech_found = true;
+                result.audio = std::mem::take(&mut self.history);
+            } else {
+                // Audio in a partial frame hasn't been checked yet, so it doesn't count.
+                let max_history =
+                    ms_to_samples(self.settings.pre_speech_ms) + self.partial_frame.len();
+                if self.history.len() > max_history {
+                    self.history.drain(..self.history.len() - max_history);
+                }
+            }
+        }
+
+        result
+    }
+}
+
+fn ms_to_samples(ms: u32) -> usize {
+    (ms as u64 * SAMPLE_RATE as u64 / 1000) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stands in for webrtc-vad so we know exactly what is and isn't speech.
+    struct LoudIsSpeech;
+
+    impl SpeechClassifier for LoudIsSpeech {
+        fn is_speech(&mut self, frame: &[i16]) -> bool {
+            frame.iter().any(|samp| samp.abs() >= 8000)
+        }
+    }
+
+    fn settings() -> VadSettings {
+        VadSettings {
+            mode: VadAggressiveness::Aggressive,
+            silence_level: 1000,
+            adaptive_noise_floor: false,
+            noise_floor_margin: 2.0,
+            pre_speech_ms: 100,
+            end_of_utterance_ms: 500,
+        }
+    }
+
+    // Half a second of quiet, half a second of speech and then a second of quiet.
+    fn utterance() -> Vec<i16> {
+        let mut audio = vec![100; 8000];
+        audio.extend(vec![10_000; 8000]);
+        audio.extend(vec![-100; 16_000]);
+        audio
+    }
+
+    fn run(detector: &mut UtteranceDetector<LoudIsSpeech>, audio: &[i16], chunk_len: usize) {
+        let mut fed = vec![];
+        let mut end = None;
+        for (i, chunk) in audio.chunks(chunk_len).enumerate() {
+            let result = detector.process(chunk);
+            fed.extend(result.audio);
+            if result.end_of_utterance {
+                assert!(end.is_none(), "utterance ended twice");
+                end = Some((i + 1) * chunk_len);
+            }
+        }
+
+        // The speech ends at 16000 samples, so the utterance should end half a second later (give
+        // or take the chunks on either end) no matter how big the chunks are.
+        let end = end.expect("utterance never ended");
+        assert!(
+            end >= 24_000 && end < 24_000 + 2 * chunk_len,
+            "ended at {}",
+            end
+        );
+
+        // We should have gotten all of the speech and the silence after it, plus a bit of the
+        // quiet from before the speech started.
+        assert_eq!(fed[fed.len() - (end - 8000)..], audio[8000..end]);
+        let pre_speech = fed.len() - (end - 8000);
+        assert!(pre_speech >= 1600 && pre_speech <= 1600 + chunk_len);
+    }
+
+    #[test]
+    fn test_end_of_utterance_independent_of_chunk_size() {
+        // Including chunks shorter than a frame, and chunks that don't line up with frames.
+        for chunk_len in &[100, 147, 160, 480, 1024, 1600] {
+            let mut detector = UtteranceDetector::new(LoudIsSpeech, settings());
+            run(&mut detector, &utterance(), *chunk_len);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut detector = UtteranceDetector::new(LoudIsSpeech, settings());
+        detector.process(&[100; 1600]);
+        assert!(!detector.process(&[10_000; 1600]).audio.is_empty());
+
+        // After a reset, the speech that was in progress never ends and nothing from before it
+        // is fed in once new speech starts.
+        detector.reset();
+        for _ in 0..10 {
+            let result = detector.process(&[0; 1600]);
+            assert!(!result.end_of_utterance);
+            assert!(result.audio.is_empty());
+        }
+        let result = detector.process(&[10_000; 160]);
+        assert_eq!(result.audio.len(), 1760);
+        assert!(result.audio[..1600].iter().all(|samp| *samp == 0));
+    }
+
+    #[test]
+    fn test_quiet_audio_not_checked() {
+        // Loud enough that it would be speech if it were checked, but under the gate.
+        let mut settings = settings();
+        settings.silence_level = 12_000;
+        let mut detector = UtteranceDetector::new(LoudIsSpeech, settings);
+        let result = detector.process(&[10_000; 1600]);
+        assert!(!result.is_speech);
+        assert!(result.audio.is_empty());
+    }
+
+    #[test]
+    fn test_adaptive_noise_floor() {
+        let mut settings = settings();
+        settings.adaptive_noise_floor = true;
+        let mut detector = UtteranceDetector::new(LoudIsSpeech, settings);
+        assert_eq!(detector.gate(), 1000);
+
+        // Ten seconds of a noisy room should push the gate up above the noise.
+        for _ in 0..100 {
+            detector.process(&[3000; 1600]);
+        }
+        let gate = detector.gate();
+        assert!(gate > 5500 && gate <= 6000, "gate is {}", gate);
+
+        // Speech still makes it through and doesn't drag the noise floor along with it.
+        for _ in 0..10 {
+            assert!(detector.process(&[10_000; 1600]).is_speech);
+        }
+        assert_eq!(detector.gate(), gate);
+
+        // Once the room quiets back down, the gate should fall back to the minimum.
+        for _ in 0..200 {
+            detector.process(&[0; 1600]);
+        }
+        assert_eq!(detector.gate(), 1000);
+    }
+}
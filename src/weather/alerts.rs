@@ -0,0 +1,156 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A severe weather warning from a national weather service or the like.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WeatherAlert {
+    // Who issued the alert, like "NWS Philadelphia - Mount Holly".
+    pub sender: String,
+    // What the alert is for, like "Severe Thunderstorm Warning".
+    pub event: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub description: String,
+}
+
+impl WeatherAlert {
+    /// Alerts don't come with IDs, so they're told apart by who sent them, what they're for and
+    /// when they start. Anything else about an alert can change while it's in effect.
+    fn key(&self) -> (String, String, DateTime<Local>) {
+        (self.sender.clone(), self.event.clone(), self.start)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertChange {
+    New,
+    Updated,
+    Expired,
+}
+
+/// Lets the frontend know an alert has come up, changed or gone away. These should be shown
+/// over whatever else is on screen.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AlertUpdate {
+    // The name of the location the alert is for.
+    pub location: String,
+    pub change: AlertChange,
+    pub alert: WeatherAlert,
+}
+
+/// Keeps track of the alerts that have already been announced, so each alert is only announced
+/// once rather than every time the weather is checked. Each location has its own tracker.
+#[derive(Clone)]
+pub struct AlertTracker {
+    location: String,
+    announced: HashMap<(String, String, DateTime<Local>), WeatherAlert>,
+}
+
+impl AlertTracker {
+    pub fn new(location: &str) -> Self {
+        Self {
+            location: location.to_string(),
+            announced: HashMap::new(),
+        }
+    }
+
+    /// Takes the alerts that are out as of `now` and works out what's changed since the last
+    /// time. Alerts that have ended or are no longer out are expired.
+    pub fn update(&mut self, alerts: &[WeatherAlert], now: &DateTime<Local>) -> Vec<AlertUpdate> {
+        let mut updates = vec![];
+        let mut current = HashMap::new();
+        for alert in alerts.iter().filter(|alert| alert.end > *now) {
+            let change = match self.announced.get(&alert.key()) {
+                None => Some(AlertChange::New),
+                Some(announced) if announced != alert => Some(AlertChange::Updated),
+                Some(_) => None,
+            };
+            if let Some(change) = change {
+                updates.push(AlertUpdate {
+                    location: self.location.clone(),
+                    change,
+                    alert: alert.clone(),
+                });
+            }
+            current.insert(alert.key(), alert.clone());
+        }
+
+        let mut expired: Vec<&WeatherAlert> = self
+            .announced
+            .iter()
+            .filter(|(key, _)| !current.contains_key(key))
+            .map(|(_, alert)| alert)
+            .collect();
+        expired.sort_by_key(|alert| alert.start);
+        updates.extend(expired.into_iter().map(|alert| AlertUpdate {
+            location: self.location.clone(),
+            change: AlertChange::Expired,
+            alert: alert.clone(),
+        }));
+
+        self.announced = current;
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn alert(event: &str, start_hour: u32, end_hour: u32, description: &str) -> WeatherAlert {
+        WeatherAlert {
+            sender: "DWD".into(),
+            event: event.into(),
+            start: Local.ymd(2024, 3, 15).and_hms(start_hour, 0, 0),
+            end: Local.ymd(2024, 3, 15).and_hms(end_hour, 0, 0),
+            description: description.into(),
+        }
+    }
+
+    fn changes(updates: &[AlertUpdate]) -> Vec<(AlertChange, &str)> {
+        updates
+            .iter()
+            .map(|update| (update.change, update.alert.event.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_update() {
+        let mut tracker = AlertTracker::new("Home");
+        let now = |hour: u32| Local.ymd(2024, 3, 15).and_hms(hour, 30, 0);
+        let storm = alert("storm", 8, 14, "Gusts up to 90 km/h");
+        let frost = alert("frost", 8, 10, "Temperatures down to -5");
+
+        let updates = tracker.update(&[storm.clone(), frost.clone()], &now(8));
+        assert_eq!(
+            changes(&updates),
+            vec![(AlertChange::New, "storm"), (AlertChange::New, "frost")]
+        );
+
+        // Nothing's changed, so there's nothing to announce again.
+        assert!(tracker
+            .update(&[storm.clone(), frost.clone()], &now(9))
+            .is_empty());
+
+        // The storm warning gets worse and the frost warning runs out.
+        let worse_storm = alert("storm", 8, 16, "Gusts up to 120 km/h");
+        let updates = tracker.update(&[worse_storm.clone(), frost], &now(10));
+        assert_eq!(
+            changes(&updates),
+            vec![
+                (AlertChange::Updated, "storm"),
+                (AlertChange::Expired, "frost")
+            ]
+        );
+        assert_eq!(updates[0].alert, worse_storm);
+        assert_eq!(updates[0].location, "Home");
+
+        // Alerts that are taken down before they end are expired too.
+        let updates = tracker.update(&[], &now(11));
+        assert_eq!(changes(&updates), vec![(AlertChange::Expired, "storm")]);
+        assert!(tracker.update(&[], &now(12)).is_empty());
+    }
+}
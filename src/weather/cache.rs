@@ -0,0 +1,86 @@
+use crate::weather::{WeatherReport, WeatherRequest};
+use chrono::{DateTime, Duration, Local};
+
+/// The last weather report we got, so it can be reused for a while rather than asking for it
+/// again, and so there's still something to show when every weather provider is down.
+#[derive(Clone, Default)]
+pub struct WeatherCache {
+    entry: Option<(WeatherRequest, WeatherReport)>,
+}
+
+impl WeatherCache {
+    /// The cached report for the request, if it was fetched less than `ttl` ago.
+    pub fn fresh(
+        &self,
+        request: &WeatherRequest,
+        ttl: Duration,
+        now: &DateTime<Local>,
+    ) -> Option<WeatherReport> {
+        let report = self.get(request)?;
+        let fetched_at = report.fetched_at?;
+        if *now - fetched_at < ttl {
+            Some(report.clone())
+        } else {
+            None
+        }
+    }
+
+    /// The cached report for the request no matter how old it is, marked as stale.
+    pub fn stale(&self, request: &WeatherRequest) -> Option<WeatherReport> {
+        let mut report = self.get(request)?.clone();
+        report.stale = true;
+        Some(report)
+    }
+
+    pub fn store(&mut self, request: &WeatherRequest, report: &WeatherReport) {
+        self.entry = Some((request.clone(), report.clone()));
+    }
+
+    /// A report for somewhere else or in another language is no use. Reports are kept in the same
+    /// units no matter what's picked, so they can still be used when the units change.
+    fn get(&self, request: &WeatherRequest) -> Option<&WeatherReport> {
+        match &self.entry {
+            Some((cached, report)) if cached == request => Some(report),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{settings::Language, weather::openweather_fixture};
+    use chrono::TimeZone;
+
+    fn request(lat: f32) -> WeatherRequest {
+        WeatherRequest {
+            lat,
+            lon: 13.4,
+            language: Language::English,
+        }
+    }
+
+    #[test]
+    fn test_cache() {
+        let fetched_at = Local.ymd(2024, 1, 10).and_hms(12, 0, 0);
+        let mut report = openweather_fixture();
+        report.fetched_at = Some(fetched_at);
+        let ttl = Duration::minutes(10);
+        let mut cache = WeatherCache::default();
+        assert!(cache.fresh(&request(52.5), ttl, &fetched_at).is_none());
+        assert!(cache.stale(&request(52.5)).is_none());
+
+        cache.store(&request(52.5), &report);
+        let later = fetched_at + Duration::minutes(5);
+        let cached = cache.fresh(&request(52.5), ttl, &later).unwrap();
+        assert!(!cached.stale);
+        assert!(cache.fresh(&request(48.1), ttl, &later).is_none());
+
+        let later = fetched_at + Duration::minutes(15);
+        assert!(cache.fresh(&request(52.5), ttl, &later).is_none());
+        let cached = cache.stale(&request(52.5)).unwrap();
+        assert!(cached.stale);
+        assert_eq!(cached.fetched_at, Some(fetched_at));
+        assert!(cache.stale(&request(48.1)).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+use crate::settings::Language;
+use std::{collections::HashMap, fs::File, io::Read};
+
+// Weather conditions are reported as OpenWeather's condition codes no matter where the weather
+// came from, so the frontend only has to know about one set of conditions and icons.
+// https://openweathermap.org/weather-conditions
+pub const THUNDERSTORM: u32 = 211;
+pub const THUNDERSTORM_WITH_RAIN: u32 = 201;
+pub const DRIZZLE: u32 = 301;
+pub const LIGHT_RAIN: u32 = 500;
+pub const RAIN: u32 = 501;
+pub const HEAVY_RAIN: u32 = 502;
+pub const FREEZING_RAIN: u32 = 511;
+pub const RAIN_SHOWERS: u32 = 521;
+pub const LIGHT_SNOW: u32 = 600;
+pub const SNOW: u32 = 601;
+pub const HEAVY_SNOW: u32 = 602;
+pub const SLEET: u32 = 611;
+pub const SNOW_SHOWERS: u32 = 621;
+pub const MIST: u32 = 701;
+pub const SMOKE: u32 = 711;
+pub const HAZE: u32 = 721;
+pub const DUST: u32 = 761;
+pub const FOG: u32 = 741;
+pub const TORNADO: u32 = 781;
+pub const CLEAR: u32 = 800;
+pub const FEW_CLOUDS: u32 = 801;
+pub const SCATTERED_CLOUDS: u32 = 802;
+pub const BROKEN_CLOUDS: u32 = 803;
+pub const OVERCAST: u32 = 804;
+
+/// The OpenWeather icon for a condition, like "10d" for rain during the day.
+pub fn icon(code: u32, is_day: bool) -> String {
+    let icon = match code {
+        200..=299 => "11",
+        300..=399 | 520..=531 => "09",
+        511 | 600..=699 => "13",
+        500..=599 => "10",
+        700..=799 => "50",
+        800 => "01",
+        801 => "02",
+        802 => "03",
+        _ => "04",
+    };
+    format!("{}{}", icon, if is_day { "d" } else { "n" })
+}
+
+/// Roughly how much of the sky is covered by cloud in a condition, as a percentage, for providers
+/// that only tell us the condition.
+pub fn cloudiness(code: u32) -> f32 {
+    match code {
+        CLEAR => 0.0,
+        FEW_CLOUDS => 20.0,
+        SCATTERED_CLOUDS => 40.0,
+        BROKEN_CLOUDS => 70.0,
+        _ => 100.0,
+    }
+}
+
+/// Descriptions of the conditions in one language, for providers that don't describe the weather
+/// themselves. These are loaded from conditions_{code}.json, keyed by condition code.
+pub struct Conditions {
+    descriptions: HashMap<u32, String>,
+}
+
+impl Conditions {
+    pub fn init(language: Language) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut file = File::open(format!("conditions_{}.json", language.code()))?;
+        let mut buffer = String::new();
+        file.read_to_string(&mut buffer)?;
+        Ok(Self {
+            descriptions: serde_json::from_str(&buffer)?,
+        })
+    }
+
+    /// Describes a condition, falling back on the most typical condition in its group (like
+    /// "thunderstorm" for all of the 2xx codes) if it doesn't have a description of its own.
+    pub fn describe(&self, code: u32) -> String {
+        let typical = match code / 100 {
+            2 => THUNDERSTORM,
+            3 => DRIZZLE,
+            5 => RAIN,
+            6 => SNOW,
+            7 => MIST,
+            _ => OVERCAST,
+        };
+        self.descriptions
+            .get(&code)
+            .or_else(|| self.descriptions.get(&typical))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conditions() {
+        let conditions = Conditions::init(Language::English).expect("No conditions file found");
+        assert_eq!(conditions.describe(RAIN), "moderate rain");
+        assert_eq!(conditions.describe(202), "thunderstorm");
+        let conditions = Conditions::init(Language::German).expect("No conditions file found");
+        assert_eq!(conditions.describe(OVERCAST), "Bedeckt");
+
+        assert_eq!(icon(RAIN, true), "10d");
+        assert_eq!(icon(RAIN_SHOWERS, false), "09n");
+        assert_eq!(icon(FREEZING_RAIN, true), "13d");
+        assert_eq!(icon(OVERCAST, true), "04d");
+    }
+}
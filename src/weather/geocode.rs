@@ -0,0 +1,266 @@
+use crate::{
+    settings::{Language, WeatherSettings},
+    weather::weather_providers,
+};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::Read};
+
+// Extracts in GeoNames' formats (https://download.geonames.org/export/dump/ and
+// https://download.geonames.org/export/zip/). The bundled ones only have larger cities and a few
+// postal codes, but they can be swapped out for full GeoNames files like cities15000.txt.
+const CITIES_PATH: &str = "geonames_cities.txt";
+const POSTAL_CODES_PATH: &str = "geonames_postal_codes.txt";
+// The most places a lookup gives back.
+const MAX_PLACES: usize = 10;
+
+lazy_static! {
+    // The bundled places, which are loaded the first time anything's looked up.
+    static ref GAZETTEER: Gazetteer = Gazetteer::init().unwrap_or_else(|e| {
+        eprintln!("Unable to load the bundled places: {:?}", e);
+        Gazetteer::default()
+    });
+}
+
+/// Somewhere a name was looked up to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Place {
+    pub name: String,
+    // The ISO 3166 code of the country, like "DE".
+    pub country: String,
+    // The state or region, if we know it. For the bundled places this is GeoNames' code for it,
+    // like "CA" for California.
+    pub region: Option<String>,
+    pub lat: f32,
+    pub lon: f32,
+}
+
+struct Entry {
+    place: Place,
+    // Everything the place can be looked up by, normalized.
+    names: Vec<String>,
+    population: u64,
+}
+
+#[derive(Default)]
+struct Gazetteer {
+    cities: Vec<Entry>,
+    postal_codes: Vec<Entry>,
+}
+
+/// Lowercases a name and drops any punctuation, so "Washington, D.C." and "washington dc" are the
+/// same.
+fn normalize(name: &str) -> String {
+    name.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn read(path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut file = File::open(path)?;
+    let mut data = String::new();
+    file.read_to_string(&mut data)?;
+    Ok(data)
+}
+
+fn non_empty(field: &str) -> Option<String> {
+    Some(field.to_string()).filter(|field| !field.is_empty())
+}
+
+impl Gazetteer {
+    fn init() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            cities: Self::parse_cities(&read(CITIES_PATH)?),
+            postal_codes: Self::parse_postal_codes(&read(POSTAL_CODES_PATH)?),
+        })
+    }
+
+    /// Reads places from GeoNames' main format, which has a tab separated line for each place.
+    /// Cities can be looked up by their name, their name in ASCII or any of their alternate
+    /// names.
+    fn parse_cities(data: &str) -> Vec<Entry> {
+        data.lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                if fields.len() < 15 {
+                    return None;
+                }
+                let names = [fields[1], fields[2]]
+                    .iter()
+                    .copied()
+                    .chain(fields[3].split(','))
+                    .map(normalize)
+                    .filter(|name| !name.is_empty())
+                    .collect();
+                Some(Entry {
+                    place: Place {
+                        name: fields[1].to_string(),
+                        country: fields[8].to_string(),
+                        region: non_empty(fields[10]),
+                        lat: fields[4].parse().ok()?,
+                        lon: fields[5].parse().ok()?,
+                    },
+                    names,
+                    population: fields[14].parse().unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+
+    /// Reads postal codes from GeoNames' postal code format.
+    fn parse_postal_codes(data: &str) -> Vec<Entry> {
+        data.lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                if fields.len() < 11 {
+                    return None;
+                }
+                Some(Entry {
+                    place: Place {
+                        name: fields[2].to_string(),
+                        country: fields[0].to_string(),
+                        region: non_empty(fields[4]),
+                        lat: fields[9].parse().ok()?,
+                        lon: fields[10].parse().ok()?,
+                    },
+                    names: vec![normalize(fields[1])],
+                    population: 0,
+                })
+            })
+            .collect()
+    }
+
+    /// Finds the places with a name or postal code, biggest first. Anything after a comma narrows
+    /// it down to a country or region, as in "Paris, TX" or "10115, DE". British postcodes only
+    /// go by their first half, so that's all that has to match.
+    fn search(&self, query: &str) -> Vec<Place> {
+        let mut parts = query.splitn(2, ',');
+        let name = normalize(parts.next().unwrap_or_default());
+        let qualifier = parts.next().map(normalize).filter(|q| !q.is_empty());
+        let (entries, first_word) = if name.chars().any(|c| c.is_ascii_digit()) {
+            (&self.postal_codes, name.split(' ').next())
+        } else {
+            (&self.cities, None)
+        };
+
+        let mut found: Vec<&Entry> = entries
+            .iter()
+            .filter(|entry| {
+                entry
+                    .names
+                    .iter()
+                    .any(|n| *n == name || Some(n.as_str()) == first_word)
+            })
+            .filter(|entry| match &qualifier {
+                Some(qualifier) => {
+                    let place = &entry.place;
+                    normalize(&place.country) == *qualifier
+                        || place.region.as_deref().map(normalize).as_ref() == Some(qualifier)
+                }
+                None => true,
+            })
+            .collect();
+        found.sort_by_key(|entry| std::cmp::Reverse(entry.population));
+        found
+            .into_iter()
+            .take(MAX_PLACES)
+            .map(|entry| entry.place.clone())
+            .collect()
+    }
+}
+
+/// Looks up a place name or postal code, like "Springfield, MO" or "10115", in the bundled places.
+pub fn search_bundled(query: &str) -> Vec<Place> {
+    GAZETTEER.search(query)
+}
+
+/// Looks up a place by asking the weather providers that can look places up in turn.
+pub async fn geocode_online(
+    query: &str,
+    weather_settings: &WeatherSettings,
+    language: Language,
+) -> Vec<Place> {
+    for provider in weather_providers(weather_settings) {
+        match provider.geocode(query, language).await {
+            Ok(places) if !places.is_empty() => return places,
+            Ok(_) => (),
+            Err(e) => eprintln!(
+                "Couldn't look up {} with {}: {:?}",
+                query,
+                provider.name(),
+                e
+            ),
+        }
+    }
+    vec![]
+}
+
+/// Looks up a place name or postal code in the bundled places. If nothing's found there and online
+/// lookups are turned on, the weather providers that can look places up are asked.
+pub async fn geocode(
+    query: &str,
+    weather_settings: &WeatherSettings,
+    language: Language,
+) -> Vec<Place> {
+    let places = search_bundled(query);
+    if !places.is_empty() || !weather_settings.online_geocoding {
+        return places;
+    }
+    geocode_online(query, weather_settings, language).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(query: &str) -> Vec<(String, String, Option<String>)> {
+        let gazetteer = Gazetteer::init().expect("No bundled places found");
+        gazetteer
+            .search(query)
+            .into_iter()
+            .map(|place| (place.name, place.country, place.region))
+            .collect()
+    }
+
+    fn place(name: &str, country: &str, region: &str) -> (String, String, Option<String>) {
+        (name.into(), country.into(), Some(region.into()))
+    }
+
+    #[test]
+    fn test_search() {
+        assert_eq!(search("Berlin"), vec![place("Berlin", "DE", "16")]);
+        // Places can be found by other names, with or without accents.
+        assert_eq!(search("munich"), vec![place("München", "DE", "02")]);
+        assert_eq!(search("  Zurich "), vec![place("Zürich", "CH", "ZH")]);
+        assert_eq!(
+            search("washington dc"),
+            vec![place("Washington", "US", "DC")]
+        );
+
+        // The biggest place comes first, unless it's narrowed down.
+        assert_eq!(
+            search("paris"),
+            vec![place("Paris", "FR", "11"), place("Paris", "US", "TX")]
+        );
+        assert_eq!(search("Paris, TX"), vec![place("Paris", "US", "TX")]);
+        assert_eq!(
+            search("springfield, mo"),
+            vec![place("Springfield", "US", "MO")]
+        );
+        assert_eq!(search("springfield").len(), 3);
+
+        assert_eq!(search("10115"), vec![place("Berlin", "DE", "BE")]);
+        assert_eq!(search("02108, US"), vec![place("Boston", "US", "MA")]);
+        assert_eq!(search("SW1A 1AA"), vec![place("London", "GB", "ENG")]);
+
+        assert!(search("atlantis").is_empty());
+        assert!(search("").is_empty());
+    }
+}
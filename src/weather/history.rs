@@ -0,0 +1,406 @@
+use crate::weather::{TemperatureUnits, WeatherReport};
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::PathBuf,
+};
+
+// Every weather report that's been fetched, one JSON object per line, oldest first.
+const HISTORY_PATH: &str = "weather_history.jsonl";
+
+/// What's kept of a weather report fetched for one location, which is just what's needed to sum
+/// up the day and check the forecasts against it. Temperatures are in Celsius.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub time: DateTime<Local>,
+    pub location: String,
+    // The weather provider the report came from.
+    pub provider: Option<String>,
+    pub temp: f32,
+    pub forecast: Vec<ForecastTemps>,
+}
+
+/// The high and low forecast for a day.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ForecastTemps {
+    pub date: NaiveDate,
+    pub high: f32,
+    pub low: f32,
+}
+
+impl HistoryEntry {
+    pub fn new(time: DateTime<Local>, location: &str, report: &WeatherReport) -> Self {
+        Self {
+            time,
+            location: location.to_string(),
+            provider: report.provider.clone(),
+            temp: report.current_weather.temp,
+            forecast: report
+                .forecast
+                .iter()
+                .map(|forecast| ForecastTemps {
+                    date: forecast.date,
+                    high: forecast.max_temp,
+                    low: forecast.min_temp,
+                })
+                .collect(),
+        }
+    }
+
+    /// The entry with its temperatures in the given units.
+    pub fn in_units(&self, units: &TemperatureUnits) -> Self {
+        let temp = |temp: f32| units.convert_celsius(temp);
+        Self {
+            temp: temp(self.temp),
+            forecast: self
+                .forecast
+                .iter()
+                .map(|forecast| ForecastTemps {
+                    date: forecast.date,
+                    high: temp(forecast.high),
+                    low: temp(forecast.low),
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// What the weather was like on one day in one location, along with what was forecast for it on
+/// the days before.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DaySummary {
+    pub date: NaiveDate,
+    pub location: String,
+    // The highest and lowest temperatures in the reports fetched that day, so these are only as
+    // close to the real high and low as the polling rate allows.
+    pub high: f32,
+    pub low: f32,
+    pub forecasts: Vec<ForecastAccuracy>,
+}
+
+/// The last forecast one provider made for a day on one of the days before it.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ForecastAccuracy {
+    pub provider: Option<String>,
+    // How many days before the day the forecast was made.
+    pub days_ahead: i64,
+    pub high: f32,
+    pub low: f32,
+    // The forecast minus what was seen, so positive means it was forecast warmer than it was.
+    pub high_error: f32,
+    pub low_error: f32,
+}
+
+/// Keeps every weather report that's been fetched in a JSON lines file, so the weather can be
+/// compared from day to day and with what was forecast. Reports older than a given number of days
+/// are dropped from the file once a day.
+#[derive(Clone)]
+pub struct WeatherHistory {
+    path: PathBuf,
+    // The day old reports were last dropped.
+    trimmed_on: Option<NaiveDate>,
+}
+
+impl Default for WeatherHistory {
+    fn default() -> Self {
+        Self::new(HISTORY_PATH)
+    }
+}
+
+impl WeatherHistory {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            trimmed_on: None,
+        }
+    }
+
+    /// Adds a report to the end of the history, first dropping reports from more than
+    /// `keep_days` days before it if that hasn't been done yet today.
+    pub fn append(
+        &mut self,
+        entry: &HistoryEntry,
+        keep_days: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let today = entry.time.date().naive_local();
+        if self.trimmed_on != Some(today) {
+            self.trim(today - chrono::Duration::days(keep_days as i64))?;
+            self.trimmed_on = Some(today);
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    /// Drops the reports from before the given day, along with any lines that can't be read. The
+    /// history is written out to a new file that then replaces the old one, so nothing is lost if
+    /// that goes wrong part way through.
+    fn trim(&self, before: NaiveDate) -> Result<(), Box<dyn std::error::Error>> {
+        let entries = self.read(Some(before), None)?;
+        let trimmed_path = self.path.with_extension("jsonl.tmp");
+        let mut file = BufWriter::new(File::create(&trimmed_path)?);
+        for entry in &entries {
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        }
+        file.flush()?;
+        fs::rename(&trimmed_path, &self.path)?;
+        Ok(())
+    }
+
+    /// The reports fetched from the start of `from` to the end of `to`, oldest first. Either end
+    /// can be left open. Lines that can't be read are skipped.
+    pub fn read(
+        &self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<HistoryEntry>, Box<dyn std::error::Error>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            // Nothing's been fetched yet.
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = vec![];
+        for line in BufReader::new(file).lines() {
+            let entry: HistoryEntry = match serde_json::from_str(&line?) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    eprintln!("Skipping unreadable weather history: {}", e);
+                    continue;
+                }
+            };
+            let date = entry.time.date().naive_local();
+            let too_early = matches!(from, Some(from) if date < from);
+            let too_late = matches!(to, Some(to) if date > to);
+            if !too_early && !too_late {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Sums up each day in each location from reports that are oldest first, with temperatures in the
+/// given units. Forecasts made on the day itself are left out, since part of the day had already
+/// been seen by then.
+pub fn summarize(entries: &[HistoryEntry], units: &TemperatureUnits) -> Vec<DaySummary> {
+    let temp = |temp: f32| units.convert_celsius(temp);
+    let mut days: BTreeMap<(NaiveDate, &str), (f32, f32)> = BTreeMap::new();
+    for entry in entries {
+        let date = entry.time.date().naive_local();
+        let current = entry.temp;
+        let (high, low) = days
+            .entry((date, &entry.location))
+            .or_insert((current, current));
+        *high = high.max(current);
+        *low = low.min(current);
+    }
+
+    days.into_iter()
+        .map(|((date, location), (high, low))| {
+            // Later forecasts replace earlier ones made the same number of days ahead.
+            let mut forecasts = BTreeMap::new();
+            for entry in entries.iter().filter(|entry| entry.location == location) {
+                let days_ahead = (date - entry.time.date().naive_local()).num_days();
+                if days_ahead < 1 {
+                    continue;
+                }
+                if let Some(forecast) = entry.forecast.iter().find(|day| day.date == date) {
+                    forecasts.insert(
+                        (entry.provider.clone(), days_ahead),
+                        (forecast.high, forecast.low),
+                    );
+                }
+            }
+            DaySummary {
+                date,
+                location: location.to_string(),
+                high: temp(high),
+                low: temp(low),
+                forecasts: forecasts
+                    .into_iter()
+                    .map(|((provider, days_ahead), (forecast_high, forecast_low))| {
+                        ForecastAccuracy {
+                            provider,
+                            days_ahead,
+                            high: temp(forecast_high),
+                            low: temp(forecast_low),
+                            high_error: temp(forecast_high) - temp(high),
+                            low_error: temp(forecast_low) - temp(low),
+                        }
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::openweather_fixture;
+    use chrono::TimeZone;
+
+    /// A report with the given current temperature, forecasting a high and low for the given day.
+    fn entry(
+        time: DateTime<Local>,
+        location: &str,
+        provider: &str,
+        temp: f32,
+        forecast: (NaiveDate, f32, f32),
+    ) -> HistoryEntry {
+        let (date, high, low) = forecast;
+        HistoryEntry {
+            time,
+            location: location.to_string(),
+            provider: Some(provider.to_string()),
+            temp,
+            forecast: vec![ForecastTemps { date, high, low }],
+        }
+    }
+
+    #[test]
+    fn test_from_report() {
+        let report = openweather_fixture();
+        let entry = HistoryEntry::new(Local::now(), "Home", &report);
+        assert_eq!(entry.temp, report.current_weather.temp);
+        assert_eq!(entry.forecast.len(), report.forecast.len());
+        assert_eq!(entry.forecast[0].high, report.forecast[0].max_temp);
+
+        let fahrenheit = entry.in_units(&TemperatureUnits::Fahrenheit);
+        assert!((fahrenheit.temp - (entry.temp * 9.0 / 5.0 + 32.0)).abs() < 0.01);
+        assert!(
+            (fahrenheit.forecast[0].low - (entry.forecast[0].low * 9.0 / 5.0 + 32.0)).abs() < 0.01
+        );
+    }
+
+    #[test]
+    fn test_summarize() {
+        let at = |day: u32, hour: u32| Local.ymd(2024, 1, day).and_hms(hour, 0, 0);
+        let date = |day: u32| NaiveDate::from_ymd(2024, 1, day);
+        let entries = vec![
+            entry(at(9, 9), "Home", "OpenWeather", 1.0, (date(11), 8.0, 0.0)),
+            entry(at(10, 6), "Home", "OpenWeather", 2.0, (date(11), 7.0, 1.0)),
+            entry(at(10, 9), "Home", "OpenWeather", 4.0, (date(11), 6.0, 1.0)),
+            entry(at(10, 12), "Home", "MET Norway", 5.0, (date(11), 4.0, -1.0)),
+            entry(
+                at(10, 12),
+                "Cabin",
+                "MET Norway",
+                -3.0,
+                (date(11), -2.0, -9.0),
+            ),
+            entry(at(11, 6), "Home", "OpenWeather", 0.5, (date(11), 5.0, 0.0)),
+            entry(at(11, 15), "Home", "OpenWeather", 5.5, (date(12), 5.0, 0.0)),
+        ];
+        let days = summarize(&entries, &TemperatureUnits::Celsius);
+        let keys: Vec<_> = days
+            .iter()
+            .map(|day| (day.date, day.location.as_str()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (date(9), "Home"),
+                (date(10), "Cabin"),
+                (date(10), "Home"),
+                (date(11), "Home")
+            ]
+        );
+        assert_eq!((days[2].high, days[2].low), (5.0, 2.0));
+        assert!(days[2].forecasts.is_empty());
+
+        // The forecast made that morning doesn't count, and of the two made the day before, the
+        // later one does.
+        let eleventh = &days[3];
+        assert_eq!((eleventh.high, eleventh.low), (5.5, 0.5));
+        assert_eq!(
+            eleventh.forecasts,
+            vec![
+                ForecastAccuracy {
+                    provider: Some("MET Norway".into()),
+                    days_ahead: 1,
+                    high: 4.0,
+                    low: -1.0,
+                    high_error: -1.5,
+                    low_error: -1.5,
+                },
+                ForecastAccuracy {
+                    provider: Some("OpenWeather".into()),
+                    days_ahead: 1,
+                    high: 6.0,
+                    low: 1.0,
+                    high_error: 0.5,
+                    low_error: 0.5,
+                },
+                ForecastAccuracy {
+                    provider: Some("OpenWeather".into()),
+                    days_ahead: 2,
+                    high: 8.0,
+                    low: 0.0,
+                    high_error: 2.5,
+                    low_error: -0.5,
+                },
+            ]
+        );
+
+        let days = summarize(&entries, &TemperatureUnits::Fahrenheit);
+        assert!((days[3].high - 41.9).abs() < 0.01);
+        assert!((days[3].low - 32.9).abs() < 0.01);
+        assert!((days[3].forecasts[2].high_error - 4.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_read_and_append() {
+        let path = std::env::temp_dir().join(format!(
+            "smart_tablet_test_weather_history_{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let mut history = WeatherHistory::new(&path);
+        assert!(history.read(None, None).unwrap().is_empty());
+
+        let at = |day: u32| Local.ymd(2024, 1, day).and_hms(12, 0, 0);
+        let forecast = (NaiveDate::from_ymd(2024, 1, 12), 5.0, 0.0);
+        for day in 9..12 {
+            history
+                .append(&entry(at(day), "Home", "OpenWeather", 3.0, forecast), 365)
+                .unwrap();
+        }
+        let from = NaiveDate::from_ymd(2024, 1, 10);
+        let entries = history.read(Some(from), None).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                entry(at(10), "Home", "OpenWeather", 3.0, forecast),
+                entry(at(11), "Home", "OpenWeather", 3.0, forecast)
+            ]
+        );
+        assert_eq!(history.read(None, Some(from)).unwrap().len(), 2);
+
+        // Only a day's worth is kept, so the first report to come in on the 12th drops the 9th and
+        // 10th, but the next one doesn't drop the first.
+        history
+            .append(&entry(at(12), "Home", "OpenWeather", 3.0, forecast), 1)
+            .unwrap();
+        let later = Local.ymd(2024, 1, 12).and_hms(18, 0, 0);
+        history
+            .append(&entry(later, "Home", "OpenWeather", 3.0, forecast), 1)
+            .unwrap();
+        let times: Vec<_> = history
+            .read(None, None)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.time)
+            .collect();
+        assert_eq!(times, vec![at(11), at(12), later]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
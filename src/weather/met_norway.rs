@@ -0,0 +1,333 @@
+use crate::weather::{
+    condition::{self, Conditions},
+    get_json, moon_phase, CurrentWeather, Forecast, HourlyForecast, WeatherProvider, WeatherReport,
+    WeatherRequest,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDate, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// The Norwegian Meteorological Institute's location forecast, which covers the whole world.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MetNorway {
+    // MET Norway asks for a user agent naming the app and how to reach whoever runs it, like
+    // "smart_tablet you@example.com", and blocks requests without one.
+    pub user_agent: String,
+}
+
+#[async_trait]
+impl WeatherProvider for MetNorway {
+    fn name(&self) -> &'static str {
+        "MET Norway"
+    }
+
+    async fn get_weather(
+        &self,
+        request: &WeatherRequest,
+    ) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
+        // MET Norway doesn't want coordinates with more than 4 decimals, since they only make
+        // caching harder.
+        let uri = format!(
+            "https://api.met.no/weatherapi/locationforecast/2.0/complete?lat={:.4}&lon={:.4}",
+            request.lat, request.lon
+        );
+        let resp: MetNorwayReport = get_json(&uri, Some(&self.user_agent)).await?;
+        resp.into_report(&Conditions::init(request.language)?)
+    }
+}
+
+/// What comes back from MET Norway, in Celsius, m/s and hPa. It's a single list of times, hourly
+/// for the first couple of days and every 6 hours after that.
+#[derive(Deserialize)]
+struct MetNorwayReport {
+    properties: Properties,
+}
+
+#[derive(Deserialize)]
+struct Properties {
+    timeseries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+struct Entry {
+    time: DateTime<Local>,
+    data: Data,
+}
+
+#[derive(Deserialize)]
+struct Data {
+    instant: Instant,
+    // What the weather will be like over the next hour or 6 hours from this time.
+    next_1_hours: Option<Period>,
+    next_6_hours: Option<Period>,
+}
+
+#[derive(Deserialize)]
+struct Instant {
+    details: Details,
+}
+
+#[derive(Deserialize)]
+struct Details {
+    air_temperature: f32,
+    relative_humidity: f32,
+    air_pressure_at_sea_level: Option<f32>,
+    dew_point_temperature: Option<f32>,
+    ultraviolet_index_clear_sky: Option<f32>,
+    cloud_area_fraction: f32,
+    wind_speed: f32,
+    wind_from_direction: f32,
+    wind_speed_of_gust: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct Period {
+    summary: Summary,
+    #[serde(default)]
+    details: PeriodDetails,
+}
+
+#[derive(Deserialize)]
+struct Summary {
+    // Like "lightrainshowers_day".
+    symbol_code: String,
+}
+
+#[derive(Deserialize, Default)]
+struct PeriodDetails {
+    probability_of_precipitation: Option<f32>,
+}
+
+impl Data {
+    /// The shortest period we've got a forecast for starting at this time.
+    fn period(&self) -> Option<&Period> {
+        self.next_1_hours.as_ref().or(self.next_6_hours.as_ref())
+    }
+}
+
+/// Turns a symbol code like "lightrainshowers_day" into a condition code and whether it's day.
+fn condition(symbol_code: &str) -> (u32, bool) {
+    let mut parts = symbol_code.split('_');
+    let symbol = parts.next().unwrap_or_default();
+    let is_day = parts.next() != Some("night");
+    let code = if symbol.contains("thunder") {
+        condition::THUNDERSTORM_WITH_RAIN
+    } else if symbol.contains("sleet") {
+        condition::SLEET
+    } else if symbol.contains("snowshowers") {
+        condition::SNOW_SHOWERS
+    } else if symbol.contains("snow") {
+        match symbol {
+            "lightsnow" => condition::LIGHT_SNOW,
+            "heavysnow" => condition::HEAVY_SNOW,
+            _ => condition::SNOW,
+        }
+    } else if symbol.contains("rainshowers") {
+        condition::RAIN_SHOWERS
+    } else if symbol.contains("rain") {
+        match symbol {
+            "lightrain" => condition::LIGHT_RAIN,
+            "heavyrain" => condition::HEAVY_RAIN,
+            _ => condition::RAIN,
+        }
+    } else {
+        match symbol {
+            "clearsky" => condition::CLEAR,
+            "fair" => condition::FEW_CLOUDS,
+            "partlycloudy" => condition::SCATTERED_CLOUDS,
+            "fog" => condition::FOG,
+            _ => condition::OVERCAST,
+        }
+    };
+    (code, is_day)
+}
+
+impl MetNorwayReport {
+    fn into_report(
+        self,
+        conditions: &Conditions,
+    ) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
+        let entries = self.properties.timeseries;
+        let now = entries
+            .first()
+            .ok_or("MET Norway didn't send any weather")?;
+        let details = &now.data.instant.details;
+        let (code, is_day) = now
+            .data
+            .period()
+            .map_or((condition::OVERCAST, true), |period| {
+                condition(&period.summary.symbol_code)
+            });
+        let current_weather = CurrentWeather {
+            temp: details.air_temperature,
+            feels_like: details.air_temperature,
+            humidity: details.relative_humidity,
+            pressure: details.air_pressure_at_sea_level,
+            dew_point: details.dew_point_temperature,
+            visibility: None,
+            uv_index: details.ultraviolet_index_clear_sky,
+            cloudiness: details.cloud_area_fraction,
+            wind_speed: details.wind_speed,
+            wind_direction: details.wind_from_direction,
+            wind_gust: details.wind_speed_of_gust,
+            condition_code: code,
+            icon: condition::icon(code, is_day),
+            description: conditions.describe(code),
+            sunrise: None,
+            sunset: None,
+        };
+
+        let hourly = entries
+            .iter()
+            .filter_map(|entry| {
+                let period = entry.data.next_1_hours.as_ref()?;
+                let (code, _) = condition(&period.summary.symbol_code);
+                let details = &entry.data.instant.details;
+                Some(HourlyForecast {
+                    time: entry.time,
+                    temp: details.air_temperature,
+                    humidity: details.relative_humidity,
+                    rain_chance: period
+                        .details
+                        .probability_of_precipitation
+                        .unwrap_or_default(),
+                    cloudiness: details.cloud_area_fraction,
+                    description: conditions.describe(code),
+                })
+            })
+            .take(48)
+            .collect();
+
+        // Group the times by day, going by the lowest and highest temperatures over the day and
+        // what the weather's like closest to noon.
+        let mut days: Vec<(NaiveDate, Vec<&Entry>)> = vec![];
+        for entry in &entries {
+            let date = entry.time.date().naive_local();
+            match days.last_mut() {
+                Some((last, day)) if *last == date => day.push(entry),
+                _ => days.push((date, vec![entry])),
+            }
+        }
+        let forecast = days
+            .into_iter()
+            .filter_map(|(date, day)| {
+                let temps = day
+                    .iter()
+                    .map(|entry| entry.data.instant.details.air_temperature);
+                let min_temp = temps.clone().fold(f32::INFINITY, f32::min);
+                let max_temp = temps.fold(f32::NEG_INFINITY, f32::max);
+                let midday = day
+                    .iter()
+                    .filter(|entry| entry.data.period().is_some())
+                    .min_by_key(|entry| (entry.time.hour() as i32 - 12).abs())?;
+                let period = midday.data.period()?;
+                let (code, _) = condition(&period.summary.symbol_code);
+                let details = &midday.data.instant.details;
+                Some(Forecast {
+                    date,
+                    min_temp,
+                    max_temp,
+                    humidity: details.relative_humidity,
+                    rain_chance: day
+                        .iter()
+                        .filter_map(|entry| {
+                            entry.data.period()?.details.probability_of_precipitation
+                        })
+                        .fold(0.0, f32::max),
+                    cloudiness: details.cloud_area_fraction,
+                    description: conditions.describe(code),
+                    feels_like: None,
+                    pressure: details.air_pressure_at_sea_level,
+                    dew_point: details.dew_point_temperature,
+                    uv_index: details.ultraviolet_index_clear_sky,
+                    wind_speed: details.wind_speed,
+                    wind_direction: details.wind_from_direction,
+                    wind_gust: details.wind_speed_of_gust,
+                    condition_code: code,
+                    icon: condition::icon(code, true),
+                    sunrise: None,
+                    sunset: None,
+                    moon_phase: moon_phase(date),
+                })
+            })
+            .collect();
+
+        Ok(WeatherReport {
+            current_weather,
+            forecast,
+            hourly,
+            minutely: vec![],
+            precipitation_starts_at: None,
+            alerts: vec![],
+            fetched_at: None,
+            provider: None,
+            stale: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        settings::Language,
+        weather::{TemperatureUnits, Units},
+    };
+
+    fn report() -> WeatherReport {
+        let resp: MetNorwayReport =
+            serde_json::from_str(include_str!("fixtures/met_norway.json")).unwrap();
+        let conditions = Conditions::init(Language::English).expect("No conditions file found");
+        resp.into_report(&conditions).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let report = report();
+        let current = &report.current_weather;
+        assert_eq!(current.temp, 3.4);
+        assert_eq!(current.feels_like, 3.4);
+        assert_eq!(current.pressure, Some(1008.2));
+        assert_eq!(current.uv_index, Some(0.0));
+        assert_eq!(current.wind_gust, Some(7.9));
+        assert_eq!(current.condition_code, condition::LIGHT_RAIN);
+        assert_eq!(current.icon, "10d");
+        assert_eq!(current.description, "light rain");
+
+        // The 6 hour forecast isn't hourly.
+        assert_eq!(report.hourly.len(), 3);
+        assert_eq!(report.hourly[2].rain_chance, 30.0);
+        assert_eq!(report.hourly[2].description, "snow");
+
+        assert_eq!(report.forecast.len(), 2);
+        let tomorrow = &report.forecast[1];
+        assert_eq!(tomorrow.date, NaiveDate::from_ymd(2024, 1, 11));
+        assert_eq!(tomorrow.min_temp, -1.5);
+        assert_eq!(tomorrow.max_temp, 2.1);
+        assert_eq!(tomorrow.rain_chance, 35.0);
+        assert_eq!(tomorrow.wind_speed, 5.5);
+        assert_eq!(tomorrow.description, "few clouds");
+        assert!(report.alerts.is_empty());
+    }
+
+    #[test]
+    fn test_convert_units() {
+        let report = report().in_units(&Units::from(&TemperatureUnits::Fahrenheit));
+        assert!((report.current_weather.temp - 38.12).abs() < 0.01);
+        assert!((report.forecast[1].max_temp - 35.78).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_symbol_codes() {
+        assert_eq!(
+            condition("lightssleetshowersandthunder_polartwilight"),
+            (condition::THUNDERSTORM_WITH_RAIN, true)
+        );
+        assert_eq!(
+            condition("heavysnowshowers_night"),
+            (condition::SNOW_SHOWERS, false)
+        );
+        assert_eq!(condition("heavyrain"), (condition::HEAVY_RAIN, true));
+        assert_eq!(condition("fog"), (condition::FOG, true));
+    }
+}
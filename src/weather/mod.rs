@@ -1,69 +1,508 @@
-use crate::{message::UpdateMessage, service::Service, settings::SETTINGS};
+use crate::{
+    message::UpdateMessage,
+    service::Service,
+    settings::{Language, WeatherSettings, SETTINGS},
+};
 use actix_rt::time::interval;
 use async_trait::async_trait;
-use chrono::NaiveDate;
+use chrono::{DateTime, Local, NaiveDate};
 use futures::channel::mpsc;
-use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use lazy_static::lazy_static;
+use reqwest::{
+    header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::task;
 
+mod alerts;
+mod cache;
+mod condition;
+mod geocode;
+mod history;
+mod met_norway;
+mod nws;
+mod open_meteo;
 mod openweather;
-use openweather::{OpenWeatherCurrent, OpenWeatherForecast, OpenWeatherReport};
+mod units;
+pub use alerts::AlertUpdate;
+use alerts::{AlertTracker, WeatherAlert};
+use cache::WeatherCache;
+pub use geocode::{geocode, geocode_online, search_bundled, Place};
+pub use history::{summarize, HistoryEntry, WeatherHistory};
+use met_norway::MetNorway;
+use nws::Nws;
+use open_meteo::OpenMeteo;
+use openweather::{
+    OpenWeather, OpenWeatherAlert, OpenWeatherCurrent, OpenWeatherForecast, OpenWeatherHourly,
+    OpenWeatherMinutely, OpenWeatherReport,
+};
+use units::Converter;
+pub use units::{TemperatureUnits, Units};
 
+/// Where the weather comes from, along with any settings the provider has.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum WeatherSource {
+    // Uses the api_key from the weather settings.
     OpenWeather,
+    // https://open-meteo.com, which doesn't need a key for non-commercial use.
+    OpenMeteo(OpenMeteo),
+    // The US National Weather Service at https://weather.gov, which only covers the US.
+    Nws(Nws),
+    // The Norwegian Meteorological Institute at https://api.met.no, which covers everywhere.
+    MetNorway(MetNorway),
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub enum TemperatureUnits {
-    Kelvin,
-    Celsius,
-    Fahrenheit,
+impl WeatherSource {
+    pub fn provider(&self, api_key: &str) -> Box<dyn WeatherProvider + Send + Sync> {
+        match self {
+            WeatherSource::OpenWeather => Box::new(OpenWeather {
+                api_key: api_key.to_string(),
+            }),
+            WeatherSource::OpenMeteo(provider) => Box::new(provider.clone()),
+            WeatherSource::Nws(provider) => Box::new(provider.clone()),
+            WeatherSource::MetNorway(provider) => Box::new(provider.clone()),
+        }
+    }
+}
+
+/// A place to get the weather for.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WeatherLocation {
+    // What the location is called, like "Home" or "Cabin". This is also what it's asked for by.
+    pub name: String,
+    // Where the location is. If these are left out when the settings are changed, they're
+    // looked up from the name.
+    #[serde(default)]
+    pub lat: Option<f32>,
+    #[serde(default)]
+    pub lon: Option<f32>,
+    // Whether this is the location shown when no location is asked for.
+    #[serde(default)]
+    pub primary: bool,
 }
 
+impl WeatherLocation {
+    /// The location marked as primary, or the first location if none of them are. There's always
+    /// at least one location.
+    pub fn primary(locations: &[WeatherLocation]) -> &WeatherLocation {
+        locations
+            .iter()
+            .find(|location| location.primary)
+            .unwrap_or(&locations[0])
+    }
+}
+
+/// The latest weather for every location, by name.
 #[derive(Serialize, Deserialize, Clone)]
+pub struct WeatherReports {
+    // The name of the primary location.
+    pub primary: String,
+    pub reports: BTreeMap<String, WeatherReport>,
+}
+
+impl WeatherReports {
+    /// Finds the report for a location from its name as it was said or typed, or the primary
+    /// location's report if no location was given. Names are matched a word at a time ignoring
+    /// case and punctuation, and a location is also found if it's the end of what was heard, so
+    /// "the cabin" finds "Cabin".
+    pub fn find(&self, location: Option<&str>) -> Option<(&String, &WeatherReport)> {
+        let location = match location {
+            Some(location) => words(location),
+            None => return self.reports.get_key_value(&self.primary),
+        };
+        let matching = |exact: bool| {
+            self.reports.iter().find(|(name, _)| {
+                let name = words(name);
+                if exact {
+                    name == location
+                } else {
+                    !name.is_empty() && location.ends_with(&name)
+                }
+            })
+        };
+        matching(true).or_else(|| matching(false))
+    }
+}
+
+/// Splits a name up into lowercase words without any punctuation.
+fn words(name: &str) -> Vec<String> {
+    name.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// What to get the weather for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeatherRequest {
+    pub lat: f32,
+    pub lon: f32,
+    // The language to describe the weather in.
+    pub language: Language,
+}
+
+/// Somewhere we can get the weather from.
+#[async_trait]
+pub trait WeatherProvider {
+    /// What to call the provider when it can't be reached.
+    fn name(&self) -> &'static str;
+
+    async fn get_weather(
+        &self,
+        request: &WeatherRequest,
+    ) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Looks places up by name, for providers that can. Those that can't don't find anything.
+    async fn geocode(
+        &self,
+        _query: &str,
+        _language: Language,
+    ) -> Result<Vec<Place>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(vec![])
+    }
+}
+
+/// The weather providers to try, in order: the weather source and then the fallbacks.
+fn weather_providers(
+    weather_settings: &WeatherSettings,
+) -> Vec<Box<dyn WeatherProvider + Send + Sync>> {
+    std::iter::once(&weather_settings.weather_source)
+        .chain(&weather_settings.fallback_sources)
+        .map(|source| source.provider(&weather_settings.api_key))
+        .collect()
+}
+
+lazy_static! {
+    // Responses from weather APIs that said how to check whether they've changed, keyed by URL.
+    static ref RESPONSES: Mutex<HashMap<String, CachedResponse>> = Mutex::new(HashMap::new());
+}
+
+struct CachedResponse {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+    body: String,
+}
+
+/// Fetches JSON from a weather API. Some APIs turn away requests that don't say who's making
+/// them, so those need a user agent. If the API said how to tell whether a response has changed,
+/// the next request for it is conditional, and the last response is used if it hasn't.
+async fn get_json<T: DeserializeOwned>(
+    url: &str,
+    user_agent: Option<&str>,
+) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+    let mut client = reqwest::Client::builder();
+    if let Some(user_agent) = user_agent {
+        client = client.user_agent(user_agent);
+    }
+    let mut request = client.build()?.get(url);
+    if let Some(cached) = RESPONSES.lock().unwrap().get(url) {
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+    }
+
+    let response = request.send().await?;
+    let body = if response.status() == StatusCode::NOT_MODIFIED {
+        let responses = RESPONSES.lock().unwrap();
+        let cached = responses
+            .get(url)
+            .ok_or("got 304 Not Modified for a new request")?;
+        cached.body.clone()
+    } else {
+        let response = response.error_for_status()?;
+        let etag = response.headers().get(ETAG).cloned();
+        let last_modified = response.headers().get(LAST_MODIFIED).cloned();
+        let body = response.text().await?;
+        if etag.is_some() || last_modified.is_some() {
+            let cached = CachedResponse {
+                etag,
+                last_modified,
+                body: body.clone(),
+            };
+            RESPONSES.lock().unwrap().insert(url.to_string(), cached);
+        }
+        body
+    };
+    Ok(serde_json::from_str(&body)?)
+}
+
+/// Asks each provider for the weather in turn until one of them comes back with it.
+async fn fetch_weather(
+    providers: &[Box<dyn WeatherProvider + Send + Sync>],
+    request: &WeatherRequest,
+) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
+    let mut errors = vec![];
+    for provider in providers {
+        match provider.get_weather(request).await {
+            Ok(mut report) => {
+                report.provider = Some(provider.name().to_string());
+                return Ok(report);
+            }
+            Err(e) => {
+                eprintln!("Couldn't get weather from {}: {:?}", provider.name(), e);
+                errors.push(format!("{}: {}", provider.name(), e));
+            }
+        }
+    }
+    Err(format!(
+        "no weather provider could be reached ({})",
+        errors.join(", ")
+    )
+    .into())
+}
+
+/// How far through its cycle the moon is on the given day, for providers that don't say. 0 and 1
+/// are new moons and 0.5 is a full moon.
+fn moon_phase(date: NaiveDate) -> f32 {
+    const SYNODIC_MONTH: f64 = 29.530_588_853;
+    // There was a new moon at 18:14 UTC on the 6th of January 2000.
+    let known_new_moon = NaiveDate::from_ymd(2000, 1, 6).and_hms(18, 14, 0);
+    let days = (date.and_hms(12, 0, 0) - known_new_moon).num_seconds() as f64 / 86_400.0;
+    (days / SYNODIC_MONTH).rem_euclid(1.0) as f32
+}
+
+/// The weather for one place, always kept in Celsius, m/s, hPa, mm and metres no matter which
+/// provider it came from. It's converted to the units from the settings as it's serialized, so it
+/// goes out in whatever units are picked at the time, and converted back as it's deserialized.
+#[derive(Deserialize, Clone)]
+#[serde(from = "ReportInUnits")]
 pub struct WeatherReport {
+    pub current_weather: CurrentWeather,
+    forecast: Vec<Forecast>,
+    // The next 48 hours or so.
+    hourly: Vec<HourlyForecast>,
+    // Precipitation for the next hour, a minute at a time. This is empty for places and weather
+    // providers that don't have it.
+    minutely: Vec<MinutelyPrecipitation>,
+    // When precipitation is next expected within the next hour, if it is at all.
+    precipitation_starts_at: Option<DateTime<Local>>,
+    // Severe weather alerts that are currently out.
+    pub alerts: Vec<WeatherAlert>,
+    // When the report came from the weather provider.
+    pub fetched_at: Option<DateTime<Local>>,
+    // The name of the weather provider it came from.
+    pub provider: Option<String>,
+    // Set when no weather provider could be reached and this is the last report we got, which is
+    // older than the cache TTL.
+    pub stale: bool,
+}
+
+/// A weather report as it's sent out, in the units it says it's in.
+#[derive(Serialize, Deserialize)]
+struct ReportInUnits {
+    // Reports from before units could be picked are in the units weather is kept in.
+    #[serde(default)]
+    units: Units,
     current_weather: CurrentWeather,
     forecast: Vec<Forecast>,
+    #[serde(default)]
+    hourly: Vec<HourlyForecast>,
+    #[serde(default)]
+    minutely: Vec<MinutelyPrecipitation>,
+    #[serde(default)]
+    precipitation_starts_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    alerts: Vec<WeatherAlert>,
+    #[serde(default)]
+    fetched_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    provider: Option<String>,
+    #[serde(default)]
+    stale: bool,
+}
+
+impl WeatherReport {
+    /// The forecast for the given day, if the report goes out that far.
+    pub fn forecast_for(&self, date: NaiveDate) -> Option<&Forecast> {
+        self.forecast.iter().find(|forecast| forecast.date == date)
+    }
+
+    /// Converts every measurement in the report.
+    fn convert(&mut self, converter: &Converter) {
+        let temp = |temp: &mut f32| *temp = (converter.temp)(*temp);
+        let optional_temp = |temp: &mut Option<f32>| *temp = temp.map(&converter.temp);
+        let speed = |speed: &mut f32| *speed = (converter.speed)(*speed);
+        let optional_speed = |speed: &mut Option<f32>| *speed = speed.map(&converter.speed);
+        let pressure = |pressure: &mut Option<f32>| *pressure = pressure.map(&converter.pressure);
+
+        let current = &mut self.current_weather;
+        temp(&mut current.temp);
+        temp(&mut current.feels_like);
+        optional_temp(&mut current.dew_point);
+        speed(&mut current.wind_speed);
+        optional_speed(&mut current.wind_gust);
+        pressure(&mut current.pressure);
+        current.visibility = current.visibility.map(&converter.distance);
+        for forecast in &mut self.forecast {
+            temp(&mut forecast.min_temp);
+            temp(&mut forecast.max_temp);
+            optional_temp(&mut forecast.feels_like);
+            optional_temp(&mut forecast.dew_point);
+            speed(&mut forecast.wind_speed);
+            optional_speed(&mut forecast.wind_gust);
+            pressure(&mut forecast.pressure);
+        }
+        for hourly in &mut self.hourly {
+            temp(&mut hourly.temp);
+        }
+        for minutely in &mut self.minutely {
+            minutely.precipitation = (converter.precipitation)(minutely.precipitation);
+        }
+    }
+
+    /// The report as it would be sent out in the given units.
+    fn in_units(&self, units: &Units) -> ReportInUnits {
+        let mut report = self.clone();
+        report.convert(&units.convert_from_metric());
+        ReportInUnits {
+            units: units.clone(),
+            current_weather: report.current_weather,
+            forecast: report.forecast,
+            hourly: report.hourly,
+            minutely: report.minutely,
+            precipitation_starts_at: report.precipitation_starts_at,
+            alerts: report.alerts,
+            fetched_at: report.fetched_at,
+            provider: report.provider,
+            stale: report.stale,
+        }
+    }
+}
+
+impl Serialize for WeatherReport {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let units = SETTINGS.read().unwrap().weather_settings.units();
+        self.in_units(&units).serialize(serializer)
+    }
+}
+
+impl From<ReportInUnits> for WeatherReport {
+    fn from(report: ReportInUnits) -> Self {
+        let mut converted = Self {
+            current_weather: report.current_weather,
+            forecast: report.forecast,
+            hourly: report.hourly,
+            minutely: report.minutely,
+            precipitation_starts_at: report.precipitation_starts_at,
+            alerts: report.alerts,
+            fetched_at: report.fetched_at,
+            provider: report.provider,
+            stale: report.stale,
+        };
+        converted.convert(&report.units.convert_to_metric());
+        converted
+    }
 }
 
 impl From<OpenWeatherReport> for WeatherReport {
     fn from(report: OpenWeatherReport) -> Self {
         let current_weather = report.current.into();
         let forecast = report.daily.iter().map(|x| x.into()).collect();
+        let hourly = report.hourly.iter().map(|x| x.into()).collect();
+        let minutely: Vec<MinutelyPrecipitation> =
+            report.minutely.iter().map(|x| x.into()).collect();
+        let precipitation_starts_at = minutely
+            .iter()
+            .find(|minute| minute.precipitation > 0.0)
+            .map(|minute| minute.time);
+        let alerts = report.alerts.iter().map(|x| x.into()).collect();
         Self {
             current_weather,
             forecast,
+            hourly,
+            minutely,
+            precipitation_starts_at,
+            alerts,
+            fetched_at: None,
+            provider: None,
+            stale: false,
         }
     }
 }
 
+/// Anything optional is something not every provider has.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CurrentWeather {
-    temp: f32,
-    humidity: f32,
-    description: String,
+    pub temp: f32,
+    pub feels_like: f32,
+    pub humidity: f32,
+    pub pressure: Option<f32>,
+    pub dew_point: Option<f32>,
+    pub visibility: Option<f32>,
+    pub uv_index: Option<f32>,
+    pub cloudiness: f32,
+    pub wind_speed: f32,
+    // The direction the wind is coming from, in degrees clockwise from north.
+    pub wind_direction: f32,
+    pub wind_gust: Option<f32>,
+    // https://openweathermap.org/weather-conditions
+    pub condition_code: u32,
+    pub icon: String,
+    pub description: String,
+    pub sunrise: Option<DateTime<Local>>,
+    pub sunset: Option<DateTime<Local>>,
 }
 
 impl From<OpenWeatherCurrent> for CurrentWeather {
     fn from(current: OpenWeatherCurrent) -> Self {
         Self {
             temp: current.temp,
+            feels_like: current.feels_like,
             humidity: current.humidity,
+            pressure: Some(current.pressure),
+            dew_point: Some(current.dew_point),
+            visibility: current.visibility,
+            uv_index: Some(current.uv_index),
+            cloudiness: current.cloudiness,
+            wind_speed: current.wind_speed,
+            wind_direction: current.wind_direction,
+            wind_gust: current.wind_gust,
+            condition_code: current.condition_code,
+            icon: current.icon,
             description: current.description,
+            sunrise: current.sunrise,
+            sunset: current.sunset,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Forecast {
-    date: NaiveDate,
-    min_temp: f32,
-    max_temp: f32,
+    pub date: NaiveDate,
+    pub min_temp: f32,
+    pub max_temp: f32,
     humidity: f32,
     rain_chance: f32,
     cloudiness: f32,
-    description: String,
+    pub description: String,
+    // How warm it feels during the day.
+    feels_like: Option<f32>,
+    pressure: Option<f32>,
+    dew_point: Option<f32>,
+    uv_index: Option<f32>,
+    wind_speed: f32,
+    wind_direction: f32,
+    wind_gust: Option<f32>,
+    condition_code: u32,
+    icon: String,
+    sunrise: Option<DateTime<Local>>,
+    sunset: Option<DateTime<Local>>,
+    // 0 and 1 are new moons, 0.5 is a full moon.
+    moon_phase: f32,
 }
 
 impl From<&OpenWeatherForecast> for Forecast {
@@ -76,6 +515,69 @@ impl From<&OpenWeatherForecast> for Forecast {
             rain_chance: forecast.rain_chance * 100.0,
             cloudiness: forecast.cloudiness,
             description: forecast.description.clone(),
+            feels_like: Some(forecast.feels_like),
+            pressure: Some(forecast.pressure),
+            dew_point: Some(forecast.dew_point),
+            uv_index: Some(forecast.uv_index),
+            wind_speed: forecast.wind_speed,
+            wind_direction: forecast.wind_direction,
+            wind_gust: forecast.wind_gust,
+            condition_code: forecast.condition_code,
+            icon: forecast.icon.clone(),
+            sunrise: forecast.sunrise,
+            sunset: forecast.sunset,
+            moon_phase: forecast.moon_phase,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HourlyForecast {
+    time: DateTime<Local>,
+    temp: f32,
+    humidity: f32,
+    rain_chance: f32,
+    cloudiness: f32,
+    description: String,
+}
+
+impl From<&OpenWeatherHourly> for HourlyForecast {
+    fn from(hourly: &OpenWeatherHourly) -> Self {
+        Self {
+            time: hourly.time,
+            temp: hourly.temp,
+            humidity: hourly.humidity,
+            rain_chance: hourly.rain_chance * 100.0,
+            cloudiness: hourly.cloudiness,
+            description: hourly.description.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MinutelyPrecipitation {
+    time: DateTime<Local>,
+    // In mm/h.
+    precipitation: f32,
+}
+
+impl From<&OpenWeatherMinutely> for MinutelyPrecipitation {
+    fn from(minutely: &OpenWeatherMinutely) -> Self {
+        Self {
+            time: minutely.time,
+            precipitation: minutely.precipitation,
+        }
+    }
+}
+
+impl From<&OpenWeatherAlert> for WeatherAlert {
+    fn from(alert: &OpenWeatherAlert) -> Self {
+        Self {
+            sender: alert.sender_name.clone(),
+            event: alert.event.clone(),
+            start: alert.start,
+            end: alert.end,
+            description: alert.description.clone(),
         }
     }
 }
@@ -83,11 +585,23 @@ impl From<&OpenWeatherForecast> for Forecast {
 #[derive(Clone)]
 pub struct WeatherService {
     tx: Option<mpsc::Sender<Box<dyn erased_serde::Serialize + Send + Sync>>>,
+    // Alerts go straight out to the frontend so they don't replace the latest weather report.
+    update_tx: Option<mpsc::UnboundedSender<String>>,
+    // These are kept for each location, by name.
+    alerts: HashMap<String, AlertTracker>,
+    caches: HashMap<String, WeatherCache>,
+    history: WeatherHistory,
 }
 
 impl WeatherService {
     pub fn new() -> Self {
-        Self { tx: None }
+        Self {
+            tx: None,
+            update_tx: None,
+            alerts: HashMap::new(),
+            caches: HashMap::new(),
+            history: WeatherHistory::default(),
+        }
     }
 }
 
@@ -97,6 +611,10 @@ impl Service for WeatherService {
         self.tx = Some(tx);
     }
 
+    fn set_update_sender(&mut self, update_tx: mpsc::UnboundedSender<String>) {
+        self.update_tx = Some(update_tx);
+    }
+
     async fn start_service(&mut self) {
         // The interval between queries of the weather API is set at the start of the application
         // so changing the setting afterwards doesn't have any effect at the moment.
@@ -107,18 +625,16 @@ impl Service for WeatherService {
         }
         let mut interval = interval(Duration::from_secs(polling_rate));
         loop {
-            match self.get_weather_report().await {
-                Ok(report) => {
-                    if let Some(tx) = &mut self.tx {
-                        let weather_message = UpdateMessage::Weather(report);
-                        if tx.try_send(Box::new(weather_message)).is_err() {
-                            eprintln!("Reciever has been closed.");
-                        }
-                    } else {
-                        eprintln!("News transmitter not set.");
-                    }
+            let reports = self.get_weather_reports().await;
+            if reports.reports.is_empty() {
+                eprintln!("Couldn't get weather for any location.");
+            } else if let Some(tx) = &mut self.tx {
+                let weather_message = UpdateMessage::Weather(reports);
+                if tx.try_send(Box::new(weather_message)).is_err() {
+                    eprintln!("Reciever has been closed.");
                 }
-                Err(e) => eprintln!("Couldn't get weather: {:?}", e),
+            } else {
+                eprintln!("News transmitter not set.");
             }
             interval.tick().await;
         }
@@ -130,33 +646,278 @@ impl Service for WeatherService {
 }
 
 impl WeatherService {
-    /// Gets the current weather from the user's chosen weather provider. Polls current weather
-    /// settings information prior to querying for weather.
-    async fn get_weather_report(
-        &self,
-    ) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
-        let weather_source;
-        let api_key;
-        let temp_units;
-        let lat;
-        let lon;
+    /// Gets the current weather for every location. Polls current weather settings information
+    /// prior to querying for weather. Locations we couldn't get any weather for are left out.
+    async fn get_weather_reports(&mut self) -> WeatherReports {
+        let providers;
+        let locations;
+        let language;
+        let ttl;
+        let history_days;
         {
             let settings = SETTINGS.read().unwrap();
             let weather_settings = &settings.weather_settings;
-            weather_source = weather_settings.weather_source.clone();
-            api_key = weather_settings.api_key.clone();
-            temp_units = weather_settings.temp_units.clone();
-            lon = weather_settings.lon;
-            lat = weather_settings.lat;
-        }
-        match weather_source {
-            WeatherSource::OpenWeather => {
-                openweather::get_weather(lat, lon, temp_units, api_key).await
+            providers = weather_providers(weather_settings);
+            locations = weather_settings.locations();
+            language = settings.language;
+            ttl = chrono::Duration::seconds(weather_settings.cache_ttl_secs as i64);
+            history_days = weather_settings.history_days;
+        }
+
+        let mut reports = WeatherReports {
+            primary: WeatherLocation::primary(&locations).name.clone(),
+            reports: BTreeMap::new(),
+        };
+        for location in &locations {
+            let (lat, lon) = match (location.lat, location.lon) {
+                (Some(lat), Some(lon)) => (lat, lon),
+                _ => {
+                    eprintln!("Don't know where {} is.", location.name);
+                    continue;
+                }
+            };
+            let request = WeatherRequest { lat, lon, language };
+            match self
+                .get_weather_report(&location.name, &providers, &request, ttl, history_days)
+                .await
+            {
+                Ok(report) => {
+                    self.announce_alerts(&location.name, &report.alerts);
+                    reports.reports.insert(location.name.clone(), report);
+                }
+                Err(e) => eprintln!("Couldn't get weather for {}: {:?}", location.name, e),
             }
         }
+        reports
+    }
+
+    /// Gets the current weather for a location from the user's chosen weather provider, falling
+    /// back on the others in order if it can't be reached. A report younger than the cache TTL
+    /// is reused, and if no provider can be reached, the last report is sent again marked as
+    /// stale. Newly fetched reports are added to the weather history, which keeps `history_days`
+    /// days of them.
+    async fn get_weather_report(
+        &mut self,
+        location: &str,
+        providers: &[Box<dyn WeatherProvider + Send + Sync>],
+        request: &WeatherRequest,
+        ttl: chrono::Duration,
+        history_days: u32,
+    ) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
+        let cache = self.caches.entry(location.to_string()).or_default();
+        let now = Local::now();
+        if let Some(report) = cache.fresh(request, ttl, &now) {
+            return Ok(report);
+        }
+        match fetch_weather(providers, request).await {
+            Ok(mut report) => {
+                report.fetched_at = Some(now);
+                cache.store(request, &report);
+                let entry = HistoryEntry::new(now, location, &report);
+                self.save_history(entry, history_days).await;
+                Ok(report)
+            }
+            Err(e) => match cache.stale(request) {
+                Some(report) => {
+                    eprintln!("Couldn't get weather, sending the last report: {:?}", e);
+                    Ok(report)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Adds an entry to the weather history, keeping `keep_days` days of it. Saving the history
+    /// means writing to a file, and once a day rewriting all of it, so it's done off of the async
+    /// runtime.
+    async fn save_history(&mut self, entry: HistoryEntry, keep_days: u32) {
+        let mut history = self.history.clone();
+        let saved = task::spawn_blocking(move || {
+            let result = history.append(&entry, keep_days).map_err(|e| e.to_string());
+            (history, result)
+        })
+        .await;
+        match saved {
+            Ok((history, result)) => {
+                self.history = history;
+                if let Err(e) = result {
+                    eprintln!("Couldn't save the weather history: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Couldn't save the weather history: {:?}", e),
+        }
     }
 
     pub fn get_service_name() -> String {
         String::from("Weather")
     }
+
+    /// Sends out any alerts that are new, have changed or have expired since the last report.
+    fn announce_alerts(&mut self, location: &str, alerts: &[WeatherAlert]) {
+        let tracker = self
+            .alerts
+            .entry(location.to_string())
+            .or_insert_with(|| AlertTracker::new(location));
+        for update in tracker.update(alerts, &Local::now()) {
+            let message = serde_json::to_string(&UpdateMessage::WeatherAlert(update)).unwrap();
+            match &self.update_tx {
+                Some(update_tx) => {
+                    if update_tx.unbounded_send(message).is_err() {
+                        eprintln!("Reciever has been closed.");
+                    }
+                }
+                None => eprintln!("Weather alert transmitter not set."),
+            }
+        }
+    }
+}
+
+/// The report in the OpenWeather fixture, which the weather tests build on.
+#[cfg(test)]
+pub(crate) fn openweather_fixture() -> WeatherReport {
+    let resp: OpenWeatherReport =
+        serde_json::from_str(include_str!("fixtures/openweather_onecall.json")).unwrap();
+    resp.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Language;
+    use futures::executor::block_on;
+
+    /// A provider that's either down or always has the weather from the OpenWeather fixture.
+    struct TestProvider {
+        name: &'static str,
+        up: bool,
+    }
+
+    #[async_trait]
+    impl WeatherProvider for TestProvider {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn get_weather(
+            &self,
+            _request: &WeatherRequest,
+        ) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
+            if !self.up {
+                return Err("503 Service Unavailable".into());
+            }
+            let mut report = openweather_fixture();
+            report.current_weather.description = self.name.to_string();
+            Ok(report)
+        }
+    }
+
+    fn providers(up: &[bool]) -> Vec<Box<dyn WeatherProvider + Send + Sync>> {
+        ["first", "second", "third"]
+            .iter()
+            .zip(up)
+            .map(|(&name, &up)| {
+                Box::new(TestProvider { name, up }) as Box<dyn WeatherProvider + Send + Sync>
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_location() {
+        let report = openweather_fixture();
+        let reports = WeatherReports {
+            primary: "Home".into(),
+            reports: ["Home", "Cabin", "Grandma's"]
+                .iter()
+                .map(|name| (name.to_string(), report.clone()))
+                .collect(),
+        };
+        let found = |location| reports.find(location).map(|(name, _)| name.as_str());
+        assert_eq!(found(None), Some("Home"));
+        assert_eq!(found(Some("cabin")), Some("Cabin"));
+        assert_eq!(found(Some("the cabin")), Some("Cabin"));
+        assert_eq!(found(Some("grandmas")), Some("Grandma's"));
+        assert_eq!(found(Some("paris")), None);
+
+        let locations = vec![
+            WeatherLocation {
+                name: "Home".into(),
+                lat: Some(52.5),
+                lon: Some(13.4),
+                primary: false,
+            },
+            WeatherLocation {
+                name: "Cabin".into(),
+                lat: Some(47.4),
+                lon: Some(11.0),
+                primary: true,
+            },
+        ];
+        assert_eq!(WeatherLocation::primary(&locations).name, "Cabin");
+        assert_eq!(WeatherLocation::primary(&locations[..1]).name, "Home");
+    }
+
+    #[test]
+    fn test_failover() {
+        let request = WeatherRequest {
+            lat: 52.5,
+            lon: 13.4,
+            language: Language::English,
+        };
+        let report = block_on(fetch_weather(&providers(&[true, true]), &request)).unwrap();
+        assert_eq!(report.current_weather.description, "first");
+        let report = block_on(fetch_weather(&providers(&[false, false, true]), &request)).unwrap();
+        assert_eq!(report.current_weather.description, "third");
+        assert_eq!(report.provider.as_deref(), Some("third"));
+
+        let error = block_on(fetch_weather(&providers(&[false, false]), &request))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "no weather provider could be reached (first: 503 Service Unavailable, \
+            second: 503 Service Unavailable)"
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        let report = openweather_fixture();
+        let units = Units {
+            temperature: TemperatureUnits::Fahrenheit,
+            wind: units::WindUnits::Knots,
+            pressure: units::PressureUnits::InchesOfMercury,
+            precipitation: units::PrecipitationUnits::Inches,
+            distance: units::DistanceUnits::Kilometres,
+        };
+        let json = serde_json::to_value(report.in_units(&units)).unwrap();
+        let current = &json["current_weather"];
+        assert_eq!(json["units"]["wind"], "Knots");
+        assert!((current["temp"].as_f64().unwrap() - 54.14).abs() < 0.01);
+        assert!((current["wind_speed"].as_f64().unwrap() - 8.01).abs() < 0.01);
+        assert!((current["pressure"].as_f64().unwrap() - 29.88).abs() < 0.01);
+        assert!((current["visibility"].as_f64().unwrap() - 10.0).abs() < 0.01);
+        assert!((json["minutely"][4]["precipitation"].as_f64().unwrap() - 0.035).abs() < 0.001);
+
+        // Reading it back gets the same report as before, whatever units it was sent in.
+        let read: WeatherReport = serde_json::from_value(json).unwrap();
+        let current = &read.current_weather;
+        assert!((current.temp - 12.3).abs() < 0.01);
+        assert!((current.wind_speed - 4.12).abs() < 0.01);
+        assert!((current.pressure.unwrap() - 1012.0).abs() < 0.01);
+        assert!((read.minutely[4].precipitation - 0.9).abs() < 0.001);
+
+        // Reports from before there were units are in the units weather is kept in.
+        let mut json = serde_json::to_value(report.in_units(&Units::default())).unwrap();
+        json.as_object_mut().unwrap().remove("units");
+        let read: WeatherReport = serde_json::from_value(json).unwrap();
+        assert_eq!(read.current_weather.temp, 12.3);
+    }
+
+    #[test]
+    fn test_moon_phase() {
+        // A full moon and a new moon.
+        assert!((moon_phase(NaiveDate::from_ymd(2024, 3, 25)) - 0.5).abs() < 0.03);
+        let phase = moon_phase(NaiveDate::from_ymd(2024, 4, 8));
+        assert!(!(0.03..=0.97).contains(&phase), "{}", phase);
+    }
 }
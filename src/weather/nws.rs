@@ -0,0 +1,463 @@
+use crate::weather::{
+    alerts::WeatherAlert,
+    condition::{self, Conditions},
+    get_json, moon_phase, CurrentWeather, Forecast, HourlyForecast, WeatherProvider, WeatherReport,
+    WeatherRequest,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+const API_URL: &str = "https://api.weather.gov";
+const COMPASS_POINTS: [&str; 16] = [
+    "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW", "NW",
+    "NNW",
+];
+
+/// The US National Weather Service's API.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Nws {
+    // The NWS asks for a user agent saying who's making requests, like "(smart_tablet,
+    // you@example.com)", so they can get in touch if something's wrong. Requests without one may
+    // be blocked.
+    pub user_agent: String,
+}
+
+#[async_trait]
+impl WeatherProvider for Nws {
+    fn name(&self) -> &'static str {
+        "the NWS"
+    }
+
+    /// The NWS splits the weather up over a bunch of endpoints. Looking up the location gives us
+    /// where to find the forecasts and the nearby weather stations, and the current weather is
+    /// whatever the nearest station saw last.
+    async fn get_weather(
+        &self,
+        request: &WeatherRequest,
+    ) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
+        let user_agent = Some(self.user_agent.as_str());
+        let location = format!("{:.4},{:.4}", request.lat, request.lon);
+        let points: Points =
+            get_json(&format!("{}/points/{}", API_URL, location), user_agent).await?;
+        let points = points.properties;
+        let stations: Stations = get_json(&points.observation_stations, user_agent).await?;
+        let station = stations
+            .features
+            .first()
+            .ok_or("no weather stations nearby")?;
+
+        let resp = NwsReport {
+            observation: get_json(&format!("{}/observations/latest", station.id), user_agent)
+                .await?,
+            // The forecasts are in Fahrenheit and mph unless we ask otherwise.
+            forecast: get_json(&format!("{}?units=si", points.forecast), user_agent).await?,
+            hourly: get_json(&format!("{}?units=si", points.forecast_hourly), user_agent).await?,
+            alerts: get_json(
+                &format!("{}/alerts/active?point={}", API_URL, location),
+                user_agent,
+            )
+            .await?,
+        };
+        resp.into_report(&Conditions::init(request.language)?)
+    }
+}
+
+#[derive(Deserialize)]
+struct Points {
+    properties: PointsProperties,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PointsProperties {
+    forecast: String,
+    forecast_hourly: String,
+    observation_stations: String,
+}
+
+#[derive(Deserialize)]
+struct Stations {
+    features: Vec<Station>,
+}
+
+#[derive(Deserialize)]
+struct Station {
+    // The station's URL.
+    id: String,
+}
+
+/// Everything that goes into a report, in Celsius, km/h and Pa.
+#[derive(Deserialize)]
+struct NwsReport {
+    observation: Observation,
+    // Covers a week in 12 hour periods, alternating between day and night.
+    forecast: Periods,
+    // Covers a week an hour at a time.
+    hourly: Periods,
+    alerts: Alerts,
+}
+
+#[derive(Deserialize)]
+struct Observation {
+    properties: ObservationProperties,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObservationProperties {
+    icon: Option<String>,
+    #[serde(default)]
+    temperature: Measurement,
+    #[serde(default)]
+    dewpoint: Measurement,
+    #[serde(default)]
+    wind_direction: Measurement,
+    #[serde(default)]
+    wind_speed: Measurement,
+    #[serde(default)]
+    wind_gust: Measurement,
+    #[serde(default)]
+    barometric_pressure: Measurement,
+    #[serde(default)]
+    sea_level_pressure: Measurement,
+    #[serde(default)]
+    visibility: Measurement,
+    #[serde(default)]
+    relative_humidity: Measurement,
+    #[serde(default)]
+    wind_chill: Measurement,
+    #[serde(default)]
+    heat_index: Measurement,
+}
+
+/// A value from the NWS. Weather stations often leave out what they don't measure.
+#[derive(Deserialize, Default)]
+struct Measurement {
+    value: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct Periods {
+    properties: PeriodsProperties,
+}
+
+#[derive(Deserialize)]
+struct PeriodsProperties {
+    periods: Vec<Period>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Period {
+    start_time: DateTime<Local>,
+    is_daytime: bool,
+    temperature: f32,
+    #[serde(default)]
+    probability_of_precipitation: Measurement,
+    #[serde(default)]
+    relative_humidity: Measurement,
+    #[serde(default)]
+    dewpoint: Measurement,
+    // Like "10 to 15 km/h".
+    wind_speed: String,
+    // Like "SW".
+    wind_direction: String,
+    icon: String,
+}
+
+#[derive(Deserialize)]
+struct Alerts {
+    features: Vec<AlertFeature>,
+}
+
+#[derive(Deserialize)]
+struct AlertFeature {
+    properties: AlertProperties,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlertProperties {
+    sender_name: String,
+    event: String,
+    effective: DateTime<Local>,
+    // When the weather is expected to start, if that's later than the alert.
+    onset: Option<DateTime<Local>>,
+    expires: DateTime<Local>,
+    // When the weather is expected to end, if that's later than the alert.
+    ends: Option<DateTime<Local>>,
+    description: String,
+}
+
+/// Works out the condition and whether it's day from an icon URL like
+/// "https://api.weather.gov/icons/land/day/tsra_sct,40/rain,60?size=medium". Icons can be split
+/// between two conditions, in which case we go with the first.
+fn icon_condition(url: &str) -> Option<(u32, bool)> {
+    let mut parts = url.split('?').next()?.split("/land/").nth(1)?.split('/');
+    let is_day = parts.next()? == "day";
+    let icon = parts.next()?.split(',').next()?;
+    let code = match icon.trim_start_matches("wind_") {
+        "skc" | "hot" | "cold" => condition::CLEAR,
+        "few" => condition::FEW_CLOUDS,
+        "sct" => condition::SCATTERED_CLOUDS,
+        "bkn" => condition::BROKEN_CLOUDS,
+        "ovc" => condition::OVERCAST,
+        "rain" => condition::RAIN,
+        "rain_showers" | "rain_showers_hi" => condition::RAIN_SHOWERS,
+        "tsra" | "tsra_sct" | "tsra_hi" => condition::THUNDERSTORM_WITH_RAIN,
+        "snow" => condition::SNOW,
+        "blizzard" => condition::HEAVY_SNOW,
+        "rain_snow" | "rain_sleet" | "snow_sleet" | "sleet" => condition::SLEET,
+        "fzra" | "rain_fzra" | "snow_fzra" => condition::FREEZING_RAIN,
+        "fog" => condition::FOG,
+        "smoke" => condition::SMOKE,
+        "haze" => condition::HAZE,
+        "dust" => condition::DUST,
+        "tornado" | "hurricane" | "tropical_storm" => condition::TORNADO,
+        _ => condition::OVERCAST,
+    };
+    Some((code, is_day))
+}
+
+/// Converts a wind speed like "10 to 15 km/h" to m/s, going with the highest speed.
+fn wind_speed(text: &str) -> f32 {
+    let km_per_hour = text
+        .split_whitespace()
+        .rev()
+        .find_map(|word| word.parse::<f32>().ok())
+        .unwrap_or_default();
+    km_per_hour / 3.6
+}
+
+/// Converts a compass direction like "SW" to degrees clockwise from north.
+fn wind_direction(text: &str) -> f32 {
+    COMPASS_POINTS
+        .iter()
+        .position(|point| *point == text)
+        .map_or(0.0, |i| i as f32 * 22.5)
+}
+
+impl NwsReport {
+    fn into_report(
+        self,
+        conditions: &Conditions,
+    ) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
+        let observation = self.observation.properties;
+        let hourly = self.hourly.properties.periods;
+        let temp = observation
+            .temperature
+            .value
+            .ok_or("the weather station didn't report a temperature")?;
+        // Stations don't always report what the weather's like, but the forecast for this hour
+        // is a decent stand-in.
+        let (code, is_day) = observation
+            .icon
+            .as_deref()
+            .and_then(icon_condition)
+            .or_else(|| {
+                let period = hourly.first()?;
+                icon_condition(&period.icon)
+            })
+            .unwrap_or((condition::OVERCAST, true));
+        let current_weather = CurrentWeather {
+            temp,
+            feels_like: observation
+                .heat_index
+                .value
+                .or(observation.wind_chill.value)
+                .unwrap_or(temp),
+            humidity: observation.relative_humidity.value.unwrap_or_default(),
+            pressure: observation
+                .sea_level_pressure
+                .value
+                .or(observation.barometric_pressure.value)
+                .map(|pascals| pascals / 100.0),
+            dew_point: observation.dewpoint.value,
+            visibility: observation.visibility.value,
+            uv_index: None,
+            cloudiness: condition::cloudiness(code),
+            wind_speed: observation.wind_speed.value.unwrap_or_default() / 3.6,
+            wind_direction: observation.wind_direction.value.unwrap_or_default(),
+            wind_gust: observation.wind_gust.value.map(|gust| gust / 3.6),
+            condition_code: code,
+            icon: condition::icon(code, is_day),
+            description: conditions.describe(code),
+            sunrise: None,
+            sunset: None,
+        };
+
+        let hourly = hourly
+            .iter()
+            .take(48)
+            .map(|period| {
+                let (code, _) = icon_condition(&period.icon).unwrap_or((condition::OVERCAST, true));
+                HourlyForecast {
+                    time: period.start_time,
+                    temp: period.temperature,
+                    humidity: period.relative_humidity.value.unwrap_or_default(),
+                    rain_chance: period
+                        .probability_of_precipitation
+                        .value
+                        .unwrap_or_default(),
+                    cloudiness: condition::cloudiness(code),
+                    description: conditions.describe(code),
+                }
+            })
+            .collect();
+
+        // Pair up the day and night periods for each day. The forecast can start with tonight,
+        // in which case there's only a night for today.
+        let mut days: Vec<(NaiveDate, Option<&Period>, Option<&Period>)> = vec![];
+        for period in &self.forecast.properties.periods {
+            let date = period.start_time.date().naive_local();
+            if days.last().map(|(last, _, _)| *last) != Some(date) {
+                days.push((date, None, None));
+            }
+            if let Some((_, day, night)) = days.last_mut() {
+                if period.is_daytime {
+                    *day = Some(period);
+                } else {
+                    *night = Some(period);
+                }
+            }
+        }
+        let forecast = days
+            .into_iter()
+            .filter_map(|(date, day, night)| {
+                let main = day.or(night)?;
+                let (code, _) = icon_condition(&main.icon).unwrap_or((condition::OVERCAST, true));
+                let rain_chance = |period: Option<&Period>| {
+                    period
+                        .and_then(|period| period.probability_of_precipitation.value)
+                        .unwrap_or_default()
+                };
+                Some(Forecast {
+                    date,
+                    min_temp: night.unwrap_or(main).temperature,
+                    max_temp: main.temperature,
+                    humidity: main.relative_humidity.value.unwrap_or_default(),
+                    rain_chance: rain_chance(day).max(rain_chance(night)),
+                    cloudiness: condition::cloudiness(code),
+                    description: conditions.describe(code),
+                    feels_like: None,
+                    pressure: None,
+                    dew_point: main.dewpoint.value,
+                    uv_index: None,
+                    wind_speed: wind_speed(&main.wind_speed),
+                    wind_direction: wind_direction(&main.wind_direction),
+                    wind_gust: None,
+                    condition_code: code,
+                    icon: condition::icon(code, true),
+                    sunrise: None,
+                    sunset: None,
+                    moon_phase: moon_phase(date),
+                })
+            })
+            .collect();
+
+        let alerts = self
+            .alerts
+            .features
+            .into_iter()
+            .map(|feature| {
+                let alert = feature.properties;
+                WeatherAlert {
+                    sender: alert.sender_name,
+                    event: alert.event,
+                    start: alert.onset.unwrap_or(alert.effective),
+                    end: alert.ends.unwrap_or(alert.expires),
+                    description: alert.description,
+                }
+            })
+            .collect();
+
+        Ok(WeatherReport {
+            current_weather,
+            forecast,
+            hourly,
+            minutely: vec![],
+            precipitation_starts_at: None,
+            alerts,
+            fetched_at: None,
+            provider: None,
+            stale: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Language;
+
+    fn report(json: &str) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
+        let resp: NwsReport = serde_json::from_str(json).unwrap();
+        let conditions = Conditions::init(Language::English).expect("No conditions file found");
+        resp.into_report(&conditions)
+    }
+
+    #[test]
+    fn test_parse() {
+        let report = report(include_str!("fixtures/nws.json")).unwrap();
+        let current = &report.current_weather;
+        assert_eq!(current.temp, 18.3);
+        assert_eq!(current.feels_like, 18.3);
+        assert_eq!(current.pressure, Some(1015.9));
+        assert_eq!(current.wind_speed, 5.0);
+        assert_eq!(current.wind_gust, None);
+        assert_eq!(current.condition_code, condition::SCATTERED_CLOUDS);
+        assert_eq!(current.icon, "03d");
+
+        assert_eq!(report.hourly.len(), 2);
+        assert_eq!(report.hourly[1].rain_chance, 40.0);
+        assert_eq!(report.hourly[1].description, "thunderstorm with rain");
+
+        // Tonight, then tomorrow's day and night.
+        assert_eq!(report.forecast.len(), 2);
+        let tonight = &report.forecast[0];
+        assert_eq!(tonight.min_temp, 12.0);
+        assert_eq!(tonight.max_temp, 12.0);
+        let tomorrow = &report.forecast[1];
+        assert_eq!(tomorrow.date, NaiveDate::from_ymd(2024, 6, 5));
+        assert_eq!(tomorrow.min_temp, 13.0);
+        assert_eq!(tomorrow.max_temp, 24.0);
+        assert_eq!(tomorrow.rain_chance, 60.0);
+        assert_eq!(tomorrow.wind_speed, 20.0 / 3.6);
+        assert_eq!(tomorrow.wind_direction, 225.0);
+        assert_eq!(tomorrow.description, "shower rain");
+
+        assert_eq!(report.alerts.len(), 1);
+        let alert = &report.alerts[0];
+        assert_eq!(alert.event, "Heat Advisory");
+        assert_eq!(
+            alert.start,
+            DateTime::parse_from_rfc3339("2024-06-04T12:00:00-04:00").unwrap()
+        );
+        assert_eq!(
+            alert.end,
+            DateTime::parse_from_rfc3339("2024-06-04T20:00:00-04:00").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_without_temperature() {
+        let json = include_str!("fixtures/nws.json").replace("\"value\": 18.3", "\"value\": null");
+        assert!(report(&json).is_err());
+    }
+
+    #[test]
+    fn test_icons_and_wind() {
+        assert_eq!(
+            icon_condition("https://api.weather.gov/icons/land/night/rain_showers,30/tsra,50"),
+            Some((condition::RAIN_SHOWERS, false))
+        );
+        assert_eq!(
+            icon_condition("https://api.weather.gov/icons/land/day/wind_bkn?size=medium"),
+            Some((condition::BROKEN_CLOUDS, true))
+        );
+        assert_eq!(icon_condition("not an icon"), None);
+        assert_eq!(wind_speed("18 km/h"), 5.0);
+        assert_eq!(wind_direction("NNW"), 337.5);
+    }
+}
@@ -0,0 +1,340 @@
+use crate::{
+    settings::Language,
+    weather::{
+        condition::{self, Conditions},
+        get_json, moon_phase, CurrentWeather, Forecast, HourlyForecast, Place, WeatherProvider,
+        WeatherReport, WeatherRequest,
+    },
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+const CURRENT_FIELDS: &str = "temperature_2m,apparent_temperature,relative_humidity_2m,\
+    pressure_msl,dew_point_2m,visibility,uv_index,cloud_cover,wind_speed_10m,wind_direction_10m,\
+    wind_gusts_10m,weather_code,is_day";
+const HOURLY_FIELDS: &str =
+    "temperature_2m,relative_humidity_2m,precipitation_probability,cloud_cover,weather_code";
+const DAILY_FIELDS: &str = "temperature_2m_min,temperature_2m_max,apparent_temperature_max,\
+    relative_humidity_2m_mean,precipitation_probability_max,cloud_cover_mean,pressure_msl_mean,\
+    dew_point_2m_mean,uv_index_max,wind_speed_10m_max,wind_direction_10m_dominant,\
+    wind_gusts_10m_max,weather_code,sunrise,sunset";
+
+/// Open-Meteo's forecast API.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct OpenMeteo {
+    // Only needed for commercial use, which goes through a different server.
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+#[async_trait]
+impl WeatherProvider for OpenMeteo {
+    fn name(&self) -> &'static str {
+        "Open-Meteo"
+    }
+
+    async fn get_weather(
+        &self,
+        request: &WeatherRequest,
+    ) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
+        let (host, key) = match &self.api_key {
+            Some(api_key) => (
+                "customer-api.open-meteo.com",
+                format!("&apikey={}", api_key),
+            ),
+            None => ("api.open-meteo.com", String::new()),
+        };
+        let uri = format!(
+            "https://{}/v1/forecast?latitude={}&longitude={}&current={}&hourly={}&daily={}\
+            &forecast_hours=48&wind_speed_unit=ms&timeformat=unixtime&timezone=auto{}",
+            host, request.lat, request.lon, CURRENT_FIELDS, HOURLY_FIELDS, DAILY_FIELDS, key,
+        );
+        let resp: OpenMeteoReport = get_json(&uri, None).await?;
+        Ok(resp.into_report(&Conditions::init(request.language)?))
+    }
+
+    /// Looks places up through Open-Meteo's geocoding API, which is free for everyone. It only
+    /// goes by name, so anything after a comma is left off.
+    async fn geocode(
+        &self,
+        query: &str,
+        language: Language,
+    ) -> Result<Vec<Place>, Box<dyn std::error::Error + Send + Sync>> {
+        let name = query.split(',').next().unwrap_or_default().trim();
+        let uri = Url::parse_with_params(
+            "https://geocoding-api.open-meteo.com/v1/search",
+            &[
+                ("name", name),
+                ("count", "5"),
+                ("language", language.code()),
+                ("format", "json"),
+            ],
+        )?;
+        let resp: GeocodingResults = get_json(uri.as_str(), None).await?;
+        Ok(resp
+            .results
+            .into_iter()
+            .map(|place| Place {
+                name: place.name,
+                country: place.country_code,
+                region: place.admin1,
+                lat: place.latitude,
+                lon: place.longitude,
+            })
+            .collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct GeocodingResults {
+    // Left out when nothing was found.
+    #[serde(default)]
+    results: Vec<GeocodingPlace>,
+}
+
+#[derive(Deserialize)]
+struct GeocodingPlace {
+    name: String,
+    latitude: f32,
+    longitude: f32,
+    country_code: String,
+    // The state or region.
+    admin1: Option<String>,
+}
+
+/// What comes back from Open-Meteo, in Celsius, m/s and hPa. The hourly and daily forecasts come
+/// as a list of values for each field, and any of the values can be missing.
+#[derive(Deserialize)]
+struct OpenMeteoReport {
+    // How far ahead of UTC the location is, which the days in the daily forecast are in.
+    utc_offset_seconds: i64,
+    current: Current,
+    hourly: Hourly,
+    daily: Daily,
+}
+
+#[derive(Deserialize)]
+struct Current {
+    temperature_2m: f32,
+    apparent_temperature: f32,
+    relative_humidity_2m: f32,
+    pressure_msl: Option<f32>,
+    dew_point_2m: Option<f32>,
+    visibility: Option<f32>,
+    uv_index: Option<f32>,
+    cloud_cover: f32,
+    wind_speed_10m: f32,
+    wind_direction_10m: f32,
+    wind_gusts_10m: Option<f32>,
+    weather_code: u32,
+    is_day: u8,
+}
+
+#[derive(Deserialize)]
+struct Hourly {
+    time: Vec<i64>,
+    temperature_2m: Vec<Option<f32>>,
+    relative_humidity_2m: Vec<Option<f32>>,
+    precipitation_probability: Vec<Option<f32>>,
+    cloud_cover: Vec<Option<f32>>,
+    weather_code: Vec<Option<u32>>,
+}
+
+#[derive(Deserialize)]
+struct Daily {
+    time: Vec<i64>,
+    temperature_2m_min: Vec<Option<f32>>,
+    temperature_2m_max: Vec<Option<f32>>,
+    apparent_temperature_max: Vec<Option<f32>>,
+    relative_humidity_2m_mean: Vec<Option<f32>>,
+    precipitation_probability_max: Vec<Option<f32>>,
+    cloud_cover_mean: Vec<Option<f32>>,
+    pressure_msl_mean: Vec<Option<f32>>,
+    dew_point_2m_mean: Vec<Option<f32>>,
+    uv_index_max: Vec<Option<f32>>,
+    wind_speed_10m_max: Vec<Option<f32>>,
+    wind_direction_10m_dominant: Vec<Option<f32>>,
+    wind_gusts_10m_max: Vec<Option<f32>>,
+    weather_code: Vec<Option<u32>>,
+    sunrise: Vec<Option<i64>>,
+    sunset: Vec<Option<i64>>,
+}
+
+/// The value at the given index of one of the hourly or daily fields, if it's there.
+fn at<T: Copy>(values: &[Option<T>], i: usize) -> Option<T> {
+    values.get(i).copied().flatten()
+}
+
+fn local_time(timestamp: i64) -> DateTime<Local> {
+    Local.timestamp(timestamp, 0)
+}
+
+/// Turns a WMO weather interpretation code into a condition code.
+fn condition(code: u32) -> u32 {
+    match code {
+        0 => condition::CLEAR,
+        1 => condition::FEW_CLOUDS,
+        2 => condition::SCATTERED_CLOUDS,
+        3 => condition::OVERCAST,
+        45 | 48 => condition::FOG,
+        51 | 53 | 55 => condition::DRIZZLE,
+        56 | 57 | 66 | 67 => condition::FREEZING_RAIN,
+        61 => condition::LIGHT_RAIN,
+        63 => condition::RAIN,
+        65 => condition::HEAVY_RAIN,
+        71 => condition::LIGHT_SNOW,
+        73 | 77 => condition::SNOW,
+        75 => condition::HEAVY_SNOW,
+        80..=82 => condition::RAIN_SHOWERS,
+        85 | 86 => condition::SNOW_SHOWERS,
+        95..=99 => condition::THUNDERSTORM,
+        _ => condition::OVERCAST,
+    }
+}
+
+impl OpenMeteoReport {
+    fn into_report(self, conditions: &Conditions) -> WeatherReport {
+        let utc_offset = self.utc_offset_seconds;
+        let current = self.current;
+        let code = condition(current.weather_code);
+        let current_weather = CurrentWeather {
+            temp: current.temperature_2m,
+            feels_like: current.apparent_temperature,
+            humidity: current.relative_humidity_2m,
+            pressure: current.pressure_msl,
+            dew_point: current.dew_point_2m,
+            visibility: current.visibility,
+            uv_index: current.uv_index,
+            cloudiness: current.cloud_cover,
+            wind_speed: current.wind_speed_10m,
+            wind_direction: current.wind_direction_10m,
+            wind_gust: current.wind_gusts_10m,
+            condition_code: code,
+            icon: condition::icon(code, current.is_day != 0),
+            description: conditions.describe(code),
+            sunrise: at(&self.daily.sunrise, 0).map(local_time),
+            sunset: at(&self.daily.sunset, 0).map(local_time),
+        };
+
+        let hourly = &self.hourly;
+        let hourly = (0..hourly.time.len())
+            .filter_map(|i| {
+                let code = condition(at(&hourly.weather_code, i)?);
+                Some(HourlyForecast {
+                    time: local_time(hourly.time[i]),
+                    temp: at(&hourly.temperature_2m, i)?,
+                    humidity: at(&hourly.relative_humidity_2m, i).unwrap_or_default(),
+                    rain_chance: at(&hourly.precipitation_probability, i).unwrap_or_default(),
+                    cloudiness: at(&hourly.cloud_cover, i)
+                        .unwrap_or_else(|| condition::cloudiness(code)),
+                    description: conditions.describe(code),
+                })
+            })
+            .collect();
+
+        let daily = &self.daily;
+        let forecast = (0..daily.time.len())
+            .filter_map(|i| {
+                let code = condition(at(&daily.weather_code, i)?);
+                let date = NaiveDateTime::from_timestamp(daily.time[i] + utc_offset, 0).date();
+                Some(Forecast {
+                    date,
+                    min_temp: at(&daily.temperature_2m_min, i)?,
+                    max_temp: at(&daily.temperature_2m_max, i)?,
+                    humidity: at(&daily.relative_humidity_2m_mean, i).unwrap_or_default(),
+                    rain_chance: at(&daily.precipitation_probability_max, i).unwrap_or_default(),
+                    cloudiness: at(&daily.cloud_cover_mean, i)
+                        .unwrap_or_else(|| condition::cloudiness(code)),
+                    description: conditions.describe(code),
+                    feels_like: at(&daily.apparent_temperature_max, i),
+                    pressure: at(&daily.pressure_msl_mean, i),
+                    dew_point: at(&daily.dew_point_2m_mean, i),
+                    uv_index: at(&daily.uv_index_max, i),
+                    wind_speed: at(&daily.wind_speed_10m_max, i).unwrap_or_default(),
+                    wind_direction: at(&daily.wind_direction_10m_dominant, i).unwrap_or_default(),
+                    wind_gust: at(&daily.wind_gusts_10m_max, i),
+                    condition_code: code,
+                    icon: condition::icon(code, true),
+                    sunrise: at(&daily.sunrise, i).map(local_time),
+                    sunset: at(&daily.sunset, i).map(local_time),
+                    moon_phase: moon_phase(date),
+                })
+            })
+            .collect();
+
+        WeatherReport {
+            current_weather,
+            forecast,
+            hourly,
+            minutely: vec![],
+            precipitation_starts_at: None,
+            alerts: vec![],
+            fetched_at: None,
+            provider: None,
+            stale: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        settings::Language,
+        weather::{TemperatureUnits, Units},
+    };
+    use chrono::NaiveDate;
+
+    fn report() -> WeatherReport {
+        let resp: OpenMeteoReport =
+            serde_json::from_str(include_str!("fixtures/open_meteo.json")).unwrap();
+        let conditions = Conditions::init(Language::English).expect("No conditions file found");
+        resp.into_report(&conditions)
+    }
+
+    #[test]
+    fn test_parse() {
+        let report = report();
+        let current = &report.current_weather;
+        assert_eq!(current.temp, 12.3);
+        assert_eq!(current.feels_like, 10.9);
+        assert_eq!(current.pressure, Some(1012.4));
+        assert_eq!(current.wind_gust, Some(8.1));
+        assert_eq!(current.condition_code, condition::RAIN);
+        assert_eq!(current.icon, "10d");
+        assert_eq!(current.description, "moderate rain");
+        assert_eq!(current.sunrise, Some(Local.timestamp(1_699_943_000, 0)));
+
+        // The hour with nothing in it gets skipped.
+        assert_eq!(report.hourly.len(), 2);
+        assert_eq!(report.hourly[1].time, Local.timestamp(1_700_003_600, 0));
+        assert_eq!(report.hourly[1].rain_chance, 65.0);
+        assert_eq!(report.hourly[1].description, "shower rain");
+
+        assert_eq!(report.forecast.len(), 2);
+        let tomorrow = &report.forecast[1];
+        assert_eq!(tomorrow.date, NaiveDate::from_ymd(2023, 11, 15));
+        assert_eq!(tomorrow.min_temp, 4.2);
+        assert_eq!(tomorrow.max_temp, 9.8);
+        assert_eq!(tomorrow.uv_index, Some(1.2));
+        assert_eq!(tomorrow.pressure, None);
+        assert_eq!(tomorrow.description, "light snow");
+        assert!(report.minutely.is_empty());
+    }
+
+    #[test]
+    fn test_convert_units() {
+        let report = report().in_units(&Units::from(&TemperatureUnits::Fahrenheit));
+        let current = &report.current_weather;
+        assert!((current.temp - 54.14).abs() < 0.01);
+        assert!((current.wind_speed - 10.74).abs() < 0.01);
+        assert!((current.pressure.unwrap() - 29.90).abs() < 0.01);
+        assert!((report.forecast[1].min_temp - 39.56).abs() < 0.01);
+
+        let report = self::report().in_units(&Units::from(&TemperatureUnits::Kelvin));
+        assert!((report.hourly[0].temp - 285.45).abs() < 0.01);
+        assert_eq!(report.current_weather.wind_speed, 4.8);
+    }
+}
@@ -1,20 +1,51 @@
-use crate::weather::{TemperatureUnits, WeatherReport};
-use chrono::{NaiveDate, NaiveDateTime};
+use crate::{
+    settings::Language,
+    weather::{get_json, Place, WeatherProvider, WeatherReport, WeatherRequest},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use reqwest::Url;
 use serde::{de, Deserialize, Deserializer};
+use std::collections::HashMap;
 
 /// A structure reprsenting the results from OpenWeather's OneCall API. We only currently
-/// support the current weather and the daily forecast; all other data from the OneCall
-/// API call is discarded.
+/// support the current weather, the minutely, hourly and daily forecasts and the alerts; all other
+/// data from the OneCall API call is discarded.
 #[derive(Deserialize)]
 pub struct OpenWeatherReport {
     pub current: OpenWeatherCurrent,
+    // OpenWeather leaves these out for places it doesn't have them for.
+    #[serde(default)]
+    pub minutely: Vec<OpenWeatherMinutely>,
+    #[serde(default)]
+    pub hourly: Vec<OpenWeatherHourly>,
     pub daily: Vec<OpenWeatherForecast>,
+    #[serde(default)]
+    pub alerts: Vec<OpenWeatherAlert>,
 }
 
 pub struct OpenWeatherCurrent {
     pub temp: f32,
+    pub feels_like: f32,
     pub humidity: f32,
+    // In hPa.
+    pub pressure: f32,
+    pub dew_point: f32,
+    // In metres.
+    pub visibility: Option<f32>,
+    pub uv_index: f32,
+    pub cloudiness: f32,
+    pub wind_speed: f32,
+    // The direction the wind is coming from, in degrees clockwise from north.
+    pub wind_direction: f32,
+    pub wind_gust: Option<f32>,
+    // https://openweathermap.org/weather-conditions
+    pub condition_code: u32,
+    pub icon: String,
     pub description: String,
+    // The sun doesn't rise or set on some days near the poles.
+    pub sunrise: Option<DateTime<Local>>,
+    pub sunset: Option<DateTime<Local>>,
 }
 
 // Due to the heavily nested nature of the JSON (and the fact that we're really only interested in
@@ -28,24 +59,142 @@ impl<'de> Deserialize<'de> for OpenWeatherCurrent {
         #[derive(Deserialize)]
         struct TopLevel {
             temp: f32,
+            feels_like: f32,
             humidity: f32,
+            pressure: f32,
+            dew_point: f32,
+            visibility: Option<f32>,
+            uvi: f32,
+            clouds: f32,
+            wind_speed: f32,
+            wind_deg: f32,
+            wind_gust: Option<f32>,
             weather: Vec<Weather>,
+            #[serde(default, deserialize_with = "deserialize_optional_timestamp")]
+            sunrise: Option<DateTime<Local>>,
+            #[serde(default, deserialize_with = "deserialize_optional_timestamp")]
+            sunset: Option<DateTime<Local>>,
         }
 
         #[derive(Deserialize)]
         struct Weather {
+            id: u32,
             description: String,
+            icon: String,
         }
 
         let helper = TopLevel::deserialize(deserializer)?;
+        let weather = match helper.weather.into_iter().next() {
+            Some(weather) => weather,
+            None => return Err(de::Error::custom("missing field weather")),
+        };
         Ok(Self {
             temp: helper.temp,
+            feels_like: helper.feels_like,
             humidity: helper.humidity,
-            description: helper.weather[0].description.clone(),
+            pressure: helper.pressure,
+            dew_point: helper.dew_point,
+            visibility: helper.visibility,
+            uv_index: helper.uvi,
+            cloudiness: helper.clouds,
+            wind_speed: helper.wind_speed,
+            wind_direction: helper.wind_deg,
+            wind_gust: helper.wind_gust,
+            condition_code: weather.id,
+            icon: weather.icon,
+            description: weather.description,
+            sunrise: helper.sunrise,
+            sunset: helper.sunset,
         })
     }
 }
 
+#[derive(Deserialize)]
+pub struct OpenWeatherMinutely {
+    #[serde(rename = "dt", deserialize_with = "deserialize_timestamp")]
+    pub time: DateTime<Local>,
+    // In mm/h.
+    pub precipitation: f32,
+}
+
+pub struct OpenWeatherHourly {
+    pub time: DateTime<Local>,
+    pub temp: f32,
+    pub humidity: f32,
+    pub rain_chance: f32,
+    pub cloudiness: f32,
+    pub description: String,
+}
+
+// See OpenWeatherForecast for why this is deserialized by hand.
+impl<'de> Deserialize<'de> for OpenWeatherHourly {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct TopLevel {
+            #[serde(rename = "dt", deserialize_with = "deserialize_timestamp")]
+            time: DateTime<Local>,
+            temp: f32,
+            humidity: f32,
+            weather: Vec<Weather>,
+            clouds: f32,
+            #[serde(rename = "pop")]
+            rain_chance: f32,
+        }
+
+        #[derive(Deserialize)]
+        struct Weather {
+            description: String,
+        }
+
+        let helper = TopLevel::deserialize(deserializer)?;
+        let description = match helper.weather.get(0) {
+            Some(weather) => weather.description.clone(),
+            None => return Err(de::Error::custom("missing field weather")),
+        };
+        Ok(Self {
+            time: helper.time,
+            temp: helper.temp,
+            humidity: helper.humidity,
+            rain_chance: helper.rain_chance,
+            cloudiness: helper.clouds,
+            description,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct OpenWeatherAlert {
+    pub sender_name: String,
+    pub event: String,
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    pub start: DateTime<Local>,
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    pub end: DateTime<Local>,
+    pub description: String,
+}
+
+/// OpenWeather gives times as Unix timestamps.
+fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<DateTime<Local>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let timestamp = i64::deserialize(deserializer)?;
+    Ok(Local.timestamp(timestamp, 0))
+}
+
+fn deserialize_optional_timestamp<'de, D>(
+    deserializer: D,
+) -> Result<Option<DateTime<Local>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let timestamp = Option::<i64>::deserialize(deserializer)?;
+    Ok(timestamp.map(|timestamp| Local.timestamp(timestamp, 0)))
+}
+
 pub struct OpenWeatherForecast {
     pub date: NaiveDate,
     pub min_temp: f32,
@@ -54,6 +203,21 @@ pub struct OpenWeatherForecast {
     pub rain_chance: f32,
     pub cloudiness: f32,
     pub description: String,
+    // How warm it feels during the day.
+    pub feels_like: f32,
+    // In hPa.
+    pub pressure: f32,
+    pub dew_point: f32,
+    pub uv_index: f32,
+    pub wind_speed: f32,
+    pub wind_direction: f32,
+    pub wind_gust: Option<f32>,
+    pub condition_code: u32,
+    pub icon: String,
+    pub sunrise: Option<DateTime<Local>>,
+    pub sunset: Option<DateTime<Local>>,
+    // 0 and 1 are new moons, 0.5 is a full moon.
+    pub moon_phase: f32,
 }
 
 // Due to the heavily nested nature of the JSON (and the fact that we're really only interested in
@@ -77,6 +241,18 @@ impl<'de> Deserialize<'de> for OpenWeatherForecast {
             clouds: f32,
             #[serde(rename = "pop")]
             rain_chance: f32,
+            feels_like: FeelsLike,
+            pressure: f32,
+            dew_point: f32,
+            uvi: f32,
+            wind_speed: f32,
+            wind_deg: f32,
+            wind_gust: Option<f32>,
+            #[serde(default, deserialize_with = "deserialize_optional_timestamp")]
+            sunrise: Option<DateTime<Local>>,
+            #[serde(default, deserialize_with = "deserialize_optional_timestamp")]
+            sunset: Option<DateTime<Local>>,
+            moon_phase: f32,
         }
 
         #[derive(Deserialize)]
@@ -85,9 +261,16 @@ impl<'de> Deserialize<'de> for OpenWeatherForecast {
             max: f32,
         }
 
+        #[derive(Deserialize)]
+        struct FeelsLike {
+            day: f32,
+        }
+
         #[derive(Deserialize)]
         struct Weather {
+            id: u32,
             description: String,
+            icon: String,
         }
 
         let helper = TopLevel::deserialize(deserializer)?;
@@ -96,8 +279,8 @@ impl<'de> Deserialize<'de> for OpenWeatherForecast {
         // As described earlier, we're only using the first value. This is just a sanity check
         // since there is a possibility that this array we're getting out of the JSON could be
         // empty but I'm not sure if this is _actually_ possible in practice.
-        let description = match helper.weather.get(0) {
-            Some(weather) => weather.description.clone(),
+        let weather = match helper.weather.into_iter().next() {
+            Some(weather) => weather,
             None => return Err(de::Error::custom("missing field weather")),
         };
         Ok(Self {
@@ -107,27 +290,158 @@ impl<'de> Deserialize<'de> for OpenWeatherForecast {
             humidity: helper.humidity,
             cloudiness: helper.clouds,
             rain_chance: helper.rain_chance,
-            description,
+            description: weather.description,
+            feels_like: helper.feels_like.day,
+            pressure: helper.pressure,
+            dew_point: helper.dew_point,
+            uv_index: helper.uvi,
+            wind_speed: helper.wind_speed,
+            wind_direction: helper.wind_deg,
+            wind_gust: helper.wind_gust,
+            condition_code: weather.id,
+            icon: weather.icon,
+            sunrise: helper.sunrise,
+            sunset: helper.sunset,
+            moon_phase: helper.moon_phase,
         })
     }
 }
 
-/// Query weather from OpenWeather's OneCall API.
-pub async fn get_weather(
+/// OpenWeather's OneCall API.
+pub struct OpenWeather {
+    pub api_key: String,
+}
+
+#[async_trait]
+impl WeatherProvider for OpenWeather {
+    fn name(&self) -> &'static str {
+        "OpenWeather"
+    }
+
+    /// Query weather from OpenWeather's OneCall API in metric units. Descriptions come back in the
+    /// given language.
+    async fn get_weather(
+        &self,
+        request: &WeatherRequest,
+    ) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
+        let uri = format!(
+            "http://api.openweathermap.org/data/2.5/onecall?lat={}&lon={}&appid={}&units=metric&lang={}",
+            request.lat,
+            request.lon,
+            self.api_key,
+            request.language.code(),
+        );
+        let resp: OpenWeatherReport = get_json(&uri, None).await?;
+        Ok(resp.into())
+    }
+
+    /// Looks places up through OpenWeather's geocoding API, going by their names in the given
+    /// language where it has them.
+    async fn geocode(
+        &self,
+        query: &str,
+        language: Language,
+    ) -> Result<Vec<Place>, Box<dyn std::error::Error + Send + Sync>> {
+        let uri = Url::parse_with_params(
+            "http://api.openweathermap.org/geo/1.0/direct",
+            &[("q", query), ("limit", "5"), ("appid", &self.api_key)],
+        )?;
+        let places: Vec<OpenWeatherPlace> = get_json(uri.as_str(), None).await?;
+        Ok(places
+            .into_iter()
+            .map(|place| Place {
+                name: place
+                    .local_names
+                    .get(language.code())
+                    .cloned()
+                    .unwrap_or(place.name),
+                country: place.country,
+                region: place.state,
+                lat: place.lat,
+                lon: place.lon,
+            })
+            .collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenWeatherPlace {
+    name: String,
+    // The place's name in other languages, by language code.
+    #[serde(default)]
+    local_names: HashMap<String, String>,
     lat: f32,
     lon: f32,
-    temp_units: TemperatureUnits,
-    api_key: String,
-) -> Result<WeatherReport, Box<dyn std::error::Error + Send + Sync>> {
-    let units = match temp_units {
-        TemperatureUnits::Kelvin => "standard",
-        TemperatureUnits::Celsius => "metric",
-        TemperatureUnits::Fahrenheit => "imperial",
-    };
-    let uri = format!(
-        "http://api.openweathermap.org/data/2.5/onecall?lat={}&lon={}&appid={}&units={}",
-        lat, lon, api_key, units,
-    );
-    let resp: OpenWeatherReport = reqwest::get(uri).await?.json().await?;
-    Ok(resp.into())
+    country: String,
+    state: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::{openweather_fixture, TemperatureUnits, Units};
+
+    #[test]
+    fn test_parse_onecall() {
+        let report = openweather_fixture();
+        let start = Local.timestamp(1_700_000_000, 0);
+
+        let current = &report.current_weather;
+        assert_eq!(current.feels_like, 11.4);
+        assert_eq!(current.wind_speed, 4.12);
+        assert_eq!(current.wind_direction, 250.0);
+        assert_eq!(current.wind_gust, Some(7.2));
+        assert_eq!(current.pressure, Some(1012.0));
+        assert_eq!(current.visibility, Some(10_000.0));
+        assert_eq!(current.condition_code, 803);
+        assert_eq!(current.icon, "04d");
+        assert_eq!(current.sunrise, Some(Local.timestamp(1_699_943_000, 0)));
+        assert_eq!(report.forecast[0].feels_like, Some(12.0));
+        assert_eq!(report.forecast[0].moon_phase, 0.08);
+        let imperial = report.in_units(&Units::from(&TemperatureUnits::Fahrenheit));
+        assert!((imperial.current_weather.pressure.unwrap() - 29.88).abs() < 0.01);
+        assert!((imperial.current_weather.visibility.unwrap() - 6.21).abs() < 0.01);
+
+        assert_eq!(report.hourly.len(), 3);
+        assert_eq!(report.hourly[1].time, Local.timestamp(1_700_003_600, 0));
+        assert_eq!(report.hourly[1].temp, 11.5);
+        assert_eq!(report.hourly[1].rain_chance, 40.0);
+        assert_eq!(report.hourly[1].description, "light rain");
+
+        assert_eq!(report.minutely.len(), 5);
+        assert_eq!(report.minutely[3].precipitation, 0.6);
+        // It's dry for the first two minutes.
+        assert_eq!(
+            report.precipitation_starts_at,
+            Some(start + chrono::Duration::minutes(2))
+        );
+
+        assert_eq!(report.alerts.len(), 1);
+        assert_eq!(report.alerts[0].sender, "Deutscher Wetterdienst");
+        assert_eq!(report.alerts[0].event, "wind gusts");
+        assert_eq!(report.alerts[0].start, start);
+        assert_eq!(report.alerts[0].end, Local.timestamp(1_700_036_000, 0));
+    }
+
+    #[test]
+    fn test_parse_onecall_without_minutely() {
+        // Like somewhere far enough north in winter that the sun doesn't rise, and where
+        // OpenWeather doesn't have a minutely forecast.
+        let mut json: serde_json::Value =
+            serde_json::from_str(include_str!("fixtures/openweather_onecall.json")).unwrap();
+        for field in &["minutely", "hourly", "alerts"] {
+            json.as_object_mut().unwrap().remove(*field);
+        }
+        for field in &["sunrise", "sunset", "visibility", "wind_gust"] {
+            json["current"].as_object_mut().unwrap().remove(*field);
+        }
+        let report: OpenWeatherReport = serde_json::from_value(json).unwrap();
+        assert_eq!(report.current.sunrise, None);
+        assert_eq!(report.current.wind_gust, None);
+        let report: WeatherReport = report.into();
+        assert!(report.minutely.is_empty());
+        assert!(report.hourly.is_empty());
+        assert_eq!(report.precipitation_starts_at, None);
+        assert!(report.alerts.is_empty());
+    }
 }
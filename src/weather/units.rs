@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+
+const MPS_PER_KPH: f32 = 1.0 / 3.6;
+const MPS_PER_MPH: f32 = 0.447_04;
+const MPS_PER_KNOT: f32 = 0.514_444;
+const HPA_PER_INHG: f32 = 33.863_89;
+const MM_PER_INCH: f32 = 25.4;
+const METRES_PER_MILE: f32 = 1_609.344;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TemperatureUnits {
+    Kelvin,
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnits {
+    /// Converts a temperature in Celsius to these units.
+    pub fn convert_celsius(&self, temp: f32) -> f32 {
+        match self {
+            TemperatureUnits::Kelvin => temp + 273.15,
+            TemperatureUnits::Celsius => temp,
+            TemperatureUnits::Fahrenheit => temp * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    /// Converts a temperature in these units to Celsius.
+    pub fn to_celsius(&self, temp: f32) -> f32 {
+        match self {
+            TemperatureUnits::Kelvin => temp - 273.15,
+            TemperatureUnits::Celsius => temp,
+            TemperatureUnits::Fahrenheit => (temp - 32.0) * 5.0 / 9.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum WindUnits {
+    MetresPerSecond,
+    KilometresPerHour,
+    MilesPerHour,
+    Knots,
+}
+
+impl WindUnits {
+    /// How many m/s one of these is.
+    fn in_mps(&self) -> f32 {
+        match self {
+            WindUnits::MetresPerSecond => 1.0,
+            WindUnits::KilometresPerHour => MPS_PER_KPH,
+            WindUnits::MilesPerHour => MPS_PER_MPH,
+            WindUnits::Knots => MPS_PER_KNOT,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PressureUnits {
+    Hectopascals,
+    InchesOfMercury,
+}
+
+impl PressureUnits {
+    /// How many hPa one of these is.
+    fn in_hpa(&self) -> f32 {
+        match self {
+            PressureUnits::Hectopascals => 1.0,
+            PressureUnits::InchesOfMercury => HPA_PER_INHG,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PrecipitationUnits {
+    Millimetres,
+    Inches,
+}
+
+impl PrecipitationUnits {
+    /// How many mm one of these is.
+    fn in_mm(&self) -> f32 {
+        match self {
+            PrecipitationUnits::Millimetres => 1.0,
+            PrecipitationUnits::Inches => MM_PER_INCH,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DistanceUnits {
+    Metres,
+    Kilometres,
+    Miles,
+}
+
+impl DistanceUnits {
+    /// How many metres one of these is.
+    fn in_metres(&self) -> f32 {
+        match self {
+            DistanceUnits::Metres => 1.0,
+            DistanceUnits::Kilometres => 1_000.0,
+            DistanceUnits::Miles => METRES_PER_MILE,
+        }
+    }
+}
+
+/// The units the weather is shown in. Weather is always fetched and kept in Celsius, m/s, hPa, mm
+/// and metres, and only converted to these when it's sent out, so changing them doesn't mean
+/// asking for the weather again.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Units {
+    pub temperature: TemperatureUnits,
+    pub wind: WindUnits,
+    pub pressure: PressureUnits,
+    // For how heavy precipitation is, per hour.
+    pub precipitation: PrecipitationUnits,
+    // For visibility.
+    pub distance: DistanceUnits,
+}
+
+impl Default for Units {
+    /// The units weather is kept in.
+    fn default() -> Self {
+        Self {
+            temperature: TemperatureUnits::Celsius,
+            wind: WindUnits::MetresPerSecond,
+            pressure: PressureUnits::Hectopascals,
+            precipitation: PrecipitationUnits::Millimetres,
+            distance: DistanceUnits::Metres,
+        }
+    }
+}
+
+impl From<&TemperatureUnits> for Units {
+    /// The units that go with a temperature unit, for settings from before units could be picked
+    /// one by one. Fahrenheit has always gone with mph and inHg.
+    fn from(temperature: &TemperatureUnits) -> Self {
+        match temperature {
+            TemperatureUnits::Fahrenheit => Self {
+                temperature: TemperatureUnits::Fahrenheit,
+                wind: WindUnits::MilesPerHour,
+                pressure: PressureUnits::InchesOfMercury,
+                precipitation: PrecipitationUnits::Inches,
+                distance: DistanceUnits::Miles,
+            },
+            temperature => Self {
+                temperature: temperature.clone(),
+                ..Self::default()
+            },
+        }
+    }
+}
+
+type Conversion<'a> = Box<dyn Fn(f32) -> f32 + 'a>;
+
+/// Converts each kind of measurement in a weather report one way or the other.
+pub struct Converter<'a> {
+    pub temp: Conversion<'a>,
+    pub speed: Conversion<'a>,
+    pub pressure: Conversion<'a>,
+    pub precipitation: Conversion<'a>,
+    pub distance: Conversion<'a>,
+}
+
+impl Units {
+    /// Converts from the units weather is kept in to these units.
+    pub fn convert_from_metric(&self) -> Converter {
+        Converter {
+            temp: Box::new(move |temp| self.temperature.convert_celsius(temp)),
+            speed: Box::new(move |speed| speed / self.wind.in_mps()),
+            pressure: Box::new(move |pressure| pressure / self.pressure.in_hpa()),
+            precipitation: Box::new(move |precipitation| {
+                precipitation / self.precipitation.in_mm()
+            }),
+            distance: Box::new(move |distance| distance / self.distance.in_metres()),
+        }
+    }
+
+    /// Converts from these units back to the units weather is kept in.
+    pub fn convert_to_metric(&self) -> Converter {
+        Converter {
+            temp: Box::new(move |temp| self.temperature.to_celsius(temp)),
+            speed: Box::new(move |speed| speed * self.wind.in_mps()),
+            pressure: Box::new(move |pressure| pressure * self.pressure.in_hpa()),
+            precipitation: Box::new(move |precipitation| {
+                precipitation * self.precipitation.in_mm()
+            }),
+            distance: Box::new(move |distance| distance * self.distance.in_metres()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn units(
+        temperature: TemperatureUnits,
+        wind: WindUnits,
+        pressure: PressureUnits,
+        precipitation: PrecipitationUnits,
+        distance: DistanceUnits,
+    ) -> Units {
+        Units {
+            temperature,
+            wind,
+            pressure,
+            precipitation,
+            distance,
+        }
+    }
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!(
+            (value - expected).abs() < 0.01,
+            "expected {}, got {}",
+            expected,
+            value
+        );
+    }
+
+    #[test]
+    fn test_temperature() {
+        let kelvin = TemperatureUnits::Kelvin;
+        let fahrenheit = TemperatureUnits::Fahrenheit;
+        assert_close(kelvin.convert_celsius(0.0), 273.15);
+        assert_close(fahrenheit.convert_celsius(0.0), 32.0);
+        assert_close(fahrenheit.convert_celsius(100.0), 212.0);
+        assert_close(fahrenheit.convert_celsius(-40.0), -40.0);
+        assert_close(kelvin.to_celsius(300.0), 26.85);
+        assert_close(fahrenheit.to_celsius(98.6), 37.0);
+    }
+
+    #[test]
+    fn test_convert_from_metric() {
+        let imperial = Units::from(&TemperatureUnits::Fahrenheit);
+        let convert = imperial.convert_from_metric();
+        assert_close((convert.speed)(10.0), 22.37);
+        assert_close((convert.pressure)(1013.25), 29.92);
+        assert_close((convert.precipitation)(25.4), 1.0);
+        assert_close((convert.distance)(1609.344), 1.0);
+
+        let mixed = units(
+            TemperatureUnits::Kelvin,
+            WindUnits::Knots,
+            PressureUnits::Hectopascals,
+            PrecipitationUnits::Millimetres,
+            DistanceUnits::Kilometres,
+        );
+        let convert = mixed.convert_from_metric();
+        assert_close((convert.temp)(20.0), 293.15);
+        assert_close((convert.speed)(10.0), 19.44);
+        assert_close((convert.pressure)(1013.25), 1013.25);
+        assert_close((convert.precipitation)(2.5), 2.5);
+        assert_close((convert.distance)(10_000.0), 10.0);
+
+        let kilometres = units(
+            TemperatureUnits::Celsius,
+            WindUnits::KilometresPerHour,
+            PressureUnits::Hectopascals,
+            PrecipitationUnits::Millimetres,
+            DistanceUnits::Metres,
+        );
+        let convert = kilometres.convert_from_metric();
+        assert_close((convert.speed)(10.0), 36.0);
+        assert_close((convert.distance)(800.0), 800.0);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let all = [
+            units(
+                TemperatureUnits::Kelvin,
+                WindUnits::MetresPerSecond,
+                PressureUnits::Hectopascals,
+                PrecipitationUnits::Millimetres,
+                DistanceUnits::Metres,
+            ),
+            units(
+                TemperatureUnits::Celsius,
+                WindUnits::KilometresPerHour,
+                PressureUnits::InchesOfMercury,
+                PrecipitationUnits::Inches,
+                DistanceUnits::Kilometres,
+            ),
+            units(
+                TemperatureUnits::Fahrenheit,
+                WindUnits::MilesPerHour,
+                PressureUnits::Hectopascals,
+                PrecipitationUnits::Millimetres,
+                DistanceUnits::Miles,
+            ),
+            units(
+                TemperatureUnits::Celsius,
+                WindUnits::Knots,
+                PressureUnits::InchesOfMercury,
+                PrecipitationUnits::Inches,
+                DistanceUnits::Metres,
+            ),
+        ];
+        for units in &all {
+            let (from, to) = (units.convert_from_metric(), units.convert_to_metric());
+            assert_close((to.temp)((from.temp)(-12.3)), -12.3);
+            assert_close((to.speed)((from.speed)(4.12)), 4.12);
+            assert_close((to.pressure)((from.pressure)(1012.0)), 1012.0);
+            assert_close((to.precipitation)((from.precipitation)(0.9)), 0.9);
+            assert_close((to.distance)((from.distance)(10_000.0)), 10_000.0);
+        }
+    }
+}